@@ -0,0 +1,676 @@
+use std::{
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TryRecvError, TrySendError},
+        Arc, Condvar, Mutex, Weak,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use tracing::warn;
+use tracing_subscriber::fmt::MakeWriter;
+
+use super::{
+    config::{BufferConfig, BufferMode},
+    rotation::SyncedFile,
+    write_error::PanicCatcher,
+};
+
+/// Interval between two reports of the dropped lines
+pub const DROPPED_LINES_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+/// Delay after which a worker guard stops waiting for its worker to write the
+/// pending lines
+const WORKER_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+/// Interval between two attempts of a worker guard to send the shutdown
+/// request while the buffer is full
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Message sent to the worker of a non-blocking writer
+#[derive(Debug)]
+enum Message {
+    /// Formatted event, lines included
+    Line(Vec<u8>),
+    /// Request to flush the writer once the previous lines are written,
+    /// acknowledged through the sender
+    Flush(Sender<()>),
+    /// Request to write the pending lines and stop, sent by the worker guard
+    Shutdown,
+}
+
+/// Non-blocking writer, along with its worker
+pub struct BufferedWriter {
+    pub writer: BufferSender,
+    /// Stop the worker when dropped, after it writes the pending lines
+    pub worker_guard: WorkerGuard,
+    /// Number of lines written by the worker but not sent yet, for writers
+    /// queueing them such as the TCP one
+    pub queued_lines: Option<Arc<AtomicUsize>>,
+    /// File written by the worker, for file appenders
+    pub synced_file: Option<SyncedFile>,
+}
+
+/// Stop the worker of a non-blocking writer when dropped, once it writes the
+/// pending lines and closes its writer, or after a timeout
+#[derive(Debug)]
+#[must_use]
+pub struct WorkerGuard {
+    sender: SyncSender<Message>,
+    /// Disconnected once the worker stops
+    stopped: Receiver<()>,
+}
+
+impl Drop for WorkerGuard {
+    fn drop(&mut self) {
+        let deadline = Instant::now() + WORKER_SHUTDOWN_TIMEOUT;
+        // The buffer may be full, even with a lossy writer
+        while let Err(TrySendError::Full(_)) = self.sender.try_send(Message::Shutdown) {
+            if Instant::now() >= deadline {
+                break;
+            }
+            thread::sleep(SHUTDOWN_POLL_INTERVAL);
+        }
+        let _ = self
+            .stopped
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()));
+    }
+}
+
+/// Lines sent to the worker of a blocking writer and not received yet, so that
+/// the lines waiting too long for room in its buffer can be dropped
+#[derive(Clone)]
+pub struct BlockingGate(Arc<GateState>);
+
+struct GateState {
+    slots: Mutex<GateSlots>,
+    received: Condvar,
+    capacity: usize,
+    timeout: Duration,
+    dropped: AtomicUsize,
+}
+
+struct GateSlots {
+    pending: usize,
+    /// Whether the worker stopped, so that no room will be given back
+    closed: bool,
+}
+
+impl BlockingGate {
+    fn new(capacity: usize, timeout: Duration) -> Self {
+        Self(Arc::new(GateState {
+            slots: Mutex::new(GateSlots {
+                pending: 0,
+                closed: false,
+            }),
+            received: Condvar::new(),
+            capacity,
+            timeout,
+            dropped: AtomicUsize::new(0),
+        }))
+    }
+
+    /// Wait for room in the buffer, returning false and counting the line as
+    /// dropped if the buffer is still full after the timeout
+    ///
+    /// Once the worker stopped, the room is granted at once so that sending
+    /// the line fails rather than waiting for the timeout.
+    fn acquire(&self) -> bool {
+        let state = &*self.0;
+        let deadline = Instant::now() + state.timeout;
+        let mut slots = state.slots.lock().unwrap_or_else(|e| e.into_inner());
+        while slots.pending >= state.capacity && !slots.closed {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout.is_zero() {
+                state.dropped.fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+            slots = state
+                .received
+                .wait_timeout(slots, timeout)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        slots.pending += 1;
+        true
+    }
+
+    /// Give back the room of a line, once received by the worker or not sent
+    fn release(&self) {
+        let state = &*self.0;
+        let mut slots = state.slots.lock().unwrap_or_else(|e| e.into_inner());
+        slots.pending = slots.pending.saturating_sub(1);
+        state.received.notify_one();
+    }
+
+    /// Release the writers waiting for room, once the worker stopped
+    fn close(&self) {
+        let state = &*self.0;
+        state.slots.lock().unwrap_or_else(|e| e.into_inner()).closed = true;
+        state.received.notify_all();
+    }
+
+    fn dropped_lines(&self) -> usize {
+        self.0.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Close the gate of a blocking writer when its worker stops, even by
+/// panicking, since the lines left in its buffer are never received
+struct GateCloser(Option<BlockingGate>);
+
+impl Drop for GateCloser {
+    fn drop(&mut self) {
+        if let Some(gate) = &self.0 {
+            gate.close();
+        }
+    }
+}
+
+/// Sending side of a non-blocking writer
+///
+/// Lossy writers drop the lines sent while their buffer is full. With a
+/// blocking timeout, the lines waiting too long for room in the buffer are
+/// dropped as well, and counted along with those dropped by lossy writers.
+#[derive(Clone)]
+pub struct BufferSender {
+    sender: SyncSender<Message>,
+    lossy: bool,
+    /// Number of lines dropped by a lossy writer
+    dropped: Arc<AtomicUsize>,
+    gate: Option<BlockingGate>,
+}
+
+impl BufferSender {
+    /// Number of lines dropped so far because the buffer was full
+    pub fn dropped_lines(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+            + self.gate.as_ref().map_or(0, BlockingGate::dropped_lines)
+    }
+
+    /// Whether both senders send their lines to the same worker
+    #[cfg(test)]
+    pub(crate) fn same_worker(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.dropped, &other.dropped)
+    }
+
+    /// Request the worker to flush its writer, waiting until it writes the
+    /// pending lines or until the deadline
+    ///
+    /// The request is dropped by lossy writers whose buffer is full.
+    pub fn flush_worker(&self, deadline: Instant) -> bool {
+        let (flushed, flush_done) = mpsc::channel();
+        let sent = if self.lossy {
+            self.sender.try_send(Message::Flush(flushed)).is_ok()
+        } else {
+            self.sender.send(Message::Flush(flushed)).is_ok()
+        };
+        sent && flush_done
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .is_ok()
+    }
+}
+
+impl Write for BufferSender {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let line = Message::Line(buf.to_vec());
+        if self.lossy {
+            if let Err(TrySendError::Full(_)) = self.sender.try_send(line) {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            return Ok(buf.len());
+        }
+
+        if let Some(gate) = &self.gate {
+            if !gate.acquire() {
+                return Ok(buf.len());
+            }
+        }
+        match self.sender.send(line) {
+            Ok(()) => Ok(buf.len()),
+            Err(_) => {
+                if let Some(gate) = &self.gate {
+                    gate.release();
+                }
+                Err(io::Error::from(io::ErrorKind::BrokenPipe))
+            }
+        }
+    }
+
+    /// Lines are written by the worker, see [`BufferSender::flush_worker`]
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Write the lines received by a worker until its guard stops it
+///
+/// The writer is flushed on request, and whenever no line is pending unless
+/// it writes the lines in batches.
+fn work<W: Write>(
+    mut writer: W,
+    receiver: Receiver<Message>,
+    gate: Option<BlockingGate>,
+    flush_when_idle: bool,
+) {
+    let _gate_closer = GateCloser(gate.clone());
+    loop {
+        let message = match receiver.try_recv() {
+            Ok(message) => message,
+            Err(TryRecvError::Empty) => {
+                if flush_when_idle {
+                    let _ = writer.flush();
+                }
+                match receiver.recv() {
+                    Ok(message) => message,
+                    Err(_) => break,
+                }
+            }
+            Err(TryRecvError::Disconnected) => break,
+        };
+
+        // Write errors cannot be logged by the worker itself
+        match message {
+            Message::Line(line) => {
+                if let Some(gate) = &gate {
+                    gate.release();
+                }
+                let _ = writer.write_all(&line);
+            }
+            Message::Flush(flushed) => {
+                let _ = writer.flush();
+                let _ = flushed.send(());
+            }
+            Message::Shutdown => break,
+        }
+    }
+    let _ = writer.flush();
+}
+
+/// Number of lines and delay after which a batch of lines is written
+#[derive(Debug, Clone, Copy)]
+pub struct BatchConfig {
+    pub lines: usize,
+    pub interval: Duration,
+}
+
+/// Lines not written yet by a batching writer, along with the writer itself
+struct Batch<W> {
+    /// Writer of the batches, taken when the batching writer is dropped
+    inner: Option<W>,
+    pending: Vec<u8>,
+    pending_lines: usize,
+}
+
+impl<W: Write> Batch<W> {
+    fn write_pending(&mut self) -> io::Result<()> {
+        let Some(inner) = &mut self.inner else {
+            return Ok(());
+        };
+        let pending = std::mem::take(&mut self.pending);
+        self.pending_lines = 0;
+        if !pending.is_empty() {
+            inner.write_all(&pending)?;
+            inner.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Writer run by a worker, gathering lines so that they are written once
+/// enough of them are pending, or periodically by a timer thread
+///
+/// Flush requests write the pending lines, and the last lines are written
+/// when the worker stops and drops the writer.
+struct BatchWriter<W: Write> {
+    batch: Arc<Mutex<Batch<W>>>,
+    lines: usize,
+    /// Stop the timer thread when dropped
+    _stop: Sender<()>,
+}
+
+impl<W: Write + Send + 'static> BatchWriter<W> {
+    fn new(inner: W, config: BatchConfig) -> Self {
+        let batch = Arc::new(Mutex::new(Batch {
+            inner: Some(inner),
+            pending: Vec::new(),
+            pending_lines: 0,
+        }));
+        let (stop, stopped) = mpsc::channel();
+
+        // The timer only references the batch, which is closed by the writer
+        let timer_batch: Weak<Mutex<Batch<W>>> = Arc::downgrade(&batch);
+        thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(config.interval) {
+                let Some(batch) = timer_batch.upgrade() else {
+                    break;
+                };
+                // As with the worker, write errors cannot be logged by the writer itself
+                let _ = batch
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .write_pending();
+            }
+        });
+
+        Self {
+            batch,
+            lines: config.lines,
+            _stop: stop,
+        }
+    }
+}
+
+impl<W: Write> Write for BatchWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut batch = self.batch.lock().unwrap_or_else(|e| e.into_inner());
+        batch.pending.extend_from_slice(buf);
+        batch.pending_lines += 1;
+        if batch.pending_lines >= self.lines {
+            batch.write_pending()?;
+        }
+        Ok(buf.len())
+    }
+
+    /// Only called on request, since the worker leaves the pending lines to
+    /// the timer when it has no more line to write
+    fn flush(&mut self) -> io::Result<()> {
+        let mut batch = self.batch.lock().unwrap_or_else(|e| e.into_inner());
+        batch.write_pending()?;
+        match &mut batch.inner {
+            Some(inner) => inner.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Drop for BatchWriter<W> {
+    fn drop(&mut self) {
+        let mut batch = self.batch.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = batch.write_pending();
+        // Close the writer now rather than when the timer thread stops
+        drop(batch.inner.take());
+    }
+}
+
+/// Create a non-blocking writer buffering lines as configured
+pub fn non_blocking<W>(writer: W, config: &BufferConfig) -> BufferedWriter
+where
+    W: Write + Send + 'static,
+{
+    non_blocking_batched(writer, config, None)
+}
+
+/// Create a non-blocking writer buffering lines as configured, whose worker
+/// writes them in batches if required
+pub fn non_blocking_batched<W>(
+    writer: W,
+    config: &BufferConfig,
+    batch_config: Option<BatchConfig>,
+) -> BufferedWriter
+where
+    W: Write + Send + 'static,
+{
+    let (sender, receiver) = mpsc::sync_channel(config.capacity);
+    let (stopped_sender, stopped) = mpsc::channel::<()>();
+    let gate = config
+        .blocking_timeout()
+        .map(|timeout| BlockingGate::new(config.capacity, timeout));
+
+    let writer = PanicCatcher::new(writer);
+    let worker_gate = gate.clone();
+    thread::spawn(move || {
+        // Dropped once the worker stops, releasing the worker guard
+        let _stopped_sender = stopped_sender;
+        match batch_config {
+            Some(batch_config) => work(
+                BatchWriter::new(writer, batch_config),
+                receiver,
+                worker_gate,
+                false,
+            ),
+            None => work(writer, receiver, worker_gate, true),
+        }
+    });
+
+    BufferedWriter {
+        writer: BufferSender {
+            sender: sender.clone(),
+            lossy: config.mode == BufferMode::Lossy,
+            dropped: Arc::new(AtomicUsize::new(0)),
+            gate,
+        },
+        worker_guard: WorkerGuard { sender, stopped },
+        queued_lines: None,
+        synced_file: None,
+    }
+}
+
+/// Non-blocking writer starting each line of the formatted events with a prefix, if any
+///
+/// Each event is sent to the worker as a single message, lines included, so
+/// that the lines of an event stay together.
+#[derive(Clone)]
+pub struct PrefixedWriter {
+    writer: BufferSender,
+    prefix: Option<Arc<str>>,
+}
+
+impl PrefixedWriter {
+    pub fn new(writer: BufferSender, prefix: Option<Arc<str>>) -> Self {
+        Self { writer, prefix }
+    }
+}
+
+impl Write for PrefixedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Suppressed repeats are formatted as nothing
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let Some(prefix) = &self.prefix else {
+            return self.writer.write(buf);
+        };
+
+        let mut prefixed = Vec::with_capacity(buf.len() + prefix.len());
+        for line in buf.split_inclusive(|&byte| byte == b'\n') {
+            prefixed.extend_from_slice(prefix.as_bytes());
+            prefixed.extend_from_slice(line);
+        }
+        self.writer.write_all(&prefixed)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for PrefixedWriter {
+    type Writer = PrefixedWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+/// Periodically report the lines dropped by the writers because their buffer was full
+pub struct DroppedLinesReporter {
+    /// Stop the reporter thread when dropped
+    _stop: Sender<()>,
+}
+
+impl DroppedLinesReporter {
+    pub fn new(senders: Vec<BufferSender>, interval: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel();
+
+        thread::spawn(move || {
+            // Writers reused from a previous configuration may have already dropped lines
+            let mut reported: usize = senders.iter().map(BufferSender::dropped_lines).sum();
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let dropped: usize = senders.iter().map(BufferSender::dropped_lines).sum();
+                if dropped > reported {
+                    warn!(
+                        dropped = dropped - reported,
+                        "log lines dropped because the buffer is full"
+                    );
+                    reported = dropped;
+                }
+            }
+        });
+
+        Self { _stop: stop }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writer recording the written bytes along with the number of flushes
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<(Vec<u8>, usize)>>);
+
+    impl Recorder {
+        fn written(&self) -> (Vec<u8>, usize) {
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    impl Write for Recorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().0.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.lock().unwrap().1 += 1;
+            Ok(())
+        }
+    }
+
+    fn deadline() -> Instant {
+        Instant::now() + Duration::from_secs(5)
+    }
+
+    #[test]
+    fn flush_requests_are_not_confused_with_lines() {
+        let recorder = Recorder::default();
+        let config = BufferConfig {
+            mode: BufferMode::Blocking,
+            ..BufferConfig::default()
+        };
+        let BufferedWriter {
+            mut writer,
+            worker_guard,
+            ..
+        } = non_blocking(recorder.clone(), &config);
+
+        // Formerly used as an in-band flush request
+        writer.write_all(b"\0flush\0").unwrap();
+        writer.write_all(b"line\n").unwrap();
+        assert!(writer.flush_worker(deadline()));
+        let (written, flushes) = recorder.written();
+        assert_eq!(written, b"\0flush\0line\n");
+        assert!(flushes >= 1);
+
+        drop(worker_guard);
+        assert!(!writer.flush_worker(deadline()));
+    }
+
+    #[test]
+    fn flush_requests_write_the_pending_batch() {
+        let recorder = Recorder::default();
+        let batch_config = BatchConfig {
+            lines: 100,
+            interval: Duration::from_secs(3600),
+        };
+        let BufferedWriter {
+            mut writer,
+            worker_guard,
+            ..
+        } = non_blocking_batched(
+            recorder.clone(),
+            &BufferConfig::default(),
+            Some(batch_config),
+        );
+
+        writer.write_all(b"first\n").unwrap();
+        assert!(writer.flush_worker(deadline()));
+        assert_eq!(recorder.written().0, b"first\n");
+
+        // The last batch is written once the worker stops
+        writer.write_all(b"second\n").unwrap();
+        drop(worker_guard);
+        assert_eq!(recorder.written().0, b"first\nsecond\n");
+    }
+
+    /// Writer stalling until resumed, such as a disk which stopped responding,
+    /// announcing each write it starts
+    struct StalledSink {
+        started: Sender<()>,
+        resumed: Receiver<()>,
+    }
+
+    impl Write for StalledSink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let _ = self.started.send(());
+            let _ = self.resumed.recv();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn lines_waiting_beyond_the_blocking_timeout_are_dropped() {
+        let (started, write_started) = mpsc::channel();
+        let (resume, resumed) = mpsc::channel();
+        let config = BufferConfig {
+            mode: BufferMode::Blocking,
+            capacity: 1,
+            blocking_timeout_ms: Some(50),
+        };
+        let BufferedWriter {
+            mut writer,
+            worker_guard,
+            ..
+        } = non_blocking(StalledSink { started, resumed }, &config);
+
+        writer.write_all(b"written\n").unwrap();
+        write_started.recv().unwrap();
+        writer.write_all(b"buffered\n").unwrap();
+        let start = Instant::now();
+        writer.write_all(b"dropped\n").unwrap();
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(writer.dropped_lines(), 1);
+
+        drop(resume);
+        drop(worker_guard);
+    }
+
+    #[test]
+    fn blocked_writers_fail_at_once_when_the_worker_stops() {
+        let (sender, receiver) = mpsc::sync_channel(2);
+        let gate = BlockingGate::new(1, Duration::from_secs(60));
+        let mut writer = BufferSender {
+            sender: sender.clone(),
+            lossy: false,
+            dropped: Arc::new(AtomicUsize::new(0)),
+            gate: Some(gate.clone()),
+        };
+
+        // Lines sent after the shutdown request are never received
+        sender.send(Message::Shutdown).unwrap();
+        writer.write_all(b"left in the buffer\n").unwrap();
+        work(Vec::new(), receiver, Some(gate), true);
+
+        let start = Instant::now();
+        let error = writer.write_all(b"after the worker\n").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::BrokenPipe);
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(writer.dropped_lines(), 0);
+    }
+}
@@ -1,194 +1,2790 @@
 use std::{
     env::{self, VarError},
+    ffi::{OsStr, OsString},
+    fs,
+    io::{self, IsTerminal},
     path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    time::Duration,
 };
 
+use chrono::format::{Item, StrftimeItems};
 use indexmap::IndexMap;
-use serde::{de::Error, Deserialize, Deserializer};
-use tracing_subscriber::filter::FilterId;
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
+use tracing::Level;
+use tracing_subscriber::filter::{EnvFilter, FilterId, LevelFilter};
+
+use crate::{
+    error::{LogError, LogResult},
+    format::RENAMEABLE_FIELDS,
+    log_crate::LogCrateLogger,
+    memory::{MemoryWriter, RingBufferWriter, SharedWriter},
+};
 
 pub const DEFAULT_LOG_LEVEL: &str = "info";
+/// Environment variable naming the `[log.environments]` entry to use
+///
+/// The entries and their variable are named environments rather than profiles,
+/// `[log.profiles]` holding the sets of options shared by appenders.
+pub const LOG_ENVIRONMENT_VAR: &str = "APP_LOG_ENVIRONMENT";
 pub const DEFAULT_LOG_FILENAME: &str = "app.log";
+pub const DEFAULT_MAX_LOG_FILES: usize = 5;
+pub const DEFAULT_FILE_OPEN_RETRY_DELAY_MS: u64 = 100;
+pub const DEFAULT_BATCH_INTERVAL_MS: u64 = 1000;
+pub const DEFAULT_GELF_HOST: &str = "localhost";
+pub const DEFAULT_GELF_PORT: u16 = 12201;
+/// Number of lines buffered by each writer, as in `tracing_appender`
+pub const DEFAULT_BUFFER_CAPACITY: usize = 128_000;
+pub const DEFAULT_SYSLOG_SOCKET: &str = "/dev/log";
+pub const DEFAULT_SYSLOG_HOST: &str = "localhost";
+pub const DEFAULT_SYSLOG_PORT: u16 = 514;
+pub const DEFAULT_TCP_HOST: &str = "localhost";
+pub const DEFAULT_TCP_PORT: u16 = 5000;
+pub const DEFAULT_TCP_RECONNECT_BACKOFF_MS: u64 = 100;
+pub const DEFAULT_TCP_MAX_RECONNECT_BACKOFF_MS: u64 = 30_000;
+/// Number of lines kept by a ring buffer appender
+pub const DEFAULT_RING_BUFFER_CAPACITY: usize = 1000;
+#[cfg(feature = "otlp")]
+pub const DEFAULT_OTLP_ENDPOINT: &str = "http://localhost:4318";
+#[cfg(feature = "webhook")]
+pub const DEFAULT_WEBHOOK_LEVEL: &str = "error";
+#[cfg(feature = "webhook")]
+pub const DEFAULT_WEBHOOK_TEMPLATE: &str = "[{level}] {target}: {message} {fields}";
+#[cfg(feature = "webhook")]
+pub const DEFAULT_WEBHOOK_RATE_LIMIT: u32 = 10;
+/// Environment variable disabling colors, see <https://no-color.org>
+pub const NO_COLOR_ENV_VAR: &str = "NO_COLOR";
+/// Environment variable overriding the version tag, which defaults to the crate version
+pub const VERSION_TAG_ENV_VAR: &str = "APP_VERSION";
+/// Version of the shape of the `[log]` section, set by its `version` key,
+/// older configurations being migrated when parsed
+pub const CONFIG_VERSION: u32 = 2;
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum LogFormat {
     Full,
     Pretty,
     Compact,
     System,
+    Json,
+    /// JSON objects capped to `max_line_bytes`, for log aggregators
+    Ndjson,
+}
+
+impl LogFormat {
+    /// Name of the format in the configuration
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogFormat::Full => "full",
+            LogFormat::Pretty => "pretty",
+            LogFormat::Compact => "compact",
+            LogFormat::System => "system",
+            LogFormat::Json => "json",
+            LogFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+/// Treatment of event fields named like a key reserved by the formatters,
+/// such as `level` or `timestamp`
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReservedFieldPolicy {
+    /// Prefix the field name with `field.`
+    #[default]
+    Prefix,
+    /// Drop the field
+    Drop,
+    /// Drop the whole event, reporting a formatting error
+    Error,
+}
+
+/// Treatment of the errors of the writers of the file and network appenders,
+/// such as a full disk or a lost connection, the failed lines being dropped
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AppenderErrorPolicy {
+    /// Log an error when an appender starts failing
+    #[default]
+    Log,
+    /// Drop the errors silently
+    Ignore,
+    /// Panic in the writer thread, which stops the appender
+    Panic,
+}
+
+/// Parts of the events shown by the text formats, the unset ones keeping the
+/// default of the format
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DisplayOptions {
+    pub show_target: Option<bool>,
+    pub show_thread_id: Option<bool>,
+    pub show_thread_name: Option<bool>,
+    pub show_level: Option<bool>,
+    pub show_file: Option<bool>,
+    pub show_line: Option<bool>,
+    /// Show the `file:line` location of the events, unless `show_file` or
+    /// `show_line` are set
+    pub source_location: Option<bool>,
+    /// Pad the targets of the `full` and `compact` formats so that messages are aligned
+    pub align: Option<bool>,
+}
+
+impl DisplayOptions {
+    fn inherit(&mut self, profile: &DisplayOptions) {
+        self.show_target = self.show_target.or(profile.show_target);
+        self.show_thread_id = self.show_thread_id.or(profile.show_thread_id);
+        self.show_thread_name = self.show_thread_name.or(profile.show_thread_name);
+        self.show_level = self.show_level.or(profile.show_level);
+        self.show_file = self.show_file.or(profile.show_file);
+        self.show_line = self.show_line.or(profile.show_line);
+        self.source_location = self.source_location.or(profile.source_location);
+        self.align = self.align.or(profile.align);
+    }
+}
+
+/// Level names indexed by their number, as accepted by the level settings
+const LEVEL_NAMES: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+/// Accept a level either as a string or as a number, from 0 (`off`) to 5 (`trace`)
+fn deserialize_level<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Level {
+        Name(String),
+        Number(i64),
+    }
+
+    match Level::deserialize(deserializer)? {
+        Level::Name(level) => Ok(level),
+        Level::Number(level) => usize::try_from(level)
+            .ok()
+            .and_then(|level| LEVEL_NAMES.get(level))
+            .map(|&level| level.to_owned())
+            .ok_or_else(|| D::Error::custom(format!("invalid level {level}, expected 0 to 5"))),
+    }
+}
+
+fn deserialize_optional_level<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_level(deserializer).map(Some)
+}
+
+/// Level bound of a [`LevelRange`], which must be an actual level rather than `off`
+fn deserialize_range_level<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let level = deserialize_level(deserializer)?;
+    match Level::from_str(&level) {
+        Ok(_) => Ok(Some(level)),
+        Err(_) => Err(D::Error::custom(format!(
+            "invalid level `{level}`, expected one of: error, warn, info, debug, trace"
+        ))),
+    }
+}
+
+/// Levels of the `levels` option, which must be actual levels rather than `off`
+fn deserialize_levels<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let levels = Vec::<String>::deserialize(deserializer)?;
+    for level in &levels {
+        if Level::from_str(level).is_err() {
+            return Err(D::Error::custom(format!(
+                "invalid level `{level}`, expected one of: error, warn, info, debug, trace"
+            )));
+        }
+    }
+    Ok(Some(levels))
+}
+
+/// Levels of the events recorded by a file appender, both bounds being
+/// included, such as `{ min = "warn" }` for warnings and errors
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LevelRange {
+    /// Least severe level, such as `warn`
+    #[serde(deserialize_with = "deserialize_range_level")]
+    pub min: Option<String>,
+    /// Most severe level, such as `info` to leave out warnings and errors
+    #[serde(deserialize_with = "deserialize_range_level")]
+    pub max: Option<String>,
+}
+
+impl LevelRange {
+    /// Most verbose level of the range
+    pub fn most_verbose(&self) -> LevelFilter {
+        self.min
+            .as_deref()
+            .and_then(|level| LevelFilter::from_str(level).ok())
+            .unwrap_or(LevelFilter::TRACE)
+    }
+
+    /// Least verbose level of the range, if bounded
+    pub fn least_verbose(&self) -> Option<Level> {
+        self.max
+            .as_deref()
+            .and_then(|level| Level::from_str(level).ok())
+    }
+}
+
+/// Options shared by every kind of appender
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AppenderOptions {
+    #[serde(deserialize_with = "deserialize_optional_level")]
+    pub level: Option<String>,
+    pub directives: Option<String>,
+    /// Complete `EnvFilter` of the appender, such as `warn,[request{user=admin}]=trace`,
+    /// used instead of its level, directives and targets and of the global ones
+    pub filter: Option<String>,
+    pub format: Option<LogFormat>,
+    pub message_from_field: Option<String>,
+    pub min_fields: Option<usize>,
+    /// Maximum number of events per second of each callsite, the others being suppressed
+    pub max_per_second: Option<u32>,
+    pub reserved_fields: Option<ReservedFieldPolicy>,
+    /// Fields of events and spans whose value is replaced by `***`
+    pub redact_fields: Option<Vec<String>>,
+    /// Prefixes of the targets of the events to record, all of them if empty
+    ///
+    /// The level of the appender applies to each target, and must therefore be
+    /// a single level rather than directives.
+    pub targets: Option<Vec<String>>,
+    /// Shorthand restricting the appender to a target prefix, at a given level
+    #[serde(rename = "match")]
+    pub match_filter: Option<MatchFilter>,
+    /// Keys under which the standard fields of JSON events are written, such as `message = "msg"`
+    pub field_names: Option<IndexMap<String, String>>,
+    /// Add the OpenTelemetry `trace_id` and `span_id` of the current span to events
+    pub otel_ids: Option<bool>,
+    /// Size in bytes beyond which the message of events is truncated
+    pub max_message_bytes: Option<usize>,
+    /// Size in bytes beyond which the value of each field is truncated,
+    /// replacing the global one
+    pub max_field_value_bytes: Option<usize>,
+    /// Size in bytes of the lines of the `ndjson` format beyond which the
+    /// message of events is truncated and the `truncated` field is set
+    pub max_line_bytes: Option<usize>,
+    /// Write the fields of the spans of events along with their own fields
+    pub flatten_spans: Option<bool>,
+    /// Environment variable condition enabling the appender, which is always enabled if unset
+    pub enabled_if_env: Option<EnvCondition>,
+    /// Field which events must have to be recorded, such as `{ name = "alert", value = "true" }`
+    pub require_field: Option<RequiredField>,
+    /// Prefix of the names of the threads whose events are recorded, such as `io-`,
+    /// unnamed threads being excluded
+    pub thread_name_prefix: Option<String>,
+    /// Exact levels of the events recorded, such as `["debug", "error"]`, on
+    /// top of the level and directives of the appender
+    #[serde(deserialize_with = "deserialize_levels")]
+    pub levels: Option<Vec<String>>,
+    /// Text starting every line of the events, such as `[api-7] `, replacing
+    /// the global one
+    pub line_prefix: Option<String>,
+    /// Labels grouping appenders for bulk operations, such as `["network"]`
+    pub tags: Option<Vec<String>>,
+    /// Add the global constant fields and the context fields of the thread to
+    /// the events, which is the default
+    pub include_context: Option<bool>,
+    /// Keep only a proportion of the less severe events, such as
+    /// `{ rate = 0.1, always_keep = "warn" }`
+    pub sample: Option<SampleConfig>,
+    /// Window in milliseconds during which the repeats of an event are
+    /// suppressed, then summarized, for the text and JSON formats
+    pub dedup_window_ms: Option<u64>,
+    #[serde(flatten)]
+    pub display: DisplayOptions,
+    /// Profile from `[log.profiles]` providing the options which are not set
+    pub profile: Option<String>,
+}
+
+impl AppenderOptions {
+    /// Fill the options which are not set from a profile
+    fn inherit(&mut self, profile: &AppenderOptions) {
+        self.level = self.level.take().or_else(|| profile.level.clone());
+        self.directives = self
+            .directives
+            .take()
+            .or_else(|| profile.directives.clone());
+        self.filter = self.filter.take().or_else(|| profile.filter.clone());
+        self.format = self.format.or(profile.format);
+        self.message_from_field = self
+            .message_from_field
+            .take()
+            .or_else(|| profile.message_from_field.clone());
+        self.min_fields = self.min_fields.or(profile.min_fields);
+        self.max_per_second = self.max_per_second.or(profile.max_per_second);
+        self.reserved_fields = self.reserved_fields.or(profile.reserved_fields);
+        self.redact_fields = self
+            .redact_fields
+            .take()
+            .or_else(|| profile.redact_fields.clone());
+        self.targets = self.targets.take().or_else(|| profile.targets.clone());
+        self.match_filter = self
+            .match_filter
+            .take()
+            .or_else(|| profile.match_filter.clone());
+        self.field_names = self
+            .field_names
+            .take()
+            .or_else(|| profile.field_names.clone());
+        self.otel_ids = self.otel_ids.or(profile.otel_ids);
+        self.max_message_bytes = self.max_message_bytes.or(profile.max_message_bytes);
+        self.max_field_value_bytes = self.max_field_value_bytes.or(profile.max_field_value_bytes);
+        self.max_line_bytes = self.max_line_bytes.or(profile.max_line_bytes);
+        self.flatten_spans = self.flatten_spans.or(profile.flatten_spans);
+        self.enabled_if_env = self
+            .enabled_if_env
+            .take()
+            .or_else(|| profile.enabled_if_env.clone());
+        self.require_field = self
+            .require_field
+            .take()
+            .or_else(|| profile.require_field.clone());
+        self.thread_name_prefix = self
+            .thread_name_prefix
+            .take()
+            .or_else(|| profile.thread_name_prefix.clone());
+        self.levels = self.levels.take().or_else(|| profile.levels.clone());
+        self.line_prefix = self
+            .line_prefix
+            .take()
+            .or_else(|| profile.line_prefix.clone());
+        self.tags = self.tags.take().or_else(|| profile.tags.clone());
+        self.include_context = self.include_context.or(profile.include_context);
+        self.sample = self.sample.take().or_else(|| profile.sample.clone());
+        self.dedup_window_ms = self.dedup_window_ms.or(profile.dedup_window_ms);
+        self.display.inherit(&profile.display);
+        self.profile = profile.profile.clone();
+    }
+}
+
+/// Events recorded by an appender, such as `{ target = "audit", level = "info" }`,
+/// added to its `targets`
+///
+/// The level applies to every target of the appender, instead of `level`.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+pub struct MatchFilter {
+    pub target: String,
+    #[serde(default, deserialize_with = "deserialize_optional_level")]
+    pub level: Option<String>,
+}
+
+/// Field of the events recorded by an appender, with any value if unset
+///
+/// Values are compared with their text representation, such as `true` or `42`.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+pub struct RequiredField {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+/// Sampling of the events of an appender, such as `{ rate = 0.1, always_keep = "warn" }`
+/// to keep one in ten of the events less severe than warnings
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SampleConfig {
+    /// Probability of keeping an event, between 0 and 1
+    #[serde(deserialize_with = "deserialize_rate")]
+    pub rate: f64,
+    /// Least severe level of the events which are always kept, `warn` by default
+    #[serde(default, deserialize_with = "deserialize_range_level")]
+    pub always_keep: Option<String>,
+    /// Seed of the sampling decisions, which are then the same from one run to
+    /// the next, such as in tests, random seeds being used if unset
+    pub seed: Option<u64>,
+}
+
+/// The rate is validated, so that it is never NaN
+impl Eq for SampleConfig {}
+
+impl SampleConfig {
+    /// Least severe level of the events which are always kept
+    pub fn always_keep_level(&self) -> Level {
+        self.always_keep
+            .as_deref()
+            .and_then(|level| Level::from_str(level).ok())
+            .unwrap_or(Level::WARN)
+    }
+}
+
+fn deserialize_rate<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let rate = f64::deserialize(deserializer)?;
+    if !(0.0..=1.0).contains(&rate) {
+        return Err(D::Error::custom(format!(
+            "invalid sampling rate `{rate}`, expected a number between 0 and 1"
+        )));
+    }
+    Ok(rate)
+}
+
+/// Condition on an environment variable, such as `{ var = "APP_ENV", equals = "dev" }`
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+pub struct EnvCondition {
+    pub var: String,
+    pub equals: String,
+}
+
+impl EnvCondition {
+    /// Whether the variable is set to the expected value
+    pub fn is_met(&self) -> bool {
+        env::var_os(&self.var).is_some_and(|value| value == self.equals.as_str())
+    }
+}
+
+/// Accept the level names of `level_colors` in any case, `off` excluded
+fn deserialize_level_colors<'de, D>(
+    deserializer: D,
+) -> Result<Option<IndexMap<String, AnsiStyle>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let level_colors = IndexMap::<String, AnsiStyle>::deserialize(deserializer)?;
+    level_colors
+        .into_iter()
+        .map(|(level, style)| {
+            let level = level.to_lowercase();
+            if level == "off" || !LEVEL_NAMES.contains(&level.as_str()) {
+                return Err(D::Error::custom(format!("invalid level `{level}`")));
+            }
+            Ok((level, style))
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+/// ANSI style made of a color, such as `red` or `bright_red`, and modifiers,
+/// such as `bold` or `dimmed`, separated by spaces
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct AnsiStyle {
+    spec: String,
+    /// SGR parameters, such as `1;31`
+    codes: String,
+}
+
+impl AnsiStyle {
+    /// Escape sequence enabling the style
+    pub fn prefix(&self) -> String {
+        format!("\x1b[{}m", self.codes)
+    }
+}
+
+impl TryFrom<String> for AnsiStyle {
+    type Error = String;
+
+    fn try_from(spec: String) -> Result<Self, Self::Error> {
+        const COLORS: [&str; 8] = [
+            "black", "red", "green", "yellow", "blue", "purple", "cyan", "white",
+        ];
+
+        let codes = spec
+            .split_whitespace()
+            .map(|word| {
+                let code = match word {
+                    "bold" => 1,
+                    "dimmed" => 2,
+                    "italic" => 3,
+                    "underline" => 4,
+                    word => {
+                        let (base, color) = match word.strip_prefix("bright_") {
+                            Some(color) => (90, color),
+                            None => (30, word),
+                        };
+                        let index = COLORS
+                            .iter()
+                            .position(|&name| name == color)
+                            .ok_or_else(|| format!("invalid ANSI style `{word}`"))?;
+                        base + index
+                    }
+                };
+                Ok(code.to_string())
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if codes.is_empty() {
+            return Err("empty ANSI style".to_owned());
+        }
+
+        Ok(Self {
+            codes: codes.join(";"),
+            spec,
+        })
+    }
+}
+
+impl From<AnsiStyle> for String {
+    fn from(style: AnsiStyle) -> Self {
+        style.spec
+    }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum ConsoleTarget {
     Stdout,
     Stderr,
+    /// Stderr for warnings and errors, stdout for the other events
+    Auto,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(default)]
 pub struct ConsoleLogConfig {
-    pub color: bool,
-    pub level: Option<String>,
-    pub format: Option<LogFormat>,
+    /// Use colors, which is otherwise resolved from the environment
+    pub color: Option<bool>,
+    #[serde(flatten)]
+    pub options: AppenderOptions,
     pub target: ConsoleTarget,
+    /// Write warnings and errors to stderr and the other events to stdout,
+    /// whatever the target
+    pub split_streams: bool,
+    /// Styles of the level labels, such as `error = "bright_red"`, the other
+    /// levels keeping the default palette
+    #[serde(deserialize_with = "deserialize_level_colors")]
+    pub level_colors: Option<IndexMap<String, AnsiStyle>>,
 }
 
 impl Default for ConsoleLogConfig {
     fn default() -> Self {
         Self {
-            color: true,
-            level: None,
-            format: None,
+            color: None,
+            options: AppenderOptions::default(),
             target: ConsoleTarget::Stdout,
+            split_streams: false,
+            level_colors: None,
         }
     }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize)]
+impl ConsoleLogConfig {
+    /// Whether warnings and errors are written to stderr and the other events to stdout
+    pub fn splits_streams(&self) -> bool {
+        self.split_streams || self.target == ConsoleTarget::Auto
+    }
+
+    /// Resolve whether to use colors from the environment, see [`resolve_color`]
+    fn resolve_color(&self) -> bool {
+        let is_terminal = match self.target {
+            ConsoleTarget::Stdout | ConsoleTarget::Auto => io::stdout().is_terminal(),
+            ConsoleTarget::Stderr => io::stderr().is_terminal(),
+        };
+        resolve_color(
+            self.color,
+            env::var_os(NO_COLOR_ENV_VAR).as_deref(),
+            is_terminal,
+        )
+    }
+}
+
+/// Resolve whether to use colors, by order of precedence:
+/// - the explicit `color` setting
+/// - the `NO_COLOR` environment variable, which disables colors unless empty
+/// - whether the target is a terminal
+fn resolve_color(color: Option<bool>, no_color: Option<&OsStr>, is_terminal: bool) -> bool {
+    if let Some(color) = color {
+        return color;
+    }
+    if no_color.is_some_and(|value| !value.is_empty()) {
+        return false;
+    }
+    is_terminal
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum FileWritingMode {
     Append,
     Overwrite,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(default)]
 pub struct FileLogConfig {
     pub color: bool,
-    pub level: Option<String>,
-    pub format: Option<LogFormat>,
+    #[serde(flatten)]
+    pub options: AppenderOptions,
     pub path: PathBuf,
     pub mode: FileWritingMode,
+    /// Write the file as gzip, a gzip member being ended each time the appender
+    /// is flushed, so that the file is complete whenever the appender is idle
+    pub compress: bool,
+    /// Size in bytes (before compression) beyond which the file is rotated
+    pub max_size_bytes: Option<u64>,
+    /// Number of rotated files kept, such as `app.log.1`
+    pub max_files: usize,
+    pub line_ending: LineEnding,
+    /// Capacity of the buffer of the file writes, which are unbuffered if unset
+    pub write_buffer_bytes: Option<usize>,
+    /// Remove the ANSI escape sequences of the formatted lines, such as colors
+    /// written by fields or by a format shared with a console appender
+    pub strip_ansi: bool,
+    /// Levels of the recorded events, on top of the level of the appender
+    pub level_range: Option<LevelRange>,
+    /// Start the file with a UTF-8 byte order mark, when it is created or
+    /// overwritten, or when appending to an empty file
+    pub bom: bool,
+    /// Number of times opening the file is retried after a transient failure,
+    /// such as on a network file system
+    pub open_retries: u32,
+    /// Delay in milliseconds between the attempts to open the file
+    pub open_retry_delay_ms: u64,
+    /// Number of lines written at once, lines being written one by one if unset
+    pub batch_lines: Option<usize>,
+    /// Delay in milliseconds after which a partial batch of lines is written
+    pub batch_interval_ms: u64,
+    /// Second file receiving a copy of every line, such as on a persistent
+    /// disk, whose errors do not affect the main file
+    pub mirror_path: Option<PathBuf>,
 }
 
 impl Default for FileLogConfig {
     fn default() -> Self {
         Self {
             color: false,
-            level: None,
-            format: None,
+            options: AppenderOptions::default(),
             path: DEFAULT_LOG_FILENAME.to_owned().into(),
             mode: FileWritingMode::Append,
+            compress: false,
+            max_size_bytes: None,
+            max_files: DEFAULT_MAX_LOG_FILES,
+            line_ending: LineEnding::default(),
+            write_buffer_bytes: None,
+            strip_ansi: false,
+            level_range: None,
+            bom: false,
+            open_retries: 0,
+            open_retry_delay_ms: DEFAULT_FILE_OPEN_RETRY_DELAY_MS,
+            batch_lines: None,
+            batch_interval_ms: DEFAULT_BATCH_INTERVAL_MS,
+            mirror_path: None,
         }
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
-#[serde(tag = "kind", rename_all = "snake_case")]
-pub enum AppenderLogConfig {
-    Console(ConsoleLogConfig),
-    File(FileLogConfig),
+/// Graylog Extended Log Format appender, sending events over UDP
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GelfLogConfig {
+    #[serde(flatten)]
+    pub options: AppenderOptions,
+    pub host: String,
+    pub port: u16,
+    pub facility: Option<String>,
 }
 
-#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize)]
-#[serde(default)]
-pub struct LogConfigs {
-    pub appenders: IndexMap<String, AppenderLogConfig>,
+impl Default for GelfLogConfig {
+    fn default() -> Self {
+        Self {
+            options: AppenderOptions::default(),
+            host: DEFAULT_GELF_HOST.to_owned(),
+            port: DEFAULT_GELF_PORT,
+            facility: None,
+        }
+    }
 }
 
-fn deserialize_log_configs<'de, D>(deserializer: D) -> Result<LogConfigs, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let mut log_configs = LogConfigs::deserialize(deserializer)?;
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogTransport {
+    /// Local datagram socket
+    Unix,
+    Udp,
+    Tcp,
+}
 
-    if log_configs.appenders.is_empty() {
-        log_configs.appenders.insert(
-            "stdout".into(),
-            AppenderLogConfig::Console(ConsoleLogConfig::default()),
-        );
+/// Syslog facility, as defined by RFC 5424
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogFacility {
+    Kern = 0,
+    User = 1,
+    Mail = 2,
+    Daemon = 3,
+    Auth = 4,
+    Syslog = 5,
+    Lpr = 6,
+    News = 7,
+    Uucp = 8,
+    Cron = 9,
+    Authpriv = 10,
+    Ftp = 11,
+    Local0 = 16,
+    Local1 = 17,
+    Local2 = 18,
+    Local3 = 19,
+    Local4 = 20,
+    Local5 = 21,
+    Local6 = 22,
+    Local7 = 23,
+}
+
+/// Syslog appender, sending RFC 5424 messages
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SyslogLogConfig {
+    #[serde(flatten)]
+    pub options: AppenderOptions,
+    pub transport: SyslogTransport,
+    /// Socket path, used by the `unix` transport
+    pub path: PathBuf,
+    /// Server address, used by the `udp` and `tcp` transports
+    pub host: String,
+    pub port: u16,
+    pub facility: SyslogFacility,
+}
+
+impl Default for SyslogLogConfig {
+    fn default() -> Self {
+        Self {
+            options: AppenderOptions::default(),
+            transport: SyslogTransport::Unix,
+            path: PathBuf::from(DEFAULT_SYSLOG_SOCKET),
+            host: DEFAULT_SYSLOG_HOST.to_owned(),
+            port: DEFAULT_SYSLOG_PORT,
+            facility: SyslogFacility::User,
+        }
     }
+}
 
-    // `tracing` limits the number of simultaneous filters
-    let max_appenders = FilterId::MAX_ID;
-    if log_configs.appenders.len() > max_appenders as usize {
-        let msg = format!("cannot have more than {max_appenders} appenders");
-        return Err(D::Error::custom(msg));
+/// Appender sending formatted events as lines over a TCP connection
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TcpLogConfig {
+    #[serde(flatten)]
+    pub options: AppenderOptions,
+    pub host: String,
+    pub port: u16,
+    /// Delay before the first reconnection attempt, doubled after each failure
+    pub reconnect_backoff_ms: u64,
+    /// Upper bound of the delay between two reconnection attempts
+    pub max_reconnect_backoff_ms: u64,
+}
+
+impl Default for TcpLogConfig {
+    fn default() -> Self {
+        Self {
+            options: AppenderOptions::default(),
+            host: DEFAULT_TCP_HOST.to_owned(),
+            port: DEFAULT_TCP_PORT,
+            reconnect_backoff_ms: DEFAULT_TCP_RECONNECT_BACKOFF_MS,
+            max_reconnect_backoff_ms: DEFAULT_TCP_MAX_RECONNECT_BACKOFF_MS,
+        }
     }
+}
 
-    Ok(log_configs)
+/// Appender keeping formatted events in memory, mostly useful in tests
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MemoryLogConfig {
+    #[serde(flatten)]
+    pub options: AppenderOptions,
+    /// Captured lines, see `LogGuard::captured_lines`
+    #[serde(skip)]
+    pub writer: MemoryWriter,
 }
 
-/// Global log configuration
-#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+/// Appender writing formatted events to a writer supplied by the application,
+/// which can only be added with `LogBuilder::writer_appender`
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(default)]
-pub struct GlobalLogConfig {
+pub struct WriterLogConfig {
+    #[serde(flatten)]
+    pub options: AppenderOptions,
     #[serde(skip)]
-    pub level_from_env: Option<String>,
-    pub level: String,
-    pub format: LogFormat,
+    pub writer: SharedWriter,
 }
 
-impl Default for GlobalLogConfig {
+/// Appender keeping the most recent formatted events in memory, for instance
+/// to show them on an admin endpoint
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RingBufferLogConfig {
+    #[serde(flatten)]
+    pub options: AppenderOptions,
+    /// Number of lines kept, the oldest ones being evicted
+    pub capacity: usize,
+    /// Kept lines, see `LogGuard::recent_lines`
+    #[serde(skip)]
+    pub writer: RingBufferWriter,
+}
+
+impl Default for RingBufferLogConfig {
     fn default() -> Self {
         Self {
-            level_from_env: None,
-            level: DEFAULT_LOG_LEVEL.to_owned(),
-            format: LogFormat::Full,
+            options: AppenderOptions::default(),
+            capacity: DEFAULT_RING_BUFFER_CAPACITY,
+            writer: RingBufferWriter::new(),
         }
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
-pub struct Log {
+/// Appender forwarding events as records of the `log` crate, for instance to
+/// feed a legacy `log`-based sink
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LogCrateLogConfig {
     #[serde(flatten)]
-    pub global: GlobalLogConfig,
-    #[serde(flatten, deserialize_with = "deserialize_log_configs")]
-    pub configs: LogConfigs,
+    pub options: AppenderOptions,
+    /// Logger receiving the records, set programmatically, the global `log`
+    /// logger being used otherwise
+    #[serde(skip)]
+    pub logger: LogCrateLogger,
 }
 
-impl Log {
-    pub fn parse(file_contents: &str, data_dir: &Path) -> eyre::Result<Self> {
-        let level_from_env = match env::var("RUST_LOG") {
-            Ok(level) => Some(level),
-            Err(VarError::NotPresent) => None,
-            Err(err) => return Err(err.into()),
-        };
+/// Appender sending structured events to the systemd journal
+#[cfg(target_os = "linux")]
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct JournaldLogConfig {
+    #[serde(flatten)]
+    pub options: AppenderOptions,
+    /// Value of `SYSLOG_IDENTIFIER`, which defaults to the process name
+    pub syslog_identifier: Option<String>,
+    /// Prefix of the event fields, which defaults to `F`
+    pub field_prefix: Option<String>,
+}
 
-        #[derive(Deserialize)]
-        struct LogSection {
-            log: Log,
-        }
+/// Appender reporting events to the Windows Event Log
+#[cfg(windows)]
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EventLogLogConfig {
+    #[serde(flatten)]
+    pub options: AppenderOptions,
+    /// Event source, which defaults to the process name
+    pub source: Option<String>,
+}
 
-        let mut log = toml::from_str::<LogSection>(file_contents)?.log;
-        log.global.level_from_env = level_from_env;
+/// OpenTelemetry appender, exporting events as OTLP log records over HTTP
+#[cfg(feature = "otlp")]
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct OtlpLogConfig {
+    #[serde(flatten)]
+    pub options: AppenderOptions,
+    /// Base URL of the collector, to which `/v1/logs` is appended
+    pub endpoint: String,
+    /// Additional HTTP headers, such as authentication tokens
+    pub headers: IndexMap<String, String>,
+    /// Value of the `service.name` resource attribute, which defaults to the process name
+    pub service_name: Option<String>,
+}
 
-        for appender in log.configs.appenders.values_mut() {
-            let path = match appender {
-                AppenderLogConfig::Console(_) => continue,
-                AppenderLogConfig::File(file) => &mut file.path,
-            };
-            *path = data_dir.join(&path);
+#[cfg(feature = "otlp")]
+impl Default for OtlpLogConfig {
+    fn default() -> Self {
+        Self {
+            options: AppenderOptions::default(),
+            endpoint: DEFAULT_OTLP_ENDPOINT.to_owned(),
+            headers: IndexMap::new(),
+            service_name: None,
         }
+    }
+}
 
-        Ok(log)
+/// Appender posting high-severity events to a webhook, such as a Slack incoming webhook
+#[cfg(feature = "webhook")]
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WebhookLogConfig {
+    #[serde(flatten)]
+    pub options: AppenderOptions,
+    pub url: String,
+    /// Message body, see `WebhookFormat` for the supported placeholders
+    pub template: String,
+    /// Maximum number of messages posted per minute
+    pub rate_limit: u32,
+}
+
+#[cfg(feature = "webhook")]
+impl Default for WebhookLogConfig {
+    fn default() -> Self {
+        Self {
+            options: AppenderOptions {
+                level: Some(DEFAULT_WEBHOOK_LEVEL.to_owned()),
+                ..Default::default()
+            },
+            url: String::new(),
+            template: DEFAULT_WEBHOOK_TEMPLATE.to_owned(),
+            rate_limit: DEFAULT_WEBHOOK_RATE_LIMIT,
+        }
     }
 }
 
-/// Common methods for a log configuration
-pub trait LogConfig {
-    fn color(&self) -> bool;
-    fn level(&self) -> Option<&str>;
-    fn format(&self) -> Option<LogFormat>;
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AppenderLogConfig {
+    Console(ConsoleLogConfig),
+    File(FileLogConfig),
+    Gelf(GelfLogConfig),
+    Syslog(SyslogLogConfig),
+    Tcp(TcpLogConfig),
+    Memory(MemoryLogConfig),
+    RingBuffer(RingBufferLogConfig),
+    /// Not available in configuration files, since the writer is supplied by the application
+    #[serde(skip_deserializing)]
+    Writer(WriterLogConfig),
+    LogCrate(LogCrateLogConfig),
+    #[cfg(target_os = "linux")]
+    Journald(JournaldLogConfig),
+    #[cfg(windows)]
+    EventLog(EventLogLogConfig),
+    #[cfg(feature = "otlp")]
+    Otlp(OtlpLogConfig),
+    #[cfg(feature = "webhook")]
+    Webhook(WebhookLogConfig),
 }
 
-macro_rules! impl_log_config {
-    ($struct_name:ident) => {
-        impl LogConfig for $struct_name {
-            fn color(&self) -> bool {
-                self.color
+impl AppenderLogConfig {
+    /// Kind of the appender, as written in the configuration
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AppenderLogConfig::Console(_) => "console",
+            AppenderLogConfig::File(_) => "file",
+            AppenderLogConfig::Gelf(_) => "gelf",
+            AppenderLogConfig::Syslog(_) => "syslog",
+            AppenderLogConfig::Tcp(_) => "tcp",
+            AppenderLogConfig::Memory(_) => "memory",
+            AppenderLogConfig::RingBuffer(_) => "ring_buffer",
+            AppenderLogConfig::Writer(_) => "writer",
+            AppenderLogConfig::LogCrate(_) => "log_crate",
+            #[cfg(target_os = "linux")]
+            AppenderLogConfig::Journald(_) => "journald",
+            #[cfg(windows)]
+            AppenderLogConfig::EventLog(_) => "event_log",
+            #[cfg(feature = "otlp")]
+            AppenderLogConfig::Otlp(_) => "otlp",
+            #[cfg(feature = "webhook")]
+            AppenderLogConfig::Webhook(_) => "webhook",
+        }
+    }
+
+    /// Whether the appender sends events over the network
+    pub fn is_network(&self) -> bool {
+        match self {
+            AppenderLogConfig::Gelf(_) | AppenderLogConfig::Tcp(_) => true,
+            AppenderLogConfig::Syslog(config) => config.transport != SyslogTransport::Unix,
+            #[cfg(feature = "otlp")]
+            AppenderLogConfig::Otlp(_) => true,
+            #[cfg(feature = "webhook")]
+            AppenderLogConfig::Webhook(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Where the appender writes, such as a file path or a network address
+    ///
+    /// Only the host of webhook URLs is given, since their path may hold a token.
+    pub fn destination(&self) -> String {
+        match self {
+            AppenderLogConfig::Console(config) => match config.target {
+                ConsoleTarget::Stdout => "stdout".to_owned(),
+                ConsoleTarget::Stderr => "stderr".to_owned(),
+                ConsoleTarget::Auto => "stdout+stderr".to_owned(),
+            },
+            AppenderLogConfig::File(config) => config.path.display().to_string(),
+            AppenderLogConfig::Gelf(config) => format!("{}:{}", config.host, config.port),
+            AppenderLogConfig::Syslog(config) => match config.transport {
+                SyslogTransport::Unix => config.path.display().to_string(),
+                SyslogTransport::Udp => format!("udp://{}:{}", config.host, config.port),
+                SyslogTransport::Tcp => format!("tcp://{}:{}", config.host, config.port),
+            },
+            AppenderLogConfig::Tcp(config) => format!("{}:{}", config.host, config.port),
+            AppenderLogConfig::Memory(_) => "memory".to_owned(),
+            AppenderLogConfig::RingBuffer(config) => format!("memory ({} lines)", config.capacity),
+            AppenderLogConfig::Writer(_) => "writer".to_owned(),
+            AppenderLogConfig::LogCrate(_) => "log".to_owned(),
+            #[cfg(target_os = "linux")]
+            AppenderLogConfig::Journald(_) => "journald".to_owned(),
+            #[cfg(windows)]
+            AppenderLogConfig::EventLog(config) => config
+                .source
+                .clone()
+                .unwrap_or_else(|| "event log".to_owned()),
+            #[cfg(feature = "otlp")]
+            AppenderLogConfig::Otlp(config) => config.endpoint.clone(),
+            #[cfg(feature = "webhook")]
+            AppenderLogConfig::Webhook(config) => {
+                let host_end = config
+                    .url
+                    .match_indices('/')
+                    .nth(2)
+                    .map_or(config.url.len(), |(index, _)| index);
+                config.url[..host_end].to_owned()
+            }
+        }
+    }
+
+    /// Options common to every kind of appender
+    pub fn log_config(&self) -> &dyn LogConfig {
+        match self {
+            AppenderLogConfig::Console(config) => config,
+            AppenderLogConfig::File(config) => config,
+            AppenderLogConfig::Gelf(config) => config,
+            AppenderLogConfig::Syslog(config) => config,
+            AppenderLogConfig::Tcp(config) => config,
+            AppenderLogConfig::Memory(config) => config,
+            AppenderLogConfig::RingBuffer(config) => config,
+            AppenderLogConfig::Writer(config) => config,
+            AppenderLogConfig::LogCrate(config) => config,
+            #[cfg(target_os = "linux")]
+            AppenderLogConfig::Journald(config) => config,
+            #[cfg(windows)]
+            AppenderLogConfig::EventLog(config) => config,
+            #[cfg(feature = "otlp")]
+            AppenderLogConfig::Otlp(config) => config,
+            #[cfg(feature = "webhook")]
+            AppenderLogConfig::Webhook(config) => config,
+        }
+    }
+
+    pub(crate) fn options_mut(&mut self) -> &mut AppenderOptions {
+        match self {
+            AppenderLogConfig::Console(config) => &mut config.options,
+            AppenderLogConfig::File(config) => &mut config.options,
+            AppenderLogConfig::Gelf(config) => &mut config.options,
+            AppenderLogConfig::Syslog(config) => &mut config.options,
+            AppenderLogConfig::Tcp(config) => &mut config.options,
+            AppenderLogConfig::Memory(config) => &mut config.options,
+            AppenderLogConfig::RingBuffer(config) => &mut config.options,
+            AppenderLogConfig::Writer(config) => &mut config.options,
+            AppenderLogConfig::LogCrate(config) => &mut config.options,
+            #[cfg(target_os = "linux")]
+            AppenderLogConfig::Journald(config) => &mut config.options,
+            #[cfg(windows)]
+            AppenderLogConfig::EventLog(config) => &mut config.options,
+            #[cfg(feature = "otlp")]
+            AppenderLogConfig::Otlp(config) => &mut config.options,
+            #[cfg(feature = "webhook")]
+            AppenderLogConfig::Webhook(config) => &mut config.options,
+        }
+    }
+
+    /// Take the handles of the current appender of the same name, such as its
+    /// writer, which are not serialized and therefore lost by [`Log::merge`]
+    fn keep_handles(&mut self, current: &AppenderLogConfig) {
+        match (&mut *self, current) {
+            (AppenderLogConfig::Memory(config), AppenderLogConfig::Memory(current)) => {
+                config.writer = current.writer.clone();
             }
-            fn level(&self) -> Option<&str> {
-                self.level.as_deref()
+            (AppenderLogConfig::RingBuffer(config), AppenderLogConfig::RingBuffer(current)) => {
+                config.writer = current.writer.clone();
             }
-            fn format(&self) -> Option<LogFormat> {
-                self.format
+            (AppenderLogConfig::LogCrate(config), AppenderLogConfig::LogCrate(current)) => {
+                config.logger = current.logger.clone();
             }
+            // Merged as a memory appender, since it cannot be read back
+            (AppenderLogConfig::Memory(config), AppenderLogConfig::Writer(current)) => {
+                let options = config.options.clone();
+                *self = AppenderLogConfig::Writer(WriterLogConfig {
+                    options,
+                    writer: current.writer.clone(),
+                });
+            }
+            _ => {}
         }
-    };
+    }
 }
 
-impl_log_config!(ConsoleLogConfig);
-impl_log_config!(FileLogConfig);
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LogConfigs {
+    #[serde(deserialize_with = "deserialize_appenders")]
+    pub appenders: IndexMap<String, AppenderLogConfig>,
+}
+
+/// Appender of the `[[log.appenders]]` array form, named by a field
+#[derive(Deserialize)]
+struct NamedAppenderLogConfig {
+    name: String,
+    #[serde(flatten)]
+    config: AppenderLogConfig,
+}
+
+/// Accept appenders either as `[log.appenders.<name>]` tables or as a
+/// `[[log.appenders]]` array of tables with a `name` field
+fn deserialize_appenders<'de, D>(
+    deserializer: D,
+) -> Result<IndexMap<String, AppenderLogConfig>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Appenders {
+        Tables(IndexMap<String, AppenderLogConfig>),
+        Array(Vec<NamedAppenderLogConfig>),
+    }
+
+    let appenders = match Appenders::deserialize(deserializer)? {
+        Appenders::Tables(appenders) => return Ok(appenders),
+        Appenders::Array(appenders) => appenders,
+    };
+
+    let mut named_appenders = IndexMap::with_capacity(appenders.len());
+    for NamedAppenderLogConfig { name, config } in appenders {
+        if named_appenders.contains_key(&name) {
+            return Err(D::Error::custom(format!("duplicate appender `{name}`")));
+        }
+        named_appenders.insert(name, config);
+    }
+    Ok(named_appenders)
+}
+
+/// Maximum number of appenders of a configuration, since `tracing` limits the
+/// number of simultaneous filters
+pub fn max_appenders() -> usize {
+    FilterId::MAX_ID as usize
+}
+
+fn deserialize_log_configs<'de, D>(deserializer: D) -> Result<LogConfigs, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut log_configs = LogConfigs::deserialize(deserializer)?;
+
+    if log_configs.appenders.is_empty() {
+        log_configs.appenders.insert(
+            "stdout".into(),
+            AppenderLogConfig::Console(ConsoleLogConfig::default()),
+        );
+    }
+
+    let max_appenders = max_appenders();
+    if log_configs.appenders.len() > max_appenders {
+        let msg = format!("cannot have more than {max_appenders} appenders");
+        return Err(D::Error::custom(msg));
+    }
+
+    Ok(log_configs)
+}
+
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum TimestampFormat {
+    #[default]
+    Rfc3339,
+    EpochMillis,
+    EpochSeconds,
+    /// Custom `strftime` format string
+    Custom(String),
+}
+
+impl TryFrom<String> for TimestampFormat {
+    type Error = String;
+
+    fn try_from(format: String) -> Result<Self, Self::Error> {
+        match format.as_str() {
+            "rfc3339" => Ok(Self::Rfc3339),
+            "epoch_millis" => Ok(Self::EpochMillis),
+            "epoch_seconds" => Ok(Self::EpochSeconds),
+            _ if StrftimeItems::new(&format).any(|item| item == Item::Error) => {
+                Err(format!("invalid timestamp format `{format}`"))
+            }
+            _ => Ok(Self::Custom(format)),
+        }
+    }
+}
+
+impl From<TimestampFormat> for String {
+    fn from(format: TimestampFormat) -> Self {
+        match format {
+            TimestampFormat::Rfc3339 => "rfc3339".to_owned(),
+            TimestampFormat::EpochMillis => "epoch_millis".to_owned(),
+            TimestampFormat::EpochSeconds => "epoch_seconds".to_owned(),
+            TimestampFormat::Custom(format) => format,
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Timezone {
+    #[default]
+    Utc,
+    Local,
+}
+
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TimestampConfig {
+    pub format: TimestampFormat,
+    pub timezone: Timezone,
+}
+
+/// Behavior of the writers when their buffer is full
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BufferMode {
+    /// Drop new lines, which are counted and periodically reported
+    #[default]
+    Lossy,
+    /// Block the logging thread until there is room in the buffer, or until
+    /// the `blocking_timeout_ms` delay after which the line is dropped
+    Blocking,
+}
+
+/// Buffer of the non-blocking writers, configured by the `[log.buffer]` section
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BufferConfig {
+    pub mode: BufferMode,
+    /// Maximum number of buffered lines
+    pub capacity: usize,
+    /// Delay after which the lines waiting for room in the buffer are dropped,
+    /// with the blocking mode, instead of blocking the logging thread forever
+    pub blocking_timeout_ms: Option<u64>,
+}
+
+impl BufferConfig {
+    /// Delay after which a line waiting for room in the buffer is dropped, if any
+    pub fn blocking_timeout(&self) -> Option<Duration> {
+        (self.mode == BufferMode::Blocking)
+            .then_some(self.blocking_timeout_ms)
+            .flatten()
+            .map(Duration::from_millis)
+    }
+}
+
+impl Default for BufferConfig {
+    fn default() -> Self {
+        Self {
+            mode: BufferMode::default(),
+            capacity: DEFAULT_BUFFER_CAPACITY,
+            blocking_timeout_ms: None,
+        }
+    }
+}
+
+/// Global log configuration
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GlobalLogConfig {
+    #[serde(skip)]
+    pub level_from_env: Option<String>,
+    /// `RUST_LOG` value which was ignored for not being valid Unicode, reported
+    /// once the appenders are installed
+    #[serde(skip)]
+    pub invalid_level_from_env: Option<VarError>,
+    #[serde(deserialize_with = "deserialize_level")]
+    pub level: String,
+    pub format: LogFormat,
+    pub timestamp: TimestampConfig,
+    pub buffer: BufferConfig,
+    pub reserved_fields: ReservedFieldPolicy,
+    pub version_tag: bool,
+    /// Forward the records of the `log` crate to the appenders
+    pub bridge_log: bool,
+    /// Most verbose level which any appender may log, such as `info`
+    #[serde(deserialize_with = "deserialize_optional_level")]
+    pub max_level: Option<String>,
+    /// Targets whose events are dropped by every appender, such as noisy dependencies
+    pub deny_targets: Vec<String>,
+    /// Fields added to every event, such as `service = "api"`
+    pub constant_fields: IndexMap<String, String>,
+    /// Keys under which the standard fields of JSON events are written, which
+    /// appenders complete or override
+    pub field_names: IndexMap<String, String>,
+    /// Named sets of options, which appenders and other profiles reference with `profile`
+    pub profiles: IndexMap<String, AppenderOptions>,
+    /// Shortcut choosing the format of the appenders, see [`DevMode`]
+    pub dev_mode: Option<DevMode>,
+    /// Treatment of the write errors of the file and network appenders
+    pub on_appender_error: AppenderErrorPolicy,
+    /// Measure the latency of each appender, see `LogGuard::appender_latencies`
+    pub profiling: bool,
+    /// Text starting every line of the events of the text formats, such as
+    /// `[api-7] `, and written as a `line_prefix` field by the `json` format
+    pub line_prefix: Option<String>,
+    /// Size in bytes beyond which the value of each field of the events is
+    /// truncated, such as a serialized request body
+    pub max_field_value_bytes: Option<usize>,
+    /// Log the active appenders once logging is initialized, so that the
+    /// first line of the logs describes their configuration
+    pub log_startup_summary: bool,
+}
+
+/// Format of the console appenders chosen by the global `dev_mode` setting
+///
+/// `true` selects the `pretty` format with colors, and `false` the `compact`
+/// format without colors, for the console appenders without a format of their
+/// own. `"force"` selects the `pretty` format with colors for every console
+/// appender. The other appenders are not affected, and `NO_COLOR` still
+/// disables the colors.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "DevModeValue", into = "DevModeValue")]
+pub enum DevMode {
+    Disabled,
+    Enabled,
+    Forced,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum DevModeValue {
+    Bool(bool),
+    Name(String),
+}
+
+impl TryFrom<DevModeValue> for DevMode {
+    type Error = String;
+
+    fn try_from(value: DevModeValue) -> Result<Self, Self::Error> {
+        match value {
+            DevModeValue::Bool(false) => Ok(Self::Disabled),
+            DevModeValue::Bool(true) => Ok(Self::Enabled),
+            DevModeValue::Name(name) if name == "force" => Ok(Self::Forced),
+            DevModeValue::Name(name) => Err(format!(
+                "invalid dev mode `{name}`, expected a boolean or `force`"
+            )),
+        }
+    }
+}
+
+impl From<DevMode> for DevModeValue {
+    fn from(dev_mode: DevMode) -> Self {
+        match dev_mode {
+            DevMode::Disabled => Self::Bool(false),
+            DevMode::Enabled => Self::Bool(true),
+            DevMode::Forced => Self::Name("force".to_owned()),
+        }
+    }
+}
+
+impl Default for GlobalLogConfig {
+    fn default() -> Self {
+        Self {
+            level_from_env: None,
+            invalid_level_from_env: None,
+            level: DEFAULT_LOG_LEVEL.to_owned(),
+            format: LogFormat::Full,
+            timestamp: TimestampConfig::default(),
+            buffer: BufferConfig::default(),
+            reserved_fields: ReservedFieldPolicy::default(),
+            version_tag: false,
+            bridge_log: true,
+            max_level: None,
+            deny_targets: Vec::new(),
+            constant_fields: IndexMap::new(),
+            field_names: IndexMap::new(),
+            profiles: IndexMap::new(),
+            dev_mode: None,
+            on_appender_error: AppenderErrorPolicy::default(),
+            profiling: false,
+            line_prefix: None,
+            max_field_value_bytes: None,
+            log_startup_summary: true,
+        }
+    }
+}
+
+impl GlobalLogConfig {
+    /// Read `RUST_LOG`, a value which is not valid Unicode being ignored so
+    /// that the configured levels apply
+    pub fn read_level_from_env(&mut self) -> LogResult<()> {
+        self.invalid_level_from_env = None;
+        self.level_from_env = match read_rust_log() {
+            Ok(level) => level,
+            Err(LogError::InvalidEnv(error)) => {
+                self.invalid_level_from_env = Some(error);
+                None
+            }
+            Err(error) => return Err(error),
+        };
+        Ok(())
+    }
+
+    /// Ceiling applied to every event before the filters of the appenders
+    pub fn max_level_filter(&self) -> LogResult<LevelFilter> {
+        let Some(level) = &self.max_level else {
+            return Ok(LevelFilter::TRACE);
+        };
+        LevelFilter::from_str(level).map_err(|source| LogError::InvalidMaxLevel {
+            level: level.clone(),
+            source,
+        })
+    }
+
+    /// Resolve the filter directives of an appender, by order of precedence:
+    /// - the `RUST_LOG` environment variable, which overrides everything else
+    /// - the appender directives, appended to the level below
+    /// - the appender level
+    /// - the global level
+    ///
+    /// The denied targets are disabled last, whichever of them applies, so
+    /// that they override `RUST_LOG` and the appender directives as well.
+    pub fn filter_directives(&self, config: &(impl LogConfig + ?Sized)) -> String {
+        let directives = if let Some(level) = &self.level_from_env {
+            level.clone()
+        } else if let Some(filter) = config.filter() {
+            filter.to_owned()
+        } else {
+            self.level_directives(config)
+        };
+        self.deny_targets
+            .iter()
+            .fold(directives, |directives, target| {
+                format!("{directives},{target}=off")
+            })
+    }
+
+    /// Directives of an appender built from its level, targets and directives
+    fn level_directives(&self, config: &(impl LogConfig + ?Sized)) -> String {
+        let match_filter = config.match_filter();
+        let level = self.appender_level(config);
+        let mut targets = config
+            .targets()
+            .iter()
+            .chain(match_filter.map(|match_filter| &match_filter.target))
+            .peekable();
+        let level = match targets.peek() {
+            None => level.to_owned(),
+            // Disable every target but the listed ones
+            Some(_) => targets.fold("off".to_owned(), |directives, target| {
+                format!("{directives},{target}={level}")
+            }),
+        };
+        match config.directives() {
+            Some(directives) => format!("{level},{directives}"),
+            None => level,
+        }
+    }
+
+    /// Level of an appender, which applies to each of its targets if it has any
+    fn appender_level<'a>(&'a self, config: &'a (impl LogConfig + ?Sized)) -> &'a str {
+        config
+            .match_filter()
+            .and_then(|match_filter| match_filter.level.as_deref())
+            .or(config.level())
+            .unwrap_or(&self.level)
+    }
+
+    /// Whether the directives of an appender are built from its targets, whose
+    /// level must then be a single level
+    fn has_targets(&self, config: &(impl LogConfig + ?Sized)) -> bool {
+        self.level_from_env.is_none()
+            && config.filter().is_none()
+            && (!config.targets().is_empty() || config.match_filter().is_some())
+    }
+
+    /// Resolve the keys of the standard fields of an appender, its own names
+    /// taking precedence over the global ones
+    pub fn field_names(&self, config: &(impl LogConfig + ?Sized)) -> IndexMap<String, String> {
+        let mut field_names = self.field_names.clone();
+        if let Some(appender_names) = config.field_names() {
+            field_names.extend(appender_names.clone());
+        }
+        field_names
+    }
+
+    /// Format and colors imposed on a console appender by `dev_mode`, if any,
+    /// the colors being disabled by a non-empty `no_color`
+    fn dev_mode_override(
+        &self,
+        config: &(impl LogConfig + ?Sized),
+        no_color: Option<&OsStr>,
+    ) -> Option<(LogFormat, bool)> {
+        if !config.is_console() {
+            return None;
+        }
+        let color = no_color.map_or(true, OsStr::is_empty);
+        match (self.dev_mode?, config.format()) {
+            (DevMode::Forced, _) | (DevMode::Enabled, None) => Some((LogFormat::Pretty, color)),
+            (DevMode::Disabled, None) => Some((LogFormat::Compact, false)),
+            (_, Some(_)) => None,
+        }
+    }
+
+    /// Resolve the format of an appender, by order of precedence:
+    /// - `dev_mode = "force"`, for console appenders
+    /// - the appender format
+    /// - `dev_mode = true` or `false`, for console appenders
+    /// - the global format
+    pub fn format(&self, config: &(impl LogConfig + ?Sized)) -> LogFormat {
+        match self.dev_mode_override(config, None) {
+            Some((format, _)) => format,
+            None => config.format().unwrap_or(self.format),
+        }
+    }
+
+    /// Resolve whether an appender uses colors, which `dev_mode` imposes along
+    /// with the format of console appenders unless `NO_COLOR` disables them
+    pub fn color(&self, config: &(impl LogConfig + ?Sized)) -> bool {
+        let no_color = env::var_os(NO_COLOR_ENV_VAR);
+        match self.dev_mode_override(config, no_color.as_deref()) {
+            Some((_, color)) => color,
+            None => config.color(),
+        }
+    }
+
+    /// Resolve the prefix of the lines of an appender, which replaces the global one
+    pub fn line_prefix<'a>(&'a self, config: &'a (impl LogConfig + ?Sized)) -> Option<&'a str> {
+        config.line_prefix().or(self.line_prefix.as_deref())
+    }
+
+    /// Resolve the constant fields added to the events of an appender, none if
+    /// it excludes the context
+    pub fn constant_fields(&self, config: &(impl LogConfig + ?Sized)) -> IndexMap<String, String> {
+        if config.include_context() {
+            self.constant_fields.clone()
+        } else {
+            IndexMap::new()
+        }
+    }
+
+    /// Resolve the size limit of the field values of an appender, which replaces the global one
+    pub fn max_field_value_bytes(&self, config: &(impl LogConfig + ?Sized)) -> Option<usize> {
+        config
+            .max_field_value_bytes()
+            .or(self.max_field_value_bytes)
+    }
+
+    /// Resolve the version added to every event, if enabled
+    pub fn version(&self) -> Option<String> {
+        if !self.version_tag {
+            return None;
+        }
+        let version =
+            env::var(VERSION_TAG_ENV_VAR).unwrap_or_else(|_| env!("CARGO_PKG_VERSION").to_owned());
+        Some(version)
+    }
+}
+
+#[derive(Debug, Default, Clone, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Log {
+    #[serde(flatten)]
+    pub global: GlobalLogConfig,
+    #[serde(flatten, deserialize_with = "deserialize_log_configs")]
+    pub configs: LogConfigs,
+}
+
+/// Root of a configuration file
+#[derive(Deserialize, Serialize)]
+struct LogSection {
+    log: Log,
+}
+
+impl Log {
+    /// Parse a configuration, whose included files are relative to the data directory
+    pub fn parse(file_contents: &str, data_dir: &Path) -> LogResult<Self> {
+        Self::parse_in(file_contents, data_dir, data_dir)
+    }
+
+    /// Parse a configuration read from a file of `config_dir`, which included
+    /// files are relative to
+    ///
+    /// The files listed by the root `include` key are merged in order, each one
+    /// overriding the previous ones, and the configuration itself overrides them.
+    ///
+    /// The environment named by [`LOG_ENVIRONMENT_VAR`], if any, is selected as
+    /// with [`Log::parse_environment`].
+    pub fn parse_in(file_contents: &str, config_dir: &Path, data_dir: &Path) -> LogResult<Self> {
+        let environment = env::var(LOG_ENVIRONMENT_VAR).ok();
+        Self::parse_environment(file_contents, config_dir, data_dir, environment.as_deref())
+    }
+
+    /// Parse a configuration as with [`Log::parse_in`], using the entry of
+    /// `[log.environments]` named `environment` instead of the top-level settings
+    ///
+    /// Each entry, such as `[log.environments.prod]`, is a complete `[log]`
+    /// section with its own global settings and appenders. The top-level
+    /// settings are used when no environment is given.
+    ///
+    /// The entries are not read from `[log.profiles]`, which already holds the
+    /// named sets of appender options referenced with `profile`.
+    pub fn parse_environment(
+        file_contents: &str,
+        config_dir: &Path,
+        data_dir: &Path,
+        environment: Option<&str>,
+    ) -> LogResult<Self> {
+        let mut value = load_with_includes(file_contents, config_dir, &mut Vec::new())?;
+        select_environment(&mut value, environment)?;
+        migrate(&mut value)?;
+        value.try_into::<LogSection>()?.log.resolve(data_dir)
+    }
+
+    /// Check a configuration without installing any appender, for instance
+    /// before deploying it
+    ///
+    /// Unlike [`Log::parse`], every problem is reported rather than the first
+    /// one, and the directories of the file appenders are checked to be
+    /// writable. The number of appenders is checked while parsing, which stops
+    /// at the first syntax error.
+    pub fn validate(file_contents: &str, data_dir: &Path) -> Result<(), Vec<LogError>> {
+        let environment = env::var(LOG_ENVIRONMENT_VAR).ok();
+        let log = load_with_includes(file_contents, data_dir, &mut Vec::new())
+            .and_then(|mut value| {
+                select_environment(&mut value, environment.as_deref())?;
+                migrate(&mut value)?;
+                Ok(value.try_into::<LogSection>()?)
+            })
+            .and_then(|section| section.log.resolve_unchecked(data_dir))
+            .map_err(|error| vec![error])?;
+
+        let mut errors = log.filter_errors();
+        errors.extend(log.directory_errors());
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Apply a sparse configuration onto this one
+    ///
+    /// The appenders of the patch are added, or updated field by field, except
+    /// those with `remove = true` which are removed. The global settings of the
+    /// patch replace the current ones, tables such as `[log.buffer]` being
+    /// updated field by field as well.
+    pub fn merge(&self, patch_contents: &str, data_dir: &Path) -> LogResult<Self> {
+        let mut patch = toml::from_str::<toml::Value>(patch_contents)?;
+
+        let mut removed = Vec::new();
+        if let Some(appenders) = patch
+            .get_mut("log")
+            .and_then(|log| log.get_mut("appenders"))
+            .and_then(toml::Value::as_table_mut)
+        {
+            for (name, appender) in appenders.iter() {
+                if appender.get("remove") == Some(&toml::Value::Boolean(true)) {
+                    removed.push(name.clone());
+                }
+            }
+            for name in &removed {
+                appenders.remove(name);
+            }
+        }
+
+        // File paths are resolved again once merged
+        let mut current = self.relative_to(data_dir);
+        for name in &removed {
+            current.configs.appenders.shift_remove(name);
+        }
+        // Appenders supplied by the application cannot be read back, and are
+        // merged as memory appenders with the same options instead
+        for appender in current.configs.appenders.values_mut() {
+            if let AppenderLogConfig::Writer(config) = appender {
+                *appender = AppenderLogConfig::Memory(MemoryLogConfig {
+                    options: config.options.clone(),
+                    writer: MemoryWriter::default(),
+                });
+            }
+        }
+
+        let mut merged = toml::Value::try_from(LogSection { log: current })?;
+        merge_values(&mut merged, patch);
+        let mut merged = merged.try_into::<LogSection>()?.log.resolve(data_dir)?;
+        for (name, appender) in &mut merged.configs.appenders {
+            if let Some(current) = self.configs.appenders.get(name) {
+                appender.keep_handles(current);
+            }
+        }
+        Ok(merged)
+    }
+
+    /// Write the configuration as a file which [`Log::parse`] reads back as
+    /// the same configuration, for instance to edit the live configuration
+    ///
+    /// The file paths are written relative to the data directory, and the
+    /// level read from `RUST_LOG` is omitted. The profiles are already applied
+    /// to the appenders, which therefore have every option of their profile.
+    ///
+    /// Appenders supplied by the application, whose writer cannot be written
+    /// to a file, are omitted, and `log_crate` appenders forward to the global
+    /// `log` logger once read back.
+    pub fn to_toml(&self, data_dir: &Path) -> LogResult<String> {
+        let mut log = self.relative_to(data_dir);
+        log.configs
+            .appenders
+            .retain(|_, appender| !matches!(appender, AppenderLogConfig::Writer(_)));
+        let section = LogSection { log };
+        let mut value = toml::Value::try_from(section)?;
+        if let Some(log) = value.get_mut("log").and_then(toml::Value::as_table_mut) {
+            log.insert("version".into(), i64::from(CONFIG_VERSION).into());
+        }
+        Ok(toml::to_string(&value)?)
+    }
+
+    /// Copy of the configuration whose file paths are relative to the data
+    /// directory again, as written in the configuration file
+    fn relative_to(&self, data_dir: &Path) -> Self {
+        let mut log = self.clone();
+        for appender in log.configs.appenders.values_mut() {
+            if let AppenderLogConfig::File(file) = appender {
+                if let Ok(path) = file.path.strip_prefix(data_dir) {
+                    file.path = path.to_owned();
+                }
+                if let Some(mirror_path) = &mut file.mirror_path {
+                    if let Ok(path) = mirror_path.strip_prefix(data_dir) {
+                        *mirror_path = path.to_owned();
+                    }
+                }
+            }
+        }
+        log
+    }
+
+    /// Complete a configuration read from a file or built programmatically:
+    /// read `RUST_LOG`, make the file paths relative to the data directory,
+    /// apply the profiles and check the filters
+    pub fn resolve(self, data_dir: &Path) -> LogResult<Self> {
+        let log = self.resolve_unchecked(data_dir)?;
+        log.validate_filters()?;
+        Ok(log)
+    }
+
+    /// Resolve the configuration without checking the filters
+    fn resolve_unchecked(mut self, data_dir: &Path) -> LogResult<Self> {
+        self.global.read_level_from_env()?;
+
+        for appender in self.configs.appenders.values_mut() {
+            let path = match appender {
+                AppenderLogConfig::Console(_)
+                | AppenderLogConfig::Gelf(_)
+                | AppenderLogConfig::Syslog(_)
+                | AppenderLogConfig::Tcp(_)
+                | AppenderLogConfig::Memory(_)
+                | AppenderLogConfig::RingBuffer(_)
+                | AppenderLogConfig::Writer(_)
+                | AppenderLogConfig::LogCrate(_) => continue,
+                #[cfg(target_os = "linux")]
+                AppenderLogConfig::Journald(_) => continue,
+                #[cfg(windows)]
+                AppenderLogConfig::EventLog(_) => continue,
+                #[cfg(feature = "otlp")]
+                AppenderLogConfig::Otlp(_) => continue,
+                #[cfg(feature = "webhook")]
+                AppenderLogConfig::Webhook(_) => continue,
+                AppenderLogConfig::File(file) => {
+                    if let Some(mirror_path) = &mut file.mirror_path {
+                        *mirror_path = data_dir.join(&mirror_path);
+                    }
+                    &mut file.path
+                }
+            };
+            *path = data_dir.join(&path);
+        }
+
+        self.resolve_profiles()?;
+        Ok(self)
+    }
+
+    /// Apply the profiles referenced by the appenders, and by the profiles themselves
+    pub fn resolve_profiles(&mut self) -> LogResult<()> {
+        let profiles = &self.global.profiles;
+
+        for appender in self.configs.appenders.values_mut() {
+            let options = appender.options_mut();
+            let mut applied = Vec::new();
+
+            while let Some(name) = options.profile.take() {
+                if applied.contains(&name) {
+                    return Err(LogError::ProfileCycle { profile: name });
+                }
+                let profile = profiles
+                    .get(&name)
+                    .ok_or_else(|| LogError::MissingProfile {
+                        profile: name.clone(),
+                    })?;
+                options.inherit(profile);
+                applied.push(name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the filter directives of every appender, so that errors are
+    /// reported before any appender is built
+    pub fn validate_filters(&self) -> LogResult<()> {
+        match self.filter_errors().into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Every error of the levels, targets and filter directives
+    fn filter_errors(&self) -> Vec<LogError> {
+        let mut errors = Vec::new();
+        if let Err(error) = self.global.max_level_filter() {
+            errors.push(error);
+        }
+        let invalid_targets = self
+            .global
+            .deny_targets
+            .iter()
+            .filter(|target| !is_valid_target(target));
+        errors.extend(invalid_targets.map(|target| LogError::InvalidDeniedTarget {
+            target: target.clone(),
+        }));
+
+        for (name, appender) in &self.configs.appenders {
+            let config = appender.log_config();
+            let invalid_targets = config
+                .targets()
+                .iter()
+                .chain(
+                    config
+                        .match_filter()
+                        .map(|match_filter| &match_filter.target),
+                )
+                .filter(|target| !is_valid_target(target));
+            errors.extend(invalid_targets.map(|target| LogError::InvalidTarget {
+                appender: name.clone(),
+                target: target.clone(),
+            }));
+
+            let field_names = self.global.field_names(appender.log_config());
+            let unknown_fields = field_names
+                .keys()
+                .filter(|field| !RENAMEABLE_FIELDS.contains(&field.as_str()));
+            errors.extend(unknown_fields.map(|field| LogError::UnknownFieldName {
+                appender: name.clone(),
+                field: field.clone(),
+            }));
+
+            if config.filter().is_some() && config.has_filter_options() {
+                errors.push(LogError::ConflictingFilter {
+                    appender: name.clone(),
+                });
+            }
+
+            if self.global.has_targets(config) {
+                let level = self.global.appender_level(config);
+                if LevelFilter::from_str(level).is_err() {
+                    errors.push(LogError::TargetsLevel {
+                        appender: name.clone(),
+                        level: level.to_owned(),
+                    });
+                }
+            }
+
+            let directive = self.global.filter_directives(appender.log_config());
+            if let Err(source) = EnvFilter::from_str(&directive) {
+                errors.push(LogError::InvalidAppenderFilter {
+                    appender: name.clone(),
+                    directive,
+                    source,
+                });
+            }
+        }
+        errors
+    }
+
+    /// Check that the directories of the file appenders are writable, the
+    /// missing ones being checked through their closest existing ancestor
+    fn directory_errors(&self) -> Vec<LogError> {
+        let mut errors = Vec::new();
+        for (name, appender) in &self.configs.appenders {
+            let AppenderLogConfig::File(file) = appender else {
+                continue;
+            };
+            let directory = match file.path.parent() {
+                Some(directory) if directory.as_os_str().is_empty() => Path::new("."),
+                Some(directory) => directory,
+                None => continue,
+            };
+            let writable = directory
+                .ancestors()
+                .find(|ancestor| ancestor.exists())
+                .is_some_and(is_writable_dir);
+            if !writable {
+                errors.push(LogError::UnwritableDirectory {
+                    appender: name.clone(),
+                    path: directory.to_owned(),
+                });
+            }
+        }
+        errors
+    }
+}
+
+/// Whether files can be created in a directory, checked by creating and
+/// removing one since the permission bits ignore the owner and the ACLs
+fn is_writable_dir(directory: &Path) -> bool {
+    let probe = directory.join(format!(".log-check-{}", process::id()));
+    match fs::OpenOptions::new().write(true).create(true).open(&probe) {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Whether a target prefix may be used in a filter directive
+fn is_valid_target(target: &str) -> bool {
+    !target.is_empty()
+        && target
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | ':' | '-' | '.'))
+}
+
+/// Read the `RUST_LOG` environment variable, which overrides the configured levels
+pub fn read_rust_log() -> LogResult<Option<String>> {
+    parse_rust_log(env::var_os("RUST_LOG"))
+}
+
+/// Parse the value of `RUST_LOG`, which must be valid Unicode
+fn parse_rust_log(value: Option<OsString>) -> LogResult<Option<String>> {
+    value
+        .map(|value| {
+            value
+                .into_string()
+                .map_err(|value| LogError::InvalidEnv(VarError::NotUnicode(value)))
+        })
+        .transpose()
+}
+
+/// Replace the `[log]` section by one of its `[log.environments]` entries,
+/// or only remove them if no environment is given
+fn select_environment(value: &mut toml::Value, environment: Option<&str>) -> LogResult<()> {
+    let environment = environment.filter(|environment| !environment.is_empty());
+    let Some(log) = value.get_mut("log").and_then(toml::Value::as_table_mut) else {
+        return match environment {
+            Some(environment) => Err(LogError::UnknownEnvironment {
+                environment: environment.to_owned(),
+                available: String::new(),
+            }),
+            None => Ok(()),
+        };
+    };
+    let mut environments = match log.remove("environments") {
+        Some(environments) => environments.try_into::<toml::Table>()?,
+        None => toml::Table::new(),
+    };
+    let Some(environment) = environment else {
+        return Ok(());
+    };
+
+    match environments.remove(environment) {
+        Some(selected) => {
+            *log = selected.try_into::<toml::Table>()?;
+            Ok(())
+        }
+        None => Err(LogError::UnknownEnvironment {
+            environment: environment.to_owned(),
+            available: environments
+                .keys()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(", "),
+        }),
+    }
+}
+
+/// Migrate the `[log]` section to the current version, the files without
+/// `version` key being of version 1
+///
+/// Environments are complete sections, so that they are migrated once selected.
+fn migrate(value: &mut toml::Value) -> LogResult<()> {
+    let Some(log) = value.get_mut("log").and_then(toml::Value::as_table_mut) else {
+        return Ok(());
+    };
+    let version = match log.remove("version") {
+        Some(version) => version.try_into::<u32>()?,
+        None => 1,
+    };
+    if version > CONFIG_VERSION {
+        return Err(LogError::UnsupportedConfigVersion {
+            version,
+            supported: CONFIG_VERSION,
+        });
+    }
+    if version < 2 {
+        migrate_logfile(log);
+    }
+    Ok(())
+}
+
+/// Replace the `logfile = "app.log"` shorthand of version 1 with a file
+/// appender named `logfile`
+fn migrate_logfile(log: &mut toml::Table) {
+    let Some(path) = log.remove("logfile") else {
+        return;
+    };
+    let mut appender = toml::Table::new();
+    appender.insert("kind".into(), "file".into());
+    appender.insert("path".into(), path);
+
+    let appenders = log
+        .entry("appenders")
+        .or_insert_with(|| toml::Table::new().into());
+    match appenders {
+        toml::Value::Table(appenders) => {
+            appenders.entry("logfile").or_insert(appender.into());
+        }
+        toml::Value::Array(appenders) => {
+            appender.insert("name".into(), "logfile".into());
+            appenders.push(appender.into());
+        }
+        _ => {}
+    }
+}
+
+/// Parse a configuration file along with the files it includes, recursively
+///
+/// `including` holds the files being loaded, to detect include cycles.
+fn load_with_includes(
+    file_contents: &str,
+    config_dir: &Path,
+    including: &mut Vec<PathBuf>,
+) -> LogResult<toml::Value> {
+    let mut value = toml::from_str::<toml::Value>(file_contents)?;
+    let Some(includes) = value
+        .as_table_mut()
+        .and_then(|table| table.remove("include"))
+    else {
+        return Ok(value);
+    };
+
+    let mut merged = toml::Value::Table(toml::Table::new());
+    for include in includes.try_into::<Vec<PathBuf>>()? {
+        let path = config_dir.join(include);
+        let contents = fs::read_to_string(&path).map_err(|source| LogError::ReadConfig {
+            path: path.clone(),
+            source,
+        })?;
+        let canonical_path = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if including.contains(&canonical_path) {
+            return Err(LogError::IncludeCycle { path });
+        }
+
+        including.push(canonical_path);
+        let included_dir = path.parent().unwrap_or(config_dir);
+        let included =
+            load_with_includes(&contents, included_dir, including).map_err(|source| {
+                LogError::ConfigFile {
+                    path: path.clone(),
+                    source: Box::new(source),
+                }
+            })?;
+        including.pop();
+
+        merge_values(&mut merged, included);
+    }
+    merge_values(&mut merged, value);
+    Ok(merged)
+}
+
+/// Merge a TOML value into another, tables being merged recursively
+fn merge_values(current: &mut toml::Value, patch: toml::Value) {
+    match (current, patch) {
+        (toml::Value::Table(current), toml::Value::Table(patch)) => {
+            for (key, value) in patch {
+                match current.get_mut(&key) {
+                    Some(current) => merge_values(current, value),
+                    None => {
+                        current.insert(key, value);
+                    }
+                }
+            }
+        }
+        (current, patch) => *current = patch,
+    }
+}
+
+/// Common methods for a log configuration
+pub trait LogConfig {
+    fn color(&self) -> bool;
+    fn options(&self) -> &AppenderOptions;
+    /// Whether the appender writes to the console, whose format `dev_mode` chooses
+    fn is_console(&self) -> bool;
+
+    fn level(&self) -> Option<&str> {
+        self.options().level.as_deref()
+    }
+
+    /// Additional `EnvFilter` directives, such as `my_crate::module=debug,hyper=warn`
+    fn directives(&self) -> Option<&str> {
+        self.options().directives.as_deref()
+    }
+
+    /// Complete `EnvFilter` replacing the other filtering options
+    fn filter(&self) -> Option<&str> {
+        self.options().filter.as_deref()
+    }
+
+    /// Whether the appender has filtering options which a complete filter replaces
+    fn has_filter_options(&self) -> bool {
+        let options = self.options();
+        options.level.is_some()
+            || options.directives.is_some()
+            || options.targets.is_some()
+            || options.match_filter.is_some()
+    }
+
+    fn format(&self) -> Option<LogFormat> {
+        self.options().format
+    }
+
+    /// Field used as the message of events which have none
+    fn message_from_field(&self) -> Option<&str> {
+        self.options().message_from_field.as_deref()
+    }
+
+    /// Minimum number of fields (excluding the message) of the events to log
+    fn min_fields(&self) -> Option<usize> {
+        self.options().min_fields
+    }
+
+    fn max_per_second(&self) -> Option<u32> {
+        self.options().max_per_second
+    }
+
+    fn redact_fields(&self) -> &[String] {
+        self.options().redact_fields.as_deref().unwrap_or_default()
+    }
+
+    fn targets(&self) -> &[String] {
+        self.options().targets.as_deref().unwrap_or_default()
+    }
+
+    fn match_filter(&self) -> Option<&MatchFilter> {
+        self.options().match_filter.as_ref()
+    }
+
+    fn require_field(&self) -> Option<&RequiredField> {
+        self.options().require_field.as_ref()
+    }
+
+    fn thread_name_prefix(&self) -> Option<&str> {
+        self.options().thread_name_prefix.as_deref()
+    }
+
+    fn line_prefix(&self) -> Option<&str> {
+        self.options().line_prefix.as_deref()
+    }
+
+    fn tags(&self) -> &[String] {
+        self.options().tags.as_deref().unwrap_or_default()
+    }
+
+    fn include_context(&self) -> bool {
+        self.options().include_context.unwrap_or(true)
+    }
+
+    fn sample(&self) -> Option<&SampleConfig> {
+        self.options().sample.as_ref()
+    }
+
+    fn dedup_window(&self) -> Option<Duration> {
+        self.options().dedup_window_ms.map(Duration::from_millis)
+    }
+
+    /// Exact levels of the recorded events, if restricted
+    fn levels(&self) -> Option<Vec<Level>> {
+        let levels = self.options().levels.as_ref()?;
+        Some(
+            levels
+                .iter()
+                .filter_map(|level| Level::from_str(level).ok())
+                .collect(),
+        )
+    }
+
+    fn field_names(&self) -> Option<&IndexMap<String, String>> {
+        self.options().field_names.as_ref()
+    }
+
+    fn otel_ids(&self) -> bool {
+        self.options().otel_ids.unwrap_or(false)
+    }
+
+    fn max_message_bytes(&self) -> Option<usize> {
+        self.options().max_message_bytes
+    }
+
+    fn max_field_value_bytes(&self) -> Option<usize> {
+        self.options().max_field_value_bytes
+    }
+
+    fn max_line_bytes(&self) -> Option<usize> {
+        self.options().max_line_bytes
+    }
+
+    fn flatten_spans(&self) -> bool {
+        self.options().flatten_spans.unwrap_or(false)
+    }
+
+    /// Whether the environment enables the appender
+    fn is_enabled(&self) -> bool {
+        self.options()
+            .enabled_if_env
+            .as_ref()
+            .map_or(true, EnvCondition::is_met)
+    }
+
+    fn display(&self) -> DisplayOptions {
+        self.options().display
+    }
+
+    fn reserved_fields(&self) -> Option<ReservedFieldPolicy> {
+        self.options().reserved_fields
+    }
+}
+
+macro_rules! impl_log_config {
+    ($struct_name:ident) => {
+        impl_log_config!($struct_name, |config| config.color);
+    };
+    ($struct_name:ident, |$config:ident| $color:expr) => {
+        impl_log_config!($struct_name, |$config| $color, false);
+    };
+    ($struct_name:ident, |$config:ident| $color:expr, $is_console:expr) => {
+        impl LogConfig for $struct_name {
+            fn color(&self) -> bool {
+                let $config = self;
+                $color
+            }
+            fn options(&self) -> &AppenderOptions {
+                &self.options
+            }
+            fn is_console(&self) -> bool {
+                $is_console
+            }
+        }
+    };
+}
+
+impl_log_config!(ConsoleLogConfig, |config| config.resolve_color(), true);
+impl_log_config!(FileLogConfig);
+impl_log_config!(GelfLogConfig, |_config| false);
+impl_log_config!(SyslogLogConfig, |_config| false);
+impl_log_config!(TcpLogConfig, |_config| false);
+impl_log_config!(MemoryLogConfig, |_config| false);
+impl_log_config!(RingBufferLogConfig, |_config| false);
+impl_log_config!(WriterLogConfig, |_config| false);
+impl_log_config!(LogCrateLogConfig, |_config| false);
+#[cfg(target_os = "linux")]
+impl_log_config!(JournaldLogConfig, |_config| false);
+#[cfg(windows)]
+impl_log_config!(EventLogLogConfig, |_config| false);
+#[cfg(feature = "otlp")]
+impl_log_config!(OtlpLogConfig, |_config| false);
+#[cfg(feature = "webhook")]
+impl_log_config!(WebhookLogConfig, |_config| false);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn console(options: AppenderOptions) -> ConsoleLogConfig {
+        ConsoleLogConfig {
+            options,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn filter_directives_follow_their_precedence() {
+        let mut global = GlobalLogConfig {
+            level: "warn".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(
+            global.filter_directives(&console(Default::default())),
+            "warn"
+        );
+
+        let options = AppenderOptions {
+            level: Some("debug".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(global.filter_directives(&console(options.clone())), "debug");
+
+        let options = AppenderOptions {
+            directives: Some("hyper=off".to_owned()),
+            ..options
+        };
+        let appender = console(options);
+        assert_eq!(global.filter_directives(&appender), "debug,hyper=off");
+
+        global.level_from_env = Some("trace".to_owned());
+        assert_eq!(global.filter_directives(&appender), "trace");
+
+        global.deny_targets = vec!["noisy".to_owned()];
+        assert_eq!(global.filter_directives(&appender), "trace,noisy=off");
+        global.level_from_env = None;
+        let options = AppenderOptions {
+            filter: Some("info,[request]=debug".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            global.filter_directives(&console(options)),
+            "info,[request]=debug,noisy=off"
+        );
+    }
+
+    #[test]
+    fn color_follows_the_setting_then_no_color_then_the_terminal() {
+        let no_color = Some(OsStr::new("1"));
+        assert!(resolve_color(Some(true), no_color, false));
+        assert!(!resolve_color(Some(false), None, true));
+        assert!(!resolve_color(None, no_color, true));
+        assert!(resolve_color(None, Some(OsStr::new("")), true));
+        assert!(resolve_color(None, None, true));
+        assert!(!resolve_color(None, None, false));
+    }
+
+    #[test]
+    fn filters_are_validated_while_parsing() {
+        let valid = r#"
+            [log.appenders.console]
+            kind = "console"
+            level = "info,my_crate=trace,hyper=off"
+        "#;
+        let log = Log::parse(valid, Path::new(".")).unwrap();
+        let level = log.configs.appenders["console"].log_config().level();
+        assert_eq!(level, Some("info,my_crate=trace,hyper=off"));
+
+        let malformed = r#"
+            [log.appenders.console]
+            kind = "console"
+            level = "info,my_crate=trcae"
+        "#;
+        match Log::parse(malformed, Path::new(".")) {
+            Err(LogError::InvalidAppenderFilter {
+                appender,
+                directive,
+                ..
+            }) => {
+                assert_eq!(appender, "console");
+                assert_eq!(directive, "info,my_crate=trcae");
+            }
+            result => panic!("unexpected result: {result:?}"),
+        }
+    }
+
+    #[test]
+    fn profiles_are_inherited_by_the_appenders() {
+        let file_contents = r#"
+            [log.profiles.base]
+            format = "json"
+            level = "debug"
+
+            [log.profiles.standard]
+            profile = "base"
+            level = "info"
+
+            [log.appenders.file]
+            kind = "file"
+            path = "app.log"
+            profile = "standard"
+
+            [log.appenders.console]
+            kind = "console"
+            profile = "standard"
+            level = "warn"
+        "#;
+        let log = Log::parse(file_contents, Path::new(".")).unwrap();
+        let file = log.configs.appenders["file"].log_config().options();
+        assert_eq!(file.level.as_deref(), Some("info"));
+        assert_eq!(file.format, Some(LogFormat::Json));
+        assert_eq!(file.profile, None);
+        let console = log.configs.appenders["console"].log_config().options();
+        assert_eq!(console.level.as_deref(), Some("warn"));
+        assert_eq!(console.format, Some(LogFormat::Json));
+    }
+
+    #[test]
+    fn missing_and_cyclic_profiles_are_rejected() {
+        let missing = r#"
+            [log.appenders.console]
+            kind = "console"
+            profile = "standard"
+        "#;
+        assert!(matches!(
+            Log::parse(missing, Path::new(".")),
+            Err(LogError::MissingProfile { profile }) if profile == "standard"
+        ));
+
+        let cyclic = r#"
+            [log.profiles.first]
+            profile = "second"
+
+            [log.profiles.second]
+            profile = "first"
+
+            [log.appenders.console]
+            kind = "console"
+            profile = "first"
+        "#;
+        assert!(matches!(
+            Log::parse(cyclic, Path::new(".")),
+            Err(LogError::ProfileCycle { profile }) if profile == "first"
+        ));
+    }
+
+    #[test]
+    fn appenders_parse_the_same_as_tables_or_an_array() {
+        let tables = r#"
+            [log.appenders.console]
+            kind = "console"
+            level = "warn"
+
+            [log.appenders.file]
+            kind = "file"
+            path = "app.log"
+        "#;
+        let array = r#"
+            [[log.appenders]]
+            name = "console"
+            kind = "console"
+            level = "warn"
+
+            [[log.appenders]]
+            name = "file"
+            kind = "file"
+            path = "app.log"
+        "#;
+        let from_tables = Log::parse(tables, Path::new(".")).unwrap();
+        let from_array = Log::parse(array, Path::new(".")).unwrap();
+        assert_eq!(from_tables.configs, from_array.configs);
+        let names: Vec<_> = from_array.configs.appenders.keys().collect();
+        assert_eq!(names, ["console", "file"]);
+
+        let duplicate = r#"
+            [[log.appenders]]
+            name = "console"
+            kind = "console"
+
+            [[log.appenders]]
+            name = "console"
+            kind = "console"
+        "#;
+        assert!(Log::parse(duplicate, Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn targets_require_a_single_level() {
+        let file_contents = r#"
+            [log.appenders.jobs]
+            kind = "console"
+            level = "debug"
+            targets = ["my_app::jobs", "my_app::queue"]
+        "#;
+        let log = Log::parse(file_contents, Path::new(".")).unwrap();
+        let appender = log.configs.appenders["jobs"].log_config();
+        assert_eq!(
+            log.global.filter_directives(appender),
+            "off,my_app::jobs=debug,my_app::queue=debug"
+        );
+
+        let file_contents = r#"
+            [log.appenders.jobs]
+            kind = "console"
+            level = "info,my_crate=trace"
+            targets = ["my_app::jobs"]
+        "#;
+        assert!(matches!(
+            Log::parse(file_contents, Path::new(".")),
+            Err(LogError::TargetsLevel { appender, level })
+                if appender == "jobs" && level == "info,my_crate=trace"
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn validation_reports_the_read_only_directories() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let data_dir = crate::testing::temp_dir("read_only_validation");
+        let read_only = data_dir.join("read_only");
+        fs::create_dir(&read_only).unwrap();
+        fs::set_permissions(&read_only, fs::Permissions::from_mode(0o555)).unwrap();
+        let file_contents = r#"
+            [log.appenders.writable]
+            kind = "file"
+            path = "app.log"
+
+            [log.appenders.read_only]
+            kind = "file"
+            path = "read_only/nested/app.log"
+        "#;
+        let result = Log::validate(file_contents, &data_dir);
+        // Privileged users such as root can write regardless of the permissions
+        let privileged = is_writable_dir(&read_only);
+        fs::set_permissions(&read_only, fs::Permissions::from_mode(0o755)).unwrap();
+
+        if privileged {
+            assert!(result.is_ok());
+            return;
+        }
+        let errors = result.unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            &errors[0],
+            LogError::UnwritableDirectory { appender, path }
+                if appender == "read_only" && *path == read_only.join("nested")
+        ));
+    }
+
+    #[test]
+    fn included_files_are_merged_in_order_under_the_configuration() {
+        let dir = crate::testing::temp_dir("include_merge");
+        let base = r#"
+            [log]
+            level = "warn"
+
+            [log.appenders.file]
+            kind = "file"
+            path = "base.log"
+
+            [log.appenders.console]
+            kind = "console"
+        "#;
+        let overrides = r#"
+            [log]
+            level = "debug"
+
+            [log.appenders.file]
+            path = "overrides.log"
+        "#;
+        fs::write(dir.join("base.toml"), base).unwrap();
+        fs::write(dir.join("overrides.toml"), overrides).unwrap();
+        let file_contents = r#"
+            include = ["base.toml", "overrides.toml"]
+
+            [log.appenders.file]
+            level = "error"
+        "#;
+
+        let log = Log::parse_environment(file_contents, &dir, &dir, None).unwrap();
+        assert_eq!(log.global.level, "debug");
+        assert!(log.configs.appenders.contains_key("console"));
+        let AppenderLogConfig::File(file) = &log.configs.appenders["file"] else {
+            panic!("`file` is not a file appender");
+        };
+        assert_eq!(file.path, dir.join("overrides.log"));
+        assert_eq!(file.options.level.as_deref(), Some("error"));
+    }
+
+    #[test]
+    fn include_cycles_and_missing_files_are_rejected() {
+        let dir = crate::testing::temp_dir("include_cycle");
+        fs::write(dir.join("a.toml"), r#"include = ["b.toml"]"#).unwrap();
+        fs::write(dir.join("b.toml"), r#"include = ["a.toml"]"#).unwrap();
+
+        let mut error =
+            Log::parse_environment(r#"include = ["a.toml"]"#, &dir, &dir, None).unwrap_err();
+        while let LogError::ConfigFile { source, .. } = error {
+            error = *source;
+        }
+        assert!(matches!(error, LogError::IncludeCycle { path } if path == dir.join("a.toml")));
+
+        let error =
+            Log::parse_environment(r#"include = ["missing.toml"]"#, &dir, &dir, None).unwrap_err();
+        assert!(
+            matches!(error, LogError::ReadConfig { path, .. } if path == dir.join("missing.toml"))
+        );
+    }
+
+    #[test]
+    fn environments_replace_the_top_level_settings() {
+        let file_contents = r#"
+            [log]
+            level = "info"
+
+            [log.appenders.top_level]
+            kind = "memory"
+
+            [log.environments.dev]
+            level = "debug"
+
+            [log.environments.dev.appenders.dev_console]
+            kind = "console"
+
+            [log.environments.prod]
+            level = "warn"
+
+            [log.environments.prod.appenders.prod_memory]
+            kind = "memory"
+        "#;
+        let select = |environment| {
+            Log::parse_environment(file_contents, Path::new("."), Path::new("."), environment)
+        };
+        let selected = |environment| {
+            let log = select(environment).unwrap();
+            let names: Vec<_> = log.configs.appenders.keys().cloned().collect();
+            (log.global.level, names)
+        };
+
+        assert_eq!(
+            selected(None),
+            ("info".to_owned(), vec!["top_level".to_owned()])
+        );
+        assert_eq!(
+            selected(Some("dev")),
+            ("debug".to_owned(), vec!["dev_console".to_owned()])
+        );
+        assert_eq!(
+            selected(Some("prod")),
+            ("warn".to_owned(), vec!["prod_memory".to_owned()])
+        );
+        match select(Some("staging")) {
+            Err(LogError::UnknownEnvironment { available, .. }) => {
+                assert_eq!(available, "dev, prod");
+            }
+            result => panic!("unexpected result: {result:?}"),
+        }
+    }
+
+    #[test]
+    fn dev_mode_applies_to_the_console_appenders_by_precedence() {
+        let json = AppenderOptions {
+            format: Some(LogFormat::Json),
+            ..Default::default()
+        };
+        let unformatted_console = console(AppenderOptions::default());
+        let formatted_console = console(json);
+        let file = FileLogConfig::default();
+        let global = |dev_mode| GlobalLogConfig {
+            format: LogFormat::Full,
+            dev_mode,
+            ..Default::default()
+        };
+        let no_color = Some(OsStr::new("1"));
+
+        let enabled = global(Some(DevMode::Enabled));
+        assert_eq!(
+            enabled.dev_mode_override(&unformatted_console, None),
+            Some((LogFormat::Pretty, true))
+        );
+        assert_eq!(
+            enabled.dev_mode_override(&unformatted_console, no_color),
+            Some((LogFormat::Pretty, false))
+        );
+        assert_eq!(enabled.dev_mode_override(&formatted_console, None), None);
+        assert_eq!(enabled.format(&formatted_console), LogFormat::Json);
+
+        let disabled = global(Some(DevMode::Disabled));
+        assert_eq!(
+            disabled.dev_mode_override(&unformatted_console, None),
+            Some((LogFormat::Compact, false))
+        );
+        assert_eq!(disabled.format(&formatted_console), LogFormat::Json);
+
+        let forced = global(Some(DevMode::Forced));
+        assert_eq!(
+            forced.dev_mode_override(&formatted_console, None),
+            Some((LogFormat::Pretty, true))
+        );
+        assert_eq!(
+            forced.dev_mode_override(&formatted_console, no_color),
+            Some((LogFormat::Pretty, false))
+        );
+
+        // Other appenders keep their own format and colors
+        assert_eq!(forced.dev_mode_override(&file, None), None);
+        assert_eq!(forced.format(&file), LogFormat::Full);
+        assert_eq!(global(None).format(&unformatted_console), LogFormat::Full);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rust_log_must_be_valid_unicode() {
+        use std::os::unix::ffi::OsStringExt;
+
+        assert_eq!(parse_rust_log(None).unwrap(), None);
+        assert_eq!(
+            parse_rust_log(Some("debug".into())).unwrap().as_deref(),
+            Some("debug")
+        );
+        let not_unicode = OsString::from_vec(vec![b'd', 0xff, b'g']);
+        assert!(matches!(
+            parse_rust_log(Some(not_unicode)),
+            Err(LogError::InvalidEnv(VarError::NotUnicode(_)))
+        ));
+    }
+
+    #[test]
+    fn written_configurations_are_parsed_back_identically() {
+        let data_dir = Path::new("/var/lib/app");
+        let file_contents = r#"
+            [log]
+            level = "debug"
+            format = "json"
+
+            [log.appenders.console]
+            kind = "console"
+            target = "stderr"
+            level = "warn"
+
+            [log.appenders.file]
+            kind = "file"
+            path = "logs/app.log"
+            max_size_bytes = 1048576
+
+            [log.appenders.recent]
+            kind = "ring_buffer"
+            capacity = 100
+        "#;
+        let log = Log::parse(file_contents, data_dir).unwrap();
+        assert_eq!(log.global.level, "debug");
+        assert_eq!(log.global.format, LogFormat::Json);
+        let mut with_writer = log.clone();
+        with_writer.configs.appenders.insert(
+            "supplied".to_owned(),
+            AppenderLogConfig::Writer(WriterLogConfig::default()),
+        );
+
+        let written = with_writer.to_toml(data_dir).unwrap();
+        assert!(written.contains("level = \"debug\""), "{written}");
+        assert!(written.contains("path = \"logs/app.log\""), "{written}");
+        assert!(!written.contains("supplied"), "{written}");
+        assert_eq!(Log::parse(&written, data_dir).unwrap(), log);
+    }
+
+    #[test]
+    fn version_1_logfile_shorthands_are_migrated_to_file_appenders() {
+        let data_dir = Path::new("/var/lib/app");
+        let version_1 = r#"
+            [log]
+            logfile = "app.log"
+
+            [log.appenders.console]
+            kind = "console"
+        "#;
+        let log = Log::parse(version_1, data_dir).unwrap();
+        let names: Vec<_> = log.configs.appenders.keys().cloned().collect();
+        assert_eq!(names, ["console", "logfile"]);
+        match &log.configs.appenders["logfile"] {
+            AppenderLogConfig::File(file) => assert_eq!(file.path, data_dir.join("app.log")),
+            appender => panic!("unexpected appender: {appender:?}"),
+        }
+
+        // The shorthand is no longer read from the current version
+        let current = format!("[log]\nversion = {CONFIG_VERSION}\nlogfile = \"app.log\"");
+        let log = Log::parse(&current, data_dir).unwrap();
+        assert!(!log.configs.appenders.contains_key("logfile"));
+
+        let future = format!("[log]\nversion = {}", CONFIG_VERSION + 1);
+        assert!(matches!(
+            Log::parse(&future, data_dir),
+            Err(LogError::UnsupportedConfigVersion { version, supported })
+                if version == CONFIG_VERSION + 1 && supported == CONFIG_VERSION
+        ));
+    }
+}
@@ -0,0 +1,66 @@
+use std::{cell::RefCell, marker::PhantomData, mem};
+
+thread_local! {
+    /// Fields attached to the events of the current thread, in insertion order
+    static CONTEXT: RefCell<Vec<(String, String)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Attach a field to every event emitted by the current thread until the
+/// returned guard is dropped, such as a request identifier
+///
+/// Unlike span fields, context fields are kept across span boundaries. Setting
+/// a key which is already set replaces its value until the guard is dropped,
+/// after which the previous value is restored.
+pub fn set_context(key: impl Into<String>, value: impl ToString) -> ContextGuard {
+    let key = key.into();
+    let value = value.to_string();
+    let previous = CONTEXT.with(|context| {
+        let mut context = context.borrow_mut();
+        match context.iter_mut().find(|(name, _)| *name == key) {
+            Some((_, current)) => Some(mem::replace(current, value)),
+            None => {
+                context.push((key.clone(), value));
+                None
+            }
+        }
+    });
+    ContextGuard {
+        key,
+        previous,
+        _not_send: PhantomData,
+    }
+}
+
+/// Remove a context field when dropped, restoring its previous value if any
+///
+/// The guard cannot be sent to another thread, since the field belongs to the
+/// thread which set it.
+#[must_use = "the context field is removed when the guard is dropped"]
+pub struct ContextGuard {
+    key: String,
+    previous: Option<String>,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        // The thread-local may already be destroyed when the thread exits
+        let _ = CONTEXT.try_with(|context| {
+            let mut context = context.borrow_mut();
+            let Some(index) = context.iter().position(|(name, _)| *name == self.key) else {
+                return;
+            };
+            match self.previous.take() {
+                Some(previous) => context[index].1 = previous,
+                None => {
+                    context.remove(index);
+                }
+            }
+        });
+    }
+}
+
+/// Call a closure with the context fields of the current thread
+pub(crate) fn with_context<T>(f: impl FnOnce(&[(String, String)]) -> T) -> T {
+    CONTEXT.with(|context| f(&context.borrow()))
+}
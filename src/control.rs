@@ -0,0 +1,216 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use tracing::{info, warn};
+use tracing_subscriber::{registry::Registry, subscribe::Subscribe};
+
+use super::log::ReloadHandle;
+
+/// Control endpoint managing the logging through a Unix socket, removed when dropped
+pub struct ControlSocket {
+    path: PathBuf,
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Listen on a Unix socket for commands managing the logging, as an
+/// interactive alternative to reloading on `SIGHUP`
+///
+/// Commands are read one per line, and each one is answered by `OK`, or by
+/// `ERR: <message>` on failure:
+/// - `set-level <appender> <level>` changes the level of an appender,
+/// - `reload` reloads the configuration file,
+/// - `status` writes the table of the active appenders before `OK`,
+/// - `flush` writes the pending lines of every appender.
+///
+/// A stale socket left at `socket_path` by a previous process is replaced.
+pub fn install_control_socket<S>(
+    socket_path: impl Into<PathBuf>,
+    config_path: impl Into<PathBuf>,
+    data_dir: impl Into<PathBuf>,
+    handle: ReloadHandle<S>,
+) -> io::Result<ControlSocket>
+where
+    S: Subscribe<Registry> + Send + Sync + 'static,
+{
+    let path = socket_path.into();
+    let commands = Arc::new(Commands {
+        config_path: config_path.into(),
+        data_dir: data_dir.into(),
+        handle,
+    });
+
+    if fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        fs::remove_file(&path)?;
+    }
+    let listener = UnixListener::bind(&path)?;
+    let stopped = Arc::new(AtomicBool::new(false));
+
+    let thread = thread::spawn({
+        let stopped = stopped.clone();
+        move || {
+            for stream in listener.incoming() {
+                if stopped.load(Ordering::Acquire) {
+                    break;
+                }
+                match stream {
+                    Ok(stream) => {
+                        let commands = commands.clone();
+                        thread::spawn(move || commands.serve(stream));
+                    }
+                    Err(error) => warn!(%error, "Unable to accept a control connection"),
+                }
+            }
+        }
+    });
+
+    Ok(ControlSocket {
+        path,
+        stopped,
+        thread: Some(thread),
+    })
+}
+
+impl ControlSocket {
+    /// Stop listening for commands and remove the socket
+    ///
+    /// Connections which are already open are served until the clients close them.
+    pub fn uninstall(self) {
+        drop(self);
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Release);
+        // Wake the listener up, so that it notices it is stopped
+        let _ = UnixStream::connect(&self.path);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+struct Commands<S> {
+    config_path: PathBuf,
+    data_dir: PathBuf,
+    handle: ReloadHandle<S>,
+}
+
+impl<S> Commands<S>
+where
+    S: Subscribe<Registry> + Send + Sync,
+{
+    /// Answer the commands of a client until it closes the connection
+    fn serve(&self, stream: UnixStream) {
+        let Ok(mut writer) = stream.try_clone() else {
+            return;
+        };
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                return;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = match self.execute(&line) {
+                Ok(output) => format!("{output}OK\n"),
+                Err(error) => format!("ERR: {error}\n"),
+            };
+            if writer.write_all(response.as_bytes()).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Execute a command, returning its output
+    fn execute(&self, command: &str) -> Result<String, String> {
+        let arguments: Vec<_> = command.split_whitespace().collect();
+        match arguments.as_slice() {
+            ["set-level", appender, level] => {
+                self.handle
+                    .set_level(appender, level)
+                    .map_err(|error| error.to_string())?;
+                info!(appender, level, "Changed the level of an appender");
+                Ok(String::new())
+            }
+            ["reload"] => {
+                self.handle
+                    .reload_from_path(&self.config_path, &self.data_dir)
+                    .map_err(|error| error.to_string())?;
+                info!(path = %self.config_path.display(), "Reloaded the logging configuration");
+                Ok(String::new())
+            }
+            ["status"] => self
+                .handle
+                .status()
+                .map(|table| table + "\n")
+                .map_err(|error| error.to_string()),
+            ["flush"] => match self.handle.flush() {
+                Ok(true) => Ok(String::new()),
+                Ok(false) => Err("some appenders were not flushed in time".to_owned()),
+                Err(error) => Err(error.to_string()),
+            },
+            _ => Err(format!("unknown command `{command}`")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{log::init_log_local, platform, testing::temp_dir};
+
+    #[test]
+    fn status_lists_the_active_appenders() {
+        let data_dir = temp_dir("control_status");
+        let file_contents = r#"
+            [log.appenders.captured]
+            kind = "memory"
+            level = "debug"
+        "#;
+        let log_guard = init_log_local(file_contents, &data_dir, platform::no_op()).unwrap();
+        let socket_path = data_dir.join("control.sock");
+        let control_socket = install_control_socket(
+            &socket_path,
+            data_dir.join("log.toml"),
+            &data_dir,
+            log_guard.reload_handle(),
+        )
+        .unwrap();
+
+        let mut stream = UnixStream::connect(&socket_path).unwrap();
+        stream.write_all(b"status\nunknown\n").unwrap();
+        let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+        let mut status = Vec::new();
+        for line in lines.by_ref() {
+            let line = line.unwrap();
+            if line == "OK" {
+                break;
+            }
+            status.push(line);
+        }
+        let status = status.join("\n");
+        assert!(status.contains("captured"), "{status}");
+        assert!(status.contains("debug"), "{status}");
+        let error = lines.next().unwrap().unwrap();
+        assert_eq!(error, "ERR: unknown command `unknown`");
+
+        drop(stream);
+        control_socket.uninstall();
+        assert!(!socket_path.exists());
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    hash::{Hash, Hasher},
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use tracing::{
+    field::{Field, Visit},
+    Event,
+};
+
+/// Last distinct event recorded by an appender
+#[derive(Debug)]
+struct LastEvent {
+    key: u64,
+    first_seen: Instant,
+    repeats: usize,
+}
+
+/// Suppress the events identical to the previous one of an appender within a
+/// window, counting the repeats so that they can be summarized
+///
+/// Events are identical when they have the same level, target and fields,
+/// including their message, regardless of their timestamp and spans.
+#[derive(Debug)]
+pub struct Deduplicator {
+    window: Duration,
+    last: Mutex<Option<LastEvent>>,
+}
+
+impl Deduplicator {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Record an event, returning `None` if it repeats the previous one within
+    /// the window, or else the number of suppressed repeats of the previous one
+    ///
+    /// The repeats of the last event are only summarized by the next event
+    /// which is written, either a different one or the same one once the
+    /// window is closed.
+    pub fn check(&self, event: &Event<'_>) -> Option<usize> {
+        let key = event_key(event);
+        let now = Instant::now();
+        let mut last = self.last.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(last) = last.as_mut() {
+            if last.key == key && now.duration_since(last.first_seen) < self.window {
+                last.repeats += 1;
+                return None;
+            }
+        }
+        let previous = last.replace(LastEvent {
+            key,
+            first_seen: now,
+            repeats: 0,
+        });
+        Some(previous.map_or(0, |previous| previous.repeats))
+    }
+}
+
+/// Hash of the level, target and fields of an event
+fn event_key(event: &Event<'_>) -> u64 {
+    let metadata = event.metadata();
+    let mut hasher = DefaultHasher::new();
+    metadata.level().hash(&mut hasher);
+    metadata.target().hash(&mut hasher);
+    event.record(&mut HashVisitor(&mut hasher));
+    hasher.finish()
+}
+
+struct HashVisitor<'a>(&'a mut DefaultHasher);
+
+impl Visit for HashVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        field.name().hash(self.0);
+        format!("{value:?}").hash(self.0);
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt::{self, Write},
+};
+
+use serde::Serialize;
+
+use super::config::{Log, LogConfig};
+
+/// Maximum number of errors kept for diagnostics
+pub const MAX_RECENT_ERRORS: usize = 16;
+
+/// Errors which occurred while (re)configuring logging, oldest first
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct RecentErrors(VecDeque<String>);
+
+impl RecentErrors {
+    pub fn push(&mut self, error: &dyn Error) {
+        if self.0.len() == MAX_RECENT_ERRORS {
+            self.0.pop_front();
+        }
+        self.0.push_back(error_chain(error));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+}
+
+/// Format an error along with its sources
+pub(crate) fn error_chain(error: &dyn Error) -> String {
+    let mut chain = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        let _ = write!(chain, ": {error}");
+        source = error.source();
+    }
+    chain
+}
+
+/// Summary of an active appender
+#[derive(Debug, Clone, Serialize)]
+pub struct AppenderDiagnostics {
+    pub name: String,
+    pub kind: &'static str,
+    /// Where the appender writes, such as a file path or a network address
+    pub destination: String,
+    /// Level of the appender, its complete filter if it has one, or the global level
+    pub level: String,
+    /// Format of the appender, or the global one if it has none
+    pub format: &'static str,
+}
+
+impl AppenderDiagnostics {
+    /// Summarize the appenders of a configuration
+    pub fn from_config(config: &Log) -> Vec<Self> {
+        config
+            .configs
+            .appenders
+            .iter()
+            .map(|(name, appender)| {
+                let log_config = appender.log_config();
+                AppenderDiagnostics {
+                    name: name.clone(),
+                    kind: appender.kind(),
+                    destination: appender.destination(),
+                    level: log_config
+                        .filter()
+                        .or(log_config.level())
+                        .unwrap_or(&config.global.level)
+                        .to_owned(),
+                    format: config.global.format(log_config).as_str(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// State of the network appenders when logging was shut down, see `LogGuard::shutdown`
+#[derive(Debug, Clone, Serialize)]
+pub struct ShutdownReport {
+    pub appenders: Vec<AppenderShutdown>,
+}
+
+impl ShutdownReport {
+    /// Whether every network appender sent its lines in time
+    pub fn is_drained(&self) -> bool {
+        self.appenders.iter().all(|appender| appender.drained)
+    }
+}
+
+/// State of a network appender when logging was shut down
+#[derive(Debug, Clone, Serialize)]
+pub struct AppenderShutdown {
+    pub appender: String,
+    /// Whether the appender sent its lines before the timeout
+    pub drained: bool,
+    /// Number of lines still queued by the writer of the appender, not counting
+    /// those its worker had not received yet
+    pub queued_lines: usize,
+}
+
+/// Appenders written as a table, one per line
+pub(crate) struct AppenderTable<'a>(pub &'a [AppenderDiagnostics]);
+
+impl fmt::Display for AppenderTable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = |column: fn(&AppenderDiagnostics) -> usize, header: &str| {
+            self.0
+                .iter()
+                .map(column)
+                .chain([header.len()])
+                .max()
+                .unwrap_or_default()
+        };
+        let name_width = width(|appender| appender.name.chars().count(), "NAME");
+        let kind_width = width(|appender| appender.kind.len(), "KIND");
+        let destination_width = width(
+            |appender| appender.destination.chars().count(),
+            "DESTINATION",
+        );
+        let level_width = width(|appender| appender.level.chars().count(), "LEVEL");
+
+        write!(
+            f,
+            "{:name_width$}  {:kind_width$}  {:destination_width$}  {:level_width$}  FORMAT",
+            "NAME", "KIND", "DESTINATION", "LEVEL"
+        )?;
+        for appender in self.0 {
+            write!(
+                f,
+                "\n{:name_width$}  {:kind_width$}  {:destination_width$}  {:level_width$}  {}",
+                appender.name, appender.kind, appender.destination, appender.level, appender.format
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Appenders written on a single line, such as
+/// `stdout (console to stdout, info, full), app (file to /var/log/app.log, debug, json)`
+pub(crate) struct AppenderSummary<'a>(pub &'a [AppenderDiagnostics]);
+
+impl fmt::Display for AppenderSummary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, appender) in self.0.iter().enumerate() {
+            if index > 0 {
+                f.write_str(", ")?;
+            }
+            write!(
+                f,
+                "{} ({} to {}, {}, {})",
+                appender.name, appender.kind, appender.destination, appender.level, appender.format
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Writers backing the active appenders
+#[derive(Debug, Clone, Serialize)]
+pub struct WriterHealth {
+    /// Number of running non-blocking workers
+    pub active_workers: usize,
+}
+
+/// Snapshot of the logging state, meant to be attached to support requests
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticsBundle {
+    pub config: Log,
+    pub appenders: Vec<AppenderDiagnostics>,
+    pub recent_errors: RecentErrors,
+    pub writers: WriterHealth,
+}
+
+impl DiagnosticsBundle {
+    pub fn new(config: &Log, recent_errors: &RecentErrors, active_workers: usize) -> Self {
+        let appenders = AppenderDiagnostics::from_config(config);
+
+        Self {
+            config: config.clone(),
+            appenders,
+            recent_errors: recent_errors.clone(),
+            writers: WriterHealth { active_workers },
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
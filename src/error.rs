@@ -0,0 +1,99 @@
+use std::{env::VarError, io, path::PathBuf};
+
+use thiserror::Error;
+use tracing::dispatch::SetGlobalDefaultError;
+use tracing_subscriber::filter::{LevelParseError, ParseError};
+
+/// Errors returned by the logging initialization and reload functions
+#[derive(Debug, Error)]
+pub enum LogError {
+    #[error("invalid logging configuration file")]
+    ParseConfig(#[from] toml::de::Error),
+    #[error("unable to serialize the logging configuration")]
+    SerializeConfig(#[from] toml::ser::Error),
+    #[error("unable to read the logging configuration file `{}`", path.display())]
+    ReadConfig {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("logging configuration file `{}` includes itself", path.display())]
+    IncludeCycle { path: PathBuf },
+    #[error(
+        "logging configuration version {version} is not supported, the latest being \
+         {supported}: upgrade the application to use it"
+    )]
+    UnsupportedConfigVersion { version: u32, supported: u32 },
+    #[error("unknown logging environment `{environment}`, expected one of: {available}")]
+    UnknownEnvironment {
+        environment: String,
+        available: String,
+    },
+    #[error("invalid logging configuration file `{}`", path.display())]
+    ConfigFile {
+        path: PathBuf,
+        #[source]
+        source: Box<LogError>,
+    },
+    #[error("invalid filter directive `{directive}`")]
+    InvalidFilter {
+        directive: String,
+        #[source]
+        source: ParseError,
+    },
+    #[error("invalid filter directive `{directive}` of appender `{appender}`")]
+    InvalidAppenderFilter {
+        appender: String,
+        directive: String,
+        #[source]
+        source: ParseError,
+    },
+    #[error(
+        "appender `{appender}` has a `filter` along with a level, directives or targets, \
+         which the filter replaces"
+    )]
+    ConflictingFilter { appender: String },
+    #[error(
+        "level `{level}` of appender `{appender}` applies to its targets, and must \
+         therefore be a single level such as `info`"
+    )]
+    TargetsLevel { appender: String, level: String },
+    #[error("invalid target prefix `{target}` of appender `{appender}`")]
+    InvalidTarget { appender: String, target: String },
+    #[error("invalid denied target prefix `{target}`")]
+    InvalidDeniedTarget { target: String },
+    #[error("unknown standard field `{field}` renamed by appender `{appender}`")]
+    UnknownFieldName { appender: String, field: String },
+    #[error("invalid maximum level `{level}`")]
+    InvalidMaxLevel {
+        level: String,
+        #[source]
+        source: LevelParseError,
+    },
+    #[error("unknown appender `{appender}`")]
+    UnknownAppender { appender: String },
+    #[error("no appender is tagged `{tag}`")]
+    UnknownTag { tag: String },
+    #[error("appender `{appender}` already exists")]
+    DuplicateAppender { appender: String },
+    #[error("cannot add appender `{appender}`, the maximum of {max} appenders being reached")]
+    TooManyAppenders { appender: String, max: usize },
+    #[error("unknown profile `{profile}`")]
+    MissingProfile { profile: String },
+    #[error("profile `{profile}` references itself")]
+    ProfileCycle { profile: String },
+    #[error("directory `{}` of appender `{appender}` is not writable", path.display())]
+    UnwritableDirectory { appender: String, path: PathBuf },
+    #[error("unable to open appender")]
+    OpenAppender(#[from] io::Error),
+    #[error("invalid `RUST_LOG` environment variable")]
+    InvalidEnv(#[from] VarError),
+    #[error("logging was stopped, the guard being dropped")]
+    LoggingStopped,
+    #[error("logging is already initialized")]
+    AlreadyInitialized,
+    #[error("unable to set the global dispatcher")]
+    SetGlobalDispatch(#[from] SetGlobalDefaultError),
+}
+
+pub type LogResult<T> = Result<T, LogError>;
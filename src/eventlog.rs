@@ -0,0 +1,171 @@
+use std::{
+    env,
+    ffi::OsStr,
+    fmt::{self, Write},
+    io, iter,
+    os::windows::ffi::OsStrExt,
+    ptr,
+};
+
+use tracing::{
+    field::{Field, Visit},
+    Collect, Event, Level,
+};
+use tracing_subscriber::subscribe::{Context, Subscribe};
+use windows_sys::Win32::{
+    Foundation::HANDLE,
+    System::EventLog::{
+        DeregisterEventSource, RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE,
+        EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE,
+    },
+};
+
+/// Identifier of the reported events, since no message file describes them
+const EVENT_ID: u32 = 0;
+
+/// Map a tracing level to an event type, the Event Log having no verbose ones
+fn event_type(level: &Level) -> u16 {
+    match *level {
+        Level::ERROR => EVENTLOG_ERROR_TYPE,
+        Level::WARN => EVENTLOG_WARNING_TYPE,
+        Level::INFO | Level::DEBUG | Level::TRACE => EVENTLOG_INFORMATION_TYPE,
+    }
+}
+
+/// Null-terminated UTF-16 string, as expected by the Windows API
+fn wide(value: &str) -> Vec<u16> {
+    OsStr::new(value)
+        .encode_wide()
+        .chain(iter::once(0))
+        .collect()
+}
+
+/// Subscriber reporting events to the Windows Event Log
+///
+/// The event source does not need to be registered: Event Viewer then notes
+/// that the description of the events is missing, and still shows their text.
+/// Registering the source is left to the installer of the service.
+pub struct EventLogSubscriber {
+    handle: HANDLE,
+}
+
+impl EventLogSubscriber {
+    /// Open the event source, which defaults to the process name
+    pub fn new(source: Option<&str>) -> io::Result<Self> {
+        let source = source.map_or_else(
+            || {
+                env::current_exe()
+                    .ok()
+                    .and_then(|path| {
+                        path.file_stem()
+                            .map(|name| name.to_string_lossy().into_owned())
+                    })
+                    .unwrap_or_else(|| "Application".to_owned())
+            },
+            str::to_owned,
+        );
+        let source = wide(&source);
+
+        // SAFETY: the source name is null-terminated, and the local computer is used
+        let handle = unsafe { RegisterEventSourceW(ptr::null(), source.as_ptr()) };
+        if handle == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { handle })
+    }
+}
+
+impl Drop for EventLogSubscriber {
+    fn drop(&mut self) {
+        // SAFETY: the handle was returned by `RegisterEventSourceW` and is not used afterwards
+        unsafe { DeregisterEventSource(self.handle) };
+    }
+}
+
+impl<C: Collect> Subscribe<C> for EventLogSubscriber {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, C>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+
+        let metadata = event.metadata();
+        let text = wide(&format!(
+            "{}: {}{}",
+            metadata.target(),
+            visitor.message,
+            visitor.fields
+        ));
+        let strings = [text.as_ptr()];
+
+        // SAFETY: the handle is open, and the single string is null-terminated
+        // and outlives the call. Failures are ignored, as for other appenders.
+        unsafe {
+            ReportEventW(
+                self.handle,
+                event_type(metadata.level()),
+                0,
+                EVENT_ID,
+                ptr::null_mut(),
+                strings.len() as u16,
+                0,
+                strings.as_ptr(),
+                ptr::null(),
+            )
+        };
+    }
+}
+
+/// Render the message of an event followed by its fields, as `key=value` pairs
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_owned();
+        } else {
+            self.record_debug(field, &value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" => self.message = format!("{value:?}"),
+            name if name.starts_with("log.") => {}
+            name => {
+                let _ = write!(self.fields, " {name}={value:?}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{dispatch, error, info, warn};
+    use tracing_subscriber::subscribe::CollectExt;
+
+    use super::*;
+
+    #[test]
+    fn levels_are_mapped_to_event_types() {
+        assert_eq!(event_type(&Level::ERROR), EVENTLOG_ERROR_TYPE);
+        assert_eq!(event_type(&Level::WARN), EVENTLOG_WARNING_TYPE);
+        for level in [Level::INFO, Level::DEBUG, Level::TRACE] {
+            assert_eq!(event_type(&level), EVENTLOG_INFORMATION_TYPE);
+        }
+        assert_eq!(wide("ok"), [u16::from(b'o'), u16::from(b'k'), 0]);
+    }
+
+    #[test]
+    fn events_are_reported_to_an_unregistered_source() {
+        let subscriber = EventLogSubscriber::new(Some("tracing-reload-example-test")).unwrap();
+        let collector = tracing_subscriber::registry().with(subscriber);
+        dispatch::with_default(&collector.into(), || {
+            error!(code = 3, "reported as an error");
+            warn!("reported as a warning");
+            info!("reported as information");
+        });
+    }
+}
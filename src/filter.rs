@@ -0,0 +1,396 @@
+use std::{
+    cell::Cell,
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use arc_swap::ArcSwap;
+use tracing::{
+    callsite,
+    collect::Interest,
+    field::{Field, Visit},
+    level_filters::LevelFilter,
+    span::{self, Attributes, Id, Record},
+    Event, Level, Metadata,
+};
+use tracing_subscriber::{
+    filter::EnvFilter,
+    subscribe::{Context, Filter},
+};
+
+use super::{
+    config::{RequiredField, SampleConfig},
+    rate_limit::{RateLimiter, SUPPRESSED_EVENTS_REPORT_INTERVAL},
+};
+
+/// `EnvFilter` of an appender, which can be replaced without rebuilding the
+/// subscriber of the appender
+#[derive(Debug, Clone)]
+pub struct EnvFilterCell(Arc<ArcSwap<EnvFilter>>);
+
+impl EnvFilterCell {
+    fn new(env_filter: EnvFilter) -> Self {
+        Self(Arc::new(ArcSwap::from_pointee(env_filter)))
+    }
+}
+
+/// Replace the `EnvFilter` of several appenders, rebuilding the interest and
+/// filter caches once every filter is replaced
+///
+/// Spans which were entered before are still tracked, but the field values
+/// matched by the previous filters are forgotten.
+pub fn reload_env_filters(reloads: impl IntoIterator<Item = (EnvFilterCell, EnvFilter)>) {
+    for (cell, env_filter) in reloads {
+        cell.0.store(env_filter.into());
+    }
+    callsite::rebuild_interest_cache();
+    span::rebuild_filter_cache();
+}
+
+thread_local! {
+    /// State of the generator of the sampling decisions, seeded randomly for
+    /// each thread so that threads do not contend on a shared generator
+    static SAMPLING_RNG: Cell<u64> = Cell::new(RandomState::new().build_hasher().finish() | 1);
+}
+
+/// Next state of a xorshift64* generator, whose state is never zero
+fn next_state(mut x: u64) -> u64 {
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    x
+}
+
+/// Random number in `[0, 1)` from a state of the generator
+fn unit_random(x: u64) -> f64 {
+    // The 53 high bits fill the mantissa of a double
+    (x.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Random number in `[0, 1)`, from the generator of the current thread
+fn sampling_random() -> f64 {
+    SAMPLING_RNG.with(|state| {
+        let x = next_state(state.get());
+        state.set(x);
+        unit_random(x)
+    })
+}
+
+/// Probabilistic sampling of the events less severe than a level
+#[derive(Debug)]
+struct Sampling {
+    rate: f64,
+    always_keep: Level,
+    /// State of the generator when seeded, shared by the threads so that the
+    /// decisions only depend on the order of the events
+    seeded_rng: Option<AtomicU64>,
+}
+
+impl Sampling {
+    fn new(sample: &SampleConfig) -> Self {
+        Self {
+            rate: sample.rate,
+            always_keep: sample.always_keep_level(),
+            // Spread the seed over the bits of the state, which must not be zero
+            seeded_rng: sample
+                .seed
+                .map(|seed| AtomicU64::new(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)),
+        }
+    }
+
+    fn keeps(&self, level: &Level) -> bool {
+        *level <= self.always_keep || self.random() < self.rate
+    }
+
+    fn random(&self) -> f64 {
+        let Some(state) = &self.seeded_rng else {
+            return sampling_random();
+        };
+        let previous = state
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| {
+                Some(next_state(x))
+            })
+            .unwrap_or_else(|x| x);
+        unit_random(next_state(previous))
+    }
+}
+
+/// Per-appender filter, adding event-level checks on top of an `EnvFilter`
+#[derive(Debug)]
+pub struct AppenderFilter {
+    env_filter: EnvFilterCell,
+    /// Minimum number of fields (excluding the message) of an event
+    min_fields: Option<usize>,
+    /// Field which events must have
+    required_field: Option<RequiredField>,
+    /// Prefix of the names of the threads whose events are recorded
+    thread_name_prefix: Option<String>,
+    /// Exact levels of the events, checked after the `EnvFilter`
+    levels: Option<Vec<Level>>,
+    /// Most verbose level, checked before the `EnvFilter`
+    max_level: LevelFilter,
+    /// Least verbose level of events, the more severe ones being handled elsewhere
+    min_level: Option<Level>,
+    /// Checked before the rate limit, so that dropped events do not consume its budget
+    sampling: Option<Sampling>,
+    /// Checked last, so that only the events which would be logged consume the budget
+    rate_limiter: Option<RateLimiter>,
+}
+
+impl AppenderFilter {
+    pub fn new(env_filter: EnvFilter) -> Self {
+        Self {
+            env_filter: EnvFilterCell::new(env_filter),
+            min_fields: None,
+            required_field: None,
+            thread_name_prefix: None,
+            levels: None,
+            max_level: LevelFilter::TRACE,
+            min_level: None,
+            sampling: None,
+            rate_limiter: None,
+        }
+    }
+
+    pub fn with_min_fields(self, min_fields: Option<usize>) -> Self {
+        Self { min_fields, ..self }
+    }
+
+    pub fn with_required_field(self, required_field: Option<RequiredField>) -> Self {
+        Self {
+            required_field,
+            ..self
+        }
+    }
+
+    pub fn with_thread_name_prefix(self, thread_name_prefix: Option<String>) -> Self {
+        Self {
+            thread_name_prefix,
+            ..self
+        }
+    }
+
+    pub fn with_levels(self, levels: Option<Vec<Level>>) -> Self {
+        Self { levels, ..self }
+    }
+
+    pub fn with_max_level(self, max_level: LevelFilter) -> Self {
+        Self { max_level, ..self }
+    }
+
+    pub fn with_min_level(self, min_level: Option<Level>) -> Self {
+        Self { min_level, ..self }
+    }
+
+    pub fn with_sample(self, sample: Option<&SampleConfig>) -> Self {
+        let sampling = sample.map(Sampling::new);
+        Self { sampling, ..self }
+    }
+
+    pub fn with_max_per_second(self, max_per_second: Option<u32>) -> Self {
+        let rate_limiter =
+            max_per_second.map(|max| RateLimiter::new(max, SUPPRESSED_EVENTS_REPORT_INTERVAL));
+        Self {
+            rate_limiter,
+            ..self
+        }
+    }
+
+    /// Handle replacing the `EnvFilter`, shared with the split streams of an appender
+    pub fn env_filter_cell(&self) -> EnvFilterCell {
+        self.env_filter.clone()
+    }
+
+    fn env_filter(&self) -> arc_swap::Guard<Arc<EnvFilter>> {
+        self.env_filter.0.load()
+    }
+
+    /// Spans are kept whatever their level, to give their context to the events
+    fn is_verbose_enough(&self, meta: &Metadata<'_>) -> bool {
+        match self.min_level {
+            Some(min_level) if meta.is_event() => *meta.level() >= min_level,
+            _ => true,
+        }
+    }
+
+    /// Spans are kept whatever their level, as with the minimum level
+    fn has_listed_level(&self, meta: &Metadata<'_>) -> bool {
+        match &self.levels {
+            Some(levels) if meta.is_event() => levels.contains(meta.level()),
+            _ => true,
+        }
+    }
+
+    /// Spans are kept whatever their thread, since events of matching threads
+    /// may happen within spans entered elsewhere
+    fn is_on_matching_thread(&self, meta: &Metadata<'_>) -> bool {
+        match &self.thread_name_prefix {
+            Some(prefix) if meta.is_event() => thread::current()
+                .name()
+                .is_some_and(|name| name.starts_with(prefix.as_str())),
+            _ => true,
+        }
+    }
+
+    fn has_enough_fields(&self, event: &Event<'_>) -> bool {
+        let Some(min_fields) = self.min_fields else {
+            return true;
+        };
+
+        let fields = event
+            .fields()
+            .filter(|field| field.name() != "message" && !field.name().starts_with("log."))
+            .count();
+
+        fields >= min_fields
+    }
+
+    fn has_required_field(&self, event: &Event<'_>) -> bool {
+        let Some(required_field) = &self.required_field else {
+            return true;
+        };
+        // Events without the field are rejected without visiting them
+        if event
+            .fields()
+            .all(|field| field.name() != required_field.name)
+        {
+            return false;
+        }
+
+        let mut visitor = RequiredFieldVisitor {
+            required_field,
+            found: false,
+        };
+        event.record(&mut visitor);
+        visitor.found
+    }
+}
+
+/// Look for a field of an event, and check its value if required
+struct RequiredFieldVisitor<'a> {
+    required_field: &'a RequiredField,
+    found: bool,
+}
+
+impl RequiredFieldVisitor<'_> {
+    fn check(&mut self, field: &Field, value: &dyn fmt::Display) {
+        if field.name() == self.required_field.name {
+            self.found = match &self.required_field.value {
+                Some(expected) => value.to_string() == *expected,
+                None => true,
+            };
+        }
+    }
+}
+
+impl Visit for RequiredFieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.check(field, &value);
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.check(field, &format_args!("{value:?}"));
+    }
+}
+
+impl<C> Filter<C> for AppenderFilter
+where
+    EnvFilter: Filter<C>,
+{
+    fn enabled(&self, meta: &Metadata<'_>, cx: &Context<'_, C>) -> bool {
+        self.max_level >= *meta.level()
+            && self.is_verbose_enough(meta)
+            && self.is_on_matching_thread(meta)
+            && Filter::<C>::enabled(&**self.env_filter(), meta, cx)
+            && self.has_listed_level(meta)
+    }
+
+    fn callsite_enabled(&self, meta: &'static Metadata<'static>) -> Interest {
+        if self.max_level < *meta.level()
+            || !self.is_verbose_enough(meta)
+            || !self.has_listed_level(meta)
+        {
+            return Interest::never();
+        }
+        // The thread of an event is only known when it happens
+        if self.thread_name_prefix.is_some() && meta.is_event() {
+            return Interest::sometimes();
+        }
+        Filter::<C>::callsite_enabled(&**self.env_filter(), meta)
+    }
+
+    fn event_enabled(&self, event: &Event<'_>, cx: &Context<'_, C>) -> bool {
+        self.has_enough_fields(event)
+            && self.has_required_field(event)
+            && Filter::<C>::event_enabled(&**self.env_filter(), event, cx)
+            && self
+                .sampling
+                .as_ref()
+                .map_or(true, |sampling| sampling.keeps(event.metadata().level()))
+            && self
+                .rate_limiter
+                .as_ref()
+                .map_or(true, |rate_limiter| rate_limiter.allow(event.metadata()))
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        let hint = Filter::<C>::max_level_hint(&**self.env_filter());
+        Some(hint.map_or(self.max_level, |hint| hint.min(self.max_level)))
+    }
+
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, C>) {
+        Filter::<C>::on_new_span(&**self.env_filter(), attrs, id, ctx)
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, C>) {
+        Filter::<C>::on_record(&**self.env_filter(), id, values, ctx)
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, C>) {
+        Filter::<C>::on_enter(&**self.env_filter(), id, ctx)
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, C>) {
+        Filter::<C>::on_exit(&**self.env_filter(), id, ctx)
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, C>) {
+        Filter::<C>::on_close(&**self.env_filter(), id, ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_sampling_keeps_the_severe_events_and_decimates_the_others() {
+        let sample = SampleConfig {
+            rate: 0.1,
+            always_keep: Some("warn".to_owned()),
+            seed: Some(42),
+        };
+        let sampling = Sampling::new(&sample);
+
+        let kept = |level| (0..10_000).filter(|_| sampling.keeps(&level)).count();
+        assert_eq!(kept(Level::ERROR), 10_000);
+        assert_eq!(kept(Level::WARN), 10_000);
+        let info = kept(Level::INFO);
+        assert!((800..1200).contains(&info), "{info}");
+
+        // The same seed makes the same decisions
+        let decisions = |sampling: &Sampling| -> Vec<_> {
+            (0..100).map(|_| sampling.keeps(&Level::DEBUG)).collect()
+        };
+        assert_eq!(
+            decisions(&Sampling::new(&sample)),
+            decisions(&Sampling::new(&sample))
+        );
+    }
+}
@@ -0,0 +1,1362 @@
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    fmt::{self, Write},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{Local, SecondsFormat, Utc};
+use indexmap::IndexMap;
+use serde_json::{Map, Value};
+
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Collect, Event, Level,
+};
+use tracing_subscriber::{
+    field::{RecordFields, VisitOutput},
+    fmt::{
+        format::{Compact, DefaultFields, DefaultVisitor, Format, Full, Pretty, Writer},
+        time::{self, FormatTime},
+        FmtContext, FormatEvent, FormatFields,
+    },
+    registry::LookupSpan,
+    subscribe::{Context, Subscribe},
+};
+
+#[cfg(feature = "webhook")]
+use super::webhook::WebhookFormat;
+use super::{
+    config::{
+        AnsiStyle, DisplayOptions, LogFormat, ReservedFieldPolicy, TimestampConfig,
+        TimestampFormat, Timezone,
+    },
+    context,
+    dedup::Deduplicator,
+    gelf::{syslog_severity, GelfFormat},
+    syslog::SyslogFormat,
+};
+
+/// Keys written by the formatters, which event fields must not override
+pub const RESERVED_FIELDS: &[&str] = &["timestamp", "level", "target", "version", "spans"];
+/// Standard fields of JSON events, which `field_names` may rename
+pub const RENAMEABLE_FIELDS: &[&str] = &[
+    "timestamp",
+    "level",
+    "target",
+    "message",
+    "version",
+    "spans",
+];
+/// Value written in place of the redacted fields
+pub const REDACTED_VALUE: &str = "***";
+/// Appended to the truncated messages
+pub const TRUNCATION_MARKER: &str = "…";
+
+thread_local! {
+    /// Whether the fields currently being formatted belong to an event rather than a span
+    static FORMATTING_EVENT: Cell<bool> = const { Cell::new(false) };
+    /// OpenTelemetry identifiers of the event being formatted, if enabled and available
+    static EVENT_OTEL_IDS: Cell<Option<OtelIds>> = const { Cell::new(None) };
+    /// Fields of the spans of the event being formatted, if flattened
+    static EVENT_SPAN_FIELDS: RefCell<Vec<SpanField>> = const { RefCell::new(Vec::new()) };
+}
+
+/// OpenTelemetry identifiers of a span, stored in its extensions by the
+/// platform subscriber bridging `tracing` spans to OpenTelemetry
+///
+/// Events are stamped with the identifiers of their closest span having some,
+/// by the appenders enabling `otel_ids`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct OtelIds {
+    pub trace_id: u128,
+    pub span_id: u64,
+}
+
+impl OtelIds {
+    /// All-zero identifiers are invalid, as with an unsampled context
+    fn is_valid(&self) -> bool {
+        self.trace_id != 0 && self.span_id != 0
+    }
+}
+
+/// Identifiers of the closest span of an event which has valid ones
+fn event_otel_ids<C>(ctx: &FmtContext<'_, C, AppenderFields>) -> Option<OtelIds>
+where
+    C: Collect + for<'a> LookupSpan<'a>,
+{
+    ctx.event_scope()?.find_map(|span| {
+        let ids = span.extensions().get::<OtelIds>().copied()?;
+        ids.is_valid().then_some(ids)
+    })
+}
+
+/// Field of a span, rendered for both the text and JSON formats
+#[derive(Debug, Clone)]
+struct SpanField {
+    name: String,
+    text: String,
+    json: Value,
+}
+
+/// Fields of a span, stored in its extensions by [`SpanFieldsRecorder`]
+#[derive(Debug, Default)]
+struct SpanFields(Vec<SpanField>);
+
+impl Visit for SpanFields {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.set(field, value.to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.set(field, value.to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.set(field, value.to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.set(field, value.to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.set(field, format!("{value:?}"), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let text = format!("{value:?}");
+        self.set(field, text.clone(), text.into());
+    }
+}
+
+impl SpanFields {
+    /// Record a value, replacing the previous one so that recording is idempotent
+    fn set(&mut self, field: &Field, text: String, json: Value) {
+        if field.name().starts_with("log.") {
+            return;
+        }
+        match self
+            .0
+            .iter_mut()
+            .find(|span_field| span_field.name == field.name())
+        {
+            Some(span_field) => {
+                span_field.text = text;
+                span_field.json = json;
+            }
+            None => self.0.push(SpanField {
+                name: field.name().to_owned(),
+                text,
+                json,
+            }),
+        }
+    }
+}
+
+/// Subscriber recording the values of span fields, for the appenders
+/// flattening spans
+///
+/// The values are shared by the appenders, the first one recording them.
+#[derive(Debug, Clone, Copy)]
+pub struct SpanFieldsRecorder {
+    enabled: bool,
+}
+
+impl SpanFieldsRecorder {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+}
+
+impl<C> Subscribe<C> for SpanFieldsRecorder
+where
+    C: Collect + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, C>) {
+        let Some(span) = ctx.span(id).filter(|_| self.enabled) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if extensions.get_mut::<SpanFields>().is_none() {
+            let mut fields = SpanFields::default();
+            attrs.record(&mut fields);
+            extensions.insert(fields);
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, C>) {
+        let Some(span) = ctx.span(id).filter(|_| self.enabled) else {
+            return;
+        };
+        if let Some(fields) = span.extensions_mut().get_mut::<SpanFields>() {
+            values.record(fields);
+        }
+    }
+}
+
+/// Fields of the spans of an event, from the root, those colliding with the
+/// event fields or with the fields of an outer span being prefixed by the span name
+fn event_span_fields<C>(
+    ctx: &FmtContext<'_, C, AppenderFields>,
+    event: &Event<'_>,
+) -> Vec<SpanField>
+where
+    C: Collect + for<'a> LookupSpan<'a>,
+{
+    let fields = ctx.field_format();
+    let mut flattened: Vec<SpanField> = Vec::new();
+    for span in ctx
+        .event_scope()
+        .into_iter()
+        .flat_map(|scope| scope.from_root())
+    {
+        let extensions = span.extensions();
+        let Some(span_fields) = extensions.get::<SpanFields>() else {
+            continue;
+        };
+        for span_field in &span_fields.0 {
+            let mut span_field = span_field.clone();
+            if fields.redact_fields.contains(&span_field.name) {
+                span_field.text = REDACTED_VALUE.to_owned();
+                span_field.json = REDACTED_VALUE.into();
+            }
+            let name = span_field.name.as_str();
+            if event.metadata().fields().field(name).is_some()
+                || flattened.iter().any(|flattened| flattened.name == name)
+            {
+                span_field.name = format!("{}.{name}", span.name());
+            }
+            flattened.push(span_field);
+        }
+    }
+    flattened
+}
+
+#[derive(Debug)]
+pub enum EventFormat {
+    Full(Format<Full, Timer>),
+    Pretty(Format<Pretty, Timer>),
+    Compact(Format<Compact, Timer>),
+    ColoredFull(LevelColorFormat<Full>),
+    ColoredCompact(LevelColorFormat<Compact>),
+    System(Format<Compact, ()>),
+    Json(JsonFormat),
+    Gelf(GelfFormat),
+    Syslog(SyslogFormat),
+    #[cfg(feature = "webhook")]
+    Webhook(WebhookFormat),
+}
+
+impl EventFormat {
+    pub fn new(format: LogFormat, timer: Timer) -> Self {
+        let base = Format::default().with_timer(timer.clone());
+        match format {
+            LogFormat::Full => Self::Full(base),
+            LogFormat::Pretty => Self::Pretty(base.pretty()),
+            LogFormat::Compact => Self::Compact(base.compact()),
+            // Timestamps are added by the system logger, and priorities are
+            // given as a prefix
+            LogFormat::System => Self::System(base.compact().without_time()),
+            // Lines are capped through the fields of the appender
+            LogFormat::Json | LogFormat::Ndjson => Self::Json(JsonFormat::new(timer)),
+        }
+    }
+
+    /// Color the level labels with a custom palette, for the `full` and
+    /// `compact` formats, the other ones keeping the default palette
+    pub fn with_level_colors(
+        format: LogFormat,
+        timer: Timer,
+        level_colors: &IndexMap<String, AnsiStyle>,
+    ) -> Self {
+        let base = Format::default().without_time().with_level(false);
+        match format {
+            LogFormat::Full => {
+                Self::ColoredFull(LevelColorFormat::new(timer, Some(level_colors), base))
+            }
+            LogFormat::Compact => Self::ColoredCompact(LevelColorFormat::new(
+                timer,
+                Some(level_colors),
+                base.compact(),
+            )),
+            format => Self::new(format, timer),
+        }
+    }
+
+    /// Pad the targets of the `full` and `compact` formats so that messages
+    /// are aligned, coloring the level labels with a palette if given
+    pub fn aligned(
+        format: LogFormat,
+        timer: Timer,
+        level_colors: Option<&IndexMap<String, AnsiStyle>>,
+    ) -> Self {
+        let base = Format::default().without_time().with_level(false);
+        match format {
+            LogFormat::Full => {
+                Self::ColoredFull(LevelColorFormat::new(timer, level_colors, base).aligned())
+            }
+            LogFormat::Compact => Self::ColoredCompact(
+                LevelColorFormat::new(timer, level_colors, base.compact()).aligned(),
+            ),
+            format => Self::new(format, timer),
+        }
+    }
+
+    /// Write the summary of the suppressed repeats of the previous event
+    fn write_repeats(
+        &self,
+        fields: &AppenderFields,
+        mut writer: Writer<'_>,
+        repeats: usize,
+    ) -> fmt::Result {
+        let message = format!("... repeated {repeats} times");
+        match self {
+            Self::Json(_) => {
+                let mut object = Map::new();
+                object.insert(fields.field_name("message").into(), message.into());
+                object.insert("repeated".into(), repeats.into());
+                writeln!(writer, "{}", Value::Object(object))
+            }
+            _ => writeln!(writer, "{message}"),
+        }
+    }
+
+    /// Whether the lines of the events are plain text, which a prefix can start
+    pub fn is_text(&self) -> bool {
+        matches!(
+            self,
+            Self::Full(_)
+                | Self::Pretty(_)
+                | Self::Compact(_)
+                | Self::ColoredFull(_)
+                | Self::ColoredCompact(_)
+        )
+    }
+
+    /// Show or hide parts of the events, for the formats based on `Format`
+    pub fn with_display(self, display: DisplayOptions) -> Self {
+        match self {
+            Self::Full(format) => Self::Full(with_display(format, display)),
+            Self::Pretty(format) => Self::Pretty(with_display(format, display)),
+            Self::Compact(format) => Self::Compact(with_display(format, display)),
+            Self::ColoredFull(format) => Self::ColoredFull(format.with_display(display)),
+            Self::ColoredCompact(format) => Self::ColoredCompact(format.with_display(display)),
+            Self::System(format) => Self::System(with_display(format, display)),
+            format => format,
+        }
+    }
+}
+
+fn with_display<F, T>(mut format: Format<F, T>, display: DisplayOptions) -> Format<F, T> {
+    if let Some(show_target) = display.show_target {
+        format = format.with_target(show_target);
+    }
+    if let Some(show_thread_id) = display.show_thread_id {
+        format = format.with_thread_ids(show_thread_id);
+    }
+    if let Some(show_thread_name) = display.show_thread_name {
+        format = format.with_thread_names(show_thread_name);
+    }
+    if let Some(show_level) = display.show_level {
+        format = format.with_level(show_level);
+    }
+    if let Some(show_file) = display.show_file.or(display.source_location) {
+        format = format.with_file(show_file);
+    }
+    if let Some(show_line) = display.show_line.or(display.source_location) {
+        format = format.with_line_number(show_line);
+    }
+    format
+}
+
+/// Styles of the level labels of the default palette, from `ERROR` to `TRACE`
+const DEFAULT_LEVEL_STYLES: [&str; 5] =
+    ["\x1b[31m", "\x1b[33m", "\x1b[32m", "\x1b[34m", "\x1b[35m"];
+
+/// Format events as with `Format`, coloring the level labels with a custom
+/// palette, if any, and aligning the targets if required
+///
+/// The timestamp and the level are written before the wrapped format, which
+/// writes neither of them, nor the target when aligning them.
+#[derive(Debug)]
+pub struct LevelColorFormat<F> {
+    timer: Timer,
+    /// Escape sequences of the level labels, from `ERROR` to `TRACE`, no
+    /// escape sequence being written if unset
+    level_styles: Option<[String; 5]>,
+    display_level: bool,
+    display_target: bool,
+    align_targets: bool,
+    /// Width of the widest target written so far, to which the others are padded
+    target_width: AtomicUsize,
+    inner: Format<F, ()>,
+}
+
+impl<F> LevelColorFormat<F> {
+    fn new(
+        timer: Timer,
+        level_colors: Option<&IndexMap<String, AnsiStyle>>,
+        inner: Format<F, ()>,
+    ) -> Self {
+        let level_styles = level_colors.map(|level_colors| {
+            let mut level_styles = DEFAULT_LEVEL_STYLES.map(str::to_owned);
+            let levels = ["error", "warn", "info", "debug", "trace"];
+            for (style, level) in level_styles.iter_mut().zip(levels) {
+                if let Some(color) = level_colors.get(level) {
+                    *style = color.prefix();
+                }
+            }
+            level_styles
+        });
+        Self {
+            timer,
+            level_styles,
+            display_level: true,
+            display_target: true,
+            align_targets: false,
+            target_width: AtomicUsize::new(0),
+            inner,
+        }
+    }
+
+    /// Write the targets before the wrapped format, padded to the same width
+    fn aligned(self) -> Self {
+        Self {
+            align_targets: true,
+            inner: self.inner.with_target(false),
+            ..self
+        }
+    }
+
+    fn with_display(self, display: DisplayOptions) -> Self {
+        let inner_display = DisplayOptions {
+            show_level: None,
+            show_target: display.show_target.filter(|_| !self.align_targets),
+            ..display
+        };
+        Self {
+            display_level: display.show_level.unwrap_or(self.display_level),
+            display_target: display.show_target.unwrap_or(self.display_target),
+            inner: with_display(self.inner, inner_display),
+            ..self
+        }
+    }
+}
+
+impl<C, F> FormatEvent<C, AppenderFields> for LevelColorFormat<F>
+where
+    C: Collect + for<'a> LookupSpan<'a>,
+    Format<F, ()>: FormatEvent<C, AppenderFields>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, C, AppenderFields>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        // Timestamps and targets are dimmed, as with the default palette
+        let (dimmed, reset) = match self.level_styles {
+            Some(_) => ("\x1b[2m", "\x1b[0m"),
+            None => ("", ""),
+        };
+        write!(writer, "{dimmed}")?;
+        self.timer.format_time(&mut writer)?;
+        write!(writer, "{reset} ")?;
+
+        if self.display_level {
+            let level = event.metadata().level();
+            match &self.level_styles {
+                Some(level_styles) => {
+                    let index = match *level {
+                        Level::ERROR => 0,
+                        Level::WARN => 1,
+                        Level::INFO => 2,
+                        Level::DEBUG => 3,
+                        Level::TRACE => 4,
+                    };
+                    let style = &level_styles[index];
+                    write!(writer, "{style}{:>5}{reset} ", level.as_str())?;
+                }
+                None => write!(writer, "{:>5} ", level.as_str())?,
+            }
+        }
+
+        if self.align_targets && self.display_target {
+            // Targets are padded by their number of characters rather than
+            // bytes, so that multi-byte targets are aligned as well
+            let target = event.metadata().target();
+            let width = target.chars().count();
+            let max_width = self
+                .target_width
+                .fetch_max(width, Ordering::Relaxed)
+                .max(width);
+            let padding = max_width - width;
+            write!(writer, "{dimmed}{target}:{reset}{:padding$} ", "")?;
+        }
+        self.inner.format_event(ctx, writer, event)
+    }
+}
+
+/// Timestamp formatter, configured by the `[log.timestamp]` section
+#[derive(Debug, Clone, Default)]
+pub struct Timer {
+    config: TimestampConfig,
+}
+
+impl Timer {
+    pub fn new(config: TimestampConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl FormatTime for Timer {
+    fn format_time(&self, w: &mut Writer<'_>) -> fmt::Result {
+        let since_epoch = || {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+        };
+
+        match (&self.config.format, self.config.timezone) {
+            (TimestampFormat::Rfc3339, Timezone::Utc) => time::SystemTime.format_time(w),
+            (TimestampFormat::Rfc3339, Timezone::Local) => {
+                let now = Local::now().to_rfc3339_opts(SecondsFormat::Micros, false);
+                write!(w, "{now}")
+            }
+            (TimestampFormat::EpochMillis, _) => write!(w, "{}", since_epoch().as_millis()),
+            (TimestampFormat::EpochSeconds, _) => write!(w, "{}", since_epoch().as_secs()),
+            (TimestampFormat::Custom(format), Timezone::Utc) => {
+                write!(w, "{}", Utc::now().format(format))
+            }
+            (TimestampFormat::Custom(format), Timezone::Local) => {
+                write!(w, "{}", Local::now().format(format))
+            }
+        }
+    }
+}
+
+impl<C> FormatEvent<C, AppenderFields> for EventFormat
+where
+    C: Collect + for<'a> LookupSpan<'a>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, C, AppenderFields>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        if let Some(deduplicator) = &ctx.field_format().deduplicator {
+            match deduplicator.check(event) {
+                None => return Ok(()),
+                Some(0) => {}
+                Some(repeats) => {
+                    self.write_repeats(ctx.field_format(), writer.by_ref(), repeats)?
+                }
+            }
+        }
+
+        let previous = FORMATTING_EVENT.with(|formatting| formatting.replace(true));
+        let otel_ids = ctx
+            .field_format()
+            .otel_ids
+            .then(|| event_otel_ids(ctx))
+            .flatten();
+        let previous_otel_ids = EVENT_OTEL_IDS.with(|ids| ids.replace(otel_ids));
+        let span_fields = if ctx.field_format().flatten_spans {
+            event_span_fields(ctx, event)
+        } else {
+            Vec::new()
+        };
+        let previous_span_fields = EVENT_SPAN_FIELDS.with(|fields| fields.replace(span_fields));
+
+        let result = match self {
+            EventFormat::Full(format) => format.format_event(ctx, writer, event),
+            EventFormat::Pretty(format) => format.format_event(ctx, writer, event),
+            EventFormat::Compact(format) => format.format_event(ctx, writer, event),
+            EventFormat::ColoredFull(format) => format.format_event(ctx, writer, event),
+            EventFormat::ColoredCompact(format) => format.format_event(ctx, writer, event),
+            // Priority prefix parsed by journald, see `sd-daemon(3)`
+            EventFormat::System(format) => {
+                let priority = syslog_severity(event.metadata().level());
+                write!(writer, "<{priority}>")
+                    .and_then(|()| format.format_event(ctx, writer, event))
+            }
+            EventFormat::Json(format) => format.format_event(ctx, writer, event),
+            EventFormat::Gelf(format) => format.format_event(ctx, writer, event),
+            EventFormat::Syslog(format) => format.format_event(ctx, writer, event),
+            #[cfg(feature = "webhook")]
+            EventFormat::Webhook(format) => format.format_event(ctx, writer, event),
+        };
+
+        FORMATTING_EVENT.with(|formatting| formatting.set(previous));
+        EVENT_OTEL_IDS.with(|ids| ids.set(previous_otel_ids));
+        EVENT_SPAN_FIELDS.with(|fields| fields.replace(previous_span_fields));
+        result
+    }
+}
+
+/// Name under which an event field is written, `None` if it must be dropped
+fn field_key(name: &str, policy: ReservedFieldPolicy) -> Result<Option<Cow<'_, str>>, fmt::Error> {
+    if !RESERVED_FIELDS.contains(&name) {
+        return Ok(Some(Cow::Borrowed(name)));
+    }
+    reserved_field_key(name, policy)
+}
+
+/// Name under which an event field colliding with a standard field is written
+fn reserved_field_key(
+    name: &str,
+    policy: ReservedFieldPolicy,
+) -> Result<Option<Cow<'_, str>>, fmt::Error> {
+    match policy {
+        ReservedFieldPolicy::Prefix => Ok(Some(Cow::Owned(format!("field.{name}")))),
+        ReservedFieldPolicy::Drop => Ok(None),
+        ReservedFieldPolicy::Error => Err(fmt::Error),
+    }
+}
+
+/// Field formatter of an appender, wrapping the default one
+#[derive(Debug, Default)]
+pub struct AppenderFields {
+    /// Field used as the message of events which have none
+    message_from_field: Option<String>,
+    /// Version added to every event
+    version: Option<String>,
+    reserved_fields: ReservedFieldPolicy,
+    /// Fields of events and spans whose value is hidden
+    redact_fields: Vec<String>,
+    /// Keys of the renamed standard fields of JSON events
+    field_names: IndexMap<String, String>,
+    /// Fields added to every event
+    constant_fields: IndexMap<String, String>,
+    /// Add the context fields of the thread to events
+    include_context: bool,
+    /// Add the OpenTelemetry identifiers of the current span to events
+    otel_ids: bool,
+    /// Size in bytes beyond which messages are truncated
+    max_message_bytes: Option<usize>,
+    /// Size in bytes beyond which the values of fields other than the message are truncated
+    max_field_value_bytes: Option<usize>,
+    /// Size in bytes beyond which the messages of JSON lines are truncated
+    max_line_bytes: Option<usize>,
+    /// Write the fields of the spans of events along with their own fields
+    flatten_spans: bool,
+    /// Suppress the repeats of identical events
+    deduplicator: Option<Deduplicator>,
+}
+
+impl AppenderFields {
+    pub fn new(
+        message_from_field: Option<String>,
+        version: Option<String>,
+        reserved_fields: ReservedFieldPolicy,
+    ) -> Self {
+        Self {
+            message_from_field,
+            version,
+            reserved_fields,
+            redact_fields: Vec::new(),
+            field_names: IndexMap::new(),
+            constant_fields: IndexMap::new(),
+            include_context: true,
+            otel_ids: false,
+            max_message_bytes: None,
+            max_field_value_bytes: None,
+            max_line_bytes: None,
+            flatten_spans: false,
+            deduplicator: None,
+        }
+    }
+
+    pub fn with_redact_fields(self, redact_fields: Vec<String>) -> Self {
+        Self {
+            redact_fields,
+            ..self
+        }
+    }
+
+    pub fn with_field_names(self, field_names: IndexMap<String, String>) -> Self {
+        Self {
+            field_names,
+            ..self
+        }
+    }
+
+    pub fn with_constant_fields(self, constant_fields: IndexMap<String, String>) -> Self {
+        Self {
+            constant_fields,
+            ..self
+        }
+    }
+
+    pub fn with_include_context(self, include_context: bool) -> Self {
+        Self {
+            include_context,
+            ..self
+        }
+    }
+
+    pub fn with_otel_ids(self, otel_ids: bool) -> Self {
+        Self { otel_ids, ..self }
+    }
+
+    pub fn with_max_message_bytes(self, max_message_bytes: Option<usize>) -> Self {
+        Self {
+            max_message_bytes,
+            ..self
+        }
+    }
+
+    pub fn with_max_field_value_bytes(self, max_field_value_bytes: Option<usize>) -> Self {
+        Self {
+            max_field_value_bytes,
+            ..self
+        }
+    }
+
+    pub fn with_max_line_bytes(self, max_line_bytes: Option<usize>) -> Self {
+        Self {
+            max_line_bytes,
+            ..self
+        }
+    }
+
+    pub fn with_flatten_spans(self, flatten_spans: bool) -> Self {
+        Self {
+            flatten_spans,
+            ..self
+        }
+    }
+
+    pub fn with_dedup_window(self, dedup_window: Option<Duration>) -> Self {
+        Self {
+            deduplicator: dedup_window.map(Deduplicator::new),
+            ..self
+        }
+    }
+
+    pub fn flattens_spans(&self) -> bool {
+        self.flatten_spans
+    }
+
+    /// Key under which a standard field is written
+    fn field_name<'a>(&'a self, field: &'a str) -> &'a str {
+        self.field_names.get(field).map_or(field, String::as_str)
+    }
+
+    fn format_event_fields<R: RecordFields>(
+        &self,
+        mut writer: Writer<'_>,
+        fields: R,
+    ) -> fmt::Result {
+        let mut skipped = None;
+        if let Some(message_field) = self.message_from_field.as_deref() {
+            let mut finder =
+                RedactVisitor::new(&self.redact_fields, MessageFinder::new(message_field));
+            fields.record(&mut finder);
+            let finder = finder.inner;
+
+            if let Some(mut message) = finder.synthesized_message() {
+                truncate_message(&mut message, self.max_message_bytes);
+                write!(writer, "{message}")?;
+                skipped = Some(message_field);
+            }
+        }
+
+        let mut visitor = RedactVisitor::new(
+            &self.redact_fields,
+            TruncateVisitor::new(
+                self.max_field_value_bytes,
+                EventFieldsVisitor {
+                    skipped,
+                    max_message_bytes: self.max_message_bytes,
+                    policy: self.reserved_fields,
+                    reserved: Vec::new(),
+                    collision: false,
+                    inner: DefaultVisitor::new(writer.by_ref(), skipped.is_none()),
+                },
+            ),
+        );
+        fields.record(&mut visitor);
+
+        let EventFieldsVisitor {
+            reserved,
+            collision,
+            inner,
+            ..
+        } = visitor.inner.inner;
+        if collision {
+            return Err(fmt::Error);
+        }
+        inner.finish()?;
+
+        // Reserved fields are written last, since the default visitor uses the field names
+        for (key, value) in reserved {
+            write!(writer, " {key}={value}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<'writer> FormatFields<'writer> for AppenderFields {
+    fn format_fields<R: RecordFields>(
+        &self,
+        mut writer: Writer<'writer>,
+        fields: R,
+    ) -> fmt::Result {
+        if !FORMATTING_EVENT.with(Cell::get) {
+            if self.redact_fields.is_empty() && self.max_field_value_bytes.is_none() {
+                return DefaultFields::new().format_fields(writer, fields);
+            }
+            let mut visitor = RedactVisitor::new(
+                &self.redact_fields,
+                TruncateVisitor::new(
+                    self.max_field_value_bytes,
+                    DefaultVisitor::new(writer, true),
+                ),
+            );
+            fields.record(&mut visitor);
+            return visitor.inner.inner.finish();
+        }
+
+        self.format_event_fields(writer.by_ref(), fields)?;
+
+        if let Some(version) = &self.version {
+            write!(writer, " version={version}")?;
+        }
+        for (key, value) in &self.constant_fields {
+            write!(writer, " {key}={value:?}")?;
+        }
+        if self.include_context {
+            context::with_context(|context| {
+                context
+                    .iter()
+                    .try_for_each(|(key, value)| write!(writer, " {key}={value:?}"))
+            })?;
+        }
+        if let Some(ids) = EVENT_OTEL_IDS.with(Cell::get) {
+            write!(
+                writer,
+                " trace_id={:032x} span_id={:016x}",
+                ids.trace_id, ids.span_id
+            )?;
+        }
+        EVENT_SPAN_FIELDS.with(|fields| {
+            fields
+                .borrow()
+                .iter()
+                .try_for_each(|field| write!(writer, " {}={}", field.name, field.text))
+        })?;
+        Ok(())
+    }
+}
+
+/// Look for an explicit message and for the value of the field replacing it
+struct MessageFinder<'a> {
+    message_field: &'a str,
+    has_message: bool,
+    value: Option<String>,
+}
+
+impl<'a> MessageFinder<'a> {
+    fn new(message_field: &'a str) -> Self {
+        Self {
+            message_field,
+            has_message: false,
+            value: None,
+        }
+    }
+
+    fn synthesized_message(self) -> Option<String> {
+        if self.has_message {
+            None
+        } else {
+            self.value
+        }
+    }
+}
+
+impl Visit for MessageFinder<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == self.message_field {
+            self.value = Some(value.to_owned());
+        } else {
+            self.record_debug(field, &value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" => self.has_message = true,
+            name if name == self.message_field => self.value = Some(format!("{value:?}")),
+            _ => {}
+        }
+    }
+}
+
+/// Forward the fields to the inner visitor, replacing the value of the redacted ones
+pub(crate) struct RedactVisitor<'a, V> {
+    redact_fields: &'a [String],
+    inner: V,
+}
+
+impl<'a, V> RedactVisitor<'a, V> {
+    pub(crate) fn new(redact_fields: &'a [String], inner: V) -> Self {
+        Self {
+            redact_fields,
+            inner,
+        }
+    }
+
+    fn is_redacted(&self, field: &Field) -> bool {
+        self.redact_fields.iter().any(|name| name == field.name())
+    }
+}
+
+impl<V: Visit> Visit for RedactVisitor<'_, V> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        if self.is_redacted(field) {
+            self.inner.record_str(field, REDACTED_VALUE);
+        } else {
+            self.inner.record_f64(field, value);
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        if self.is_redacted(field) {
+            self.inner.record_str(field, REDACTED_VALUE);
+        } else {
+            self.inner.record_i64(field, value);
+        }
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if self.is_redacted(field) {
+            self.inner.record_str(field, REDACTED_VALUE);
+        } else {
+            self.inner.record_u64(field, value);
+        }
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        if self.is_redacted(field) {
+            self.inner.record_str(field, REDACTED_VALUE);
+        } else {
+            self.inner.record_bool(field, value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if self.is_redacted(field) {
+            self.inner.record_str(field, REDACTED_VALUE);
+        } else {
+            self.inner.record_str(field, value);
+        }
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        if self.is_redacted(field) {
+            self.inner.record_str(field, REDACTED_VALUE);
+        } else {
+            self.inner.record_error(field, value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if self.is_redacted(field) {
+            self.inner.record_str(field, REDACTED_VALUE);
+        } else {
+            self.inner.record_debug(field, value);
+        }
+    }
+}
+
+/// Forward the fields to the inner visitor, truncating the text values
+/// exceeding a size in bytes, except the message which has its own limit
+struct TruncateVisitor<V> {
+    max_bytes: Option<usize>,
+    inner: V,
+}
+
+impl<V> TruncateVisitor<V> {
+    fn new(max_bytes: Option<usize>, inner: V) -> Self {
+        Self { max_bytes, inner }
+    }
+
+    /// Value of a field truncated, if it exceeds the size
+    fn truncated(&self, field: &Field, value: &dyn fmt::Display) -> Option<String> {
+        let max_bytes = self.max_bytes?;
+        if field.name() == "message" {
+            return None;
+        }
+        let mut value = value.to_string();
+        if value.len() <= max_bytes {
+            return None;
+        }
+        truncate_message(&mut value, Some(max_bytes));
+        Some(value)
+    }
+}
+
+impl<V: Visit> Visit for TruncateVisitor<V> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.inner.record_f64(field, value);
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.inner.record_i64(field, value);
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.inner.record_u64(field, value);
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.inner.record_bool(field, value);
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match self.truncated(field, &value) {
+            Some(value) => self.inner.record_str(field, &value),
+            None => self.inner.record_str(field, value),
+        }
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        match self.truncated(field, &value) {
+            Some(value) => self.inner.record_debug(field, &format_args!("{value}")),
+            None => self.inner.record_error(field, value),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match self.truncated(field, &format_args!("{value:?}")) {
+            Some(value) => self.inner.record_debug(field, &format_args!("{value}")),
+            None => self.inner.record_debug(field, value),
+        }
+    }
+}
+
+/// Forward the fields of an event to the inner visitor, except the one used
+/// as message and the ones named like a reserved key, which are handled
+/// according to the policy
+struct EventFieldsVisitor<'a, V> {
+    skipped: Option<&'a str>,
+    max_message_bytes: Option<usize>,
+    policy: ReservedFieldPolicy,
+    reserved: Vec<(String, String)>,
+    collision: bool,
+    inner: V,
+}
+
+impl<V> EventFieldsVisitor<'_, V> {
+    /// Whether the field must be forwarded to the inner visitor
+    fn forward(&mut self, field: &Field, value: &dyn fmt::Debug) -> bool {
+        let name = field.name();
+        if Some(name) == self.skipped {
+            return false;
+        }
+        match field_key(name, self.policy) {
+            Ok(Some(Cow::Borrowed(_))) => true,
+            Ok(Some(Cow::Owned(key))) => {
+                self.reserved.push((key, format!("{value:?}")));
+                false
+            }
+            Ok(None) => false,
+            Err(_) => {
+                self.collision = true;
+                false
+            }
+        }
+    }
+}
+
+impl<V: Visit> EventFieldsVisitor<'_, V> {
+    /// Forward the message truncated, returning whether the field is the message
+    fn record_truncated_message(&mut self, field: &Field, value: &dyn fmt::Debug) -> bool {
+        if field.name() != "message" || self.max_message_bytes.is_none() {
+            return false;
+        }
+        let mut message = format!("{value:?}");
+        truncate_message(&mut message, self.max_message_bytes);
+        self.inner.record_debug(field, &format_args!("{message}"));
+        true
+    }
+}
+
+impl<V: Visit> Visit for EventFieldsVisitor<'_, V> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if self.forward(field, &value) && !self.record_truncated_message(field, &value) {
+            self.inner.record_str(field, value);
+        }
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        if self.forward(field, &format_args!("{value}")) {
+            self.inner.record_error(field, value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if self.forward(field, value) && !self.record_truncated_message(field, value) {
+            self.inner.record_debug(field, value);
+        }
+    }
+}
+
+/// Truncate a message exceeding a size in bytes at a character boundary,
+/// appending [`TRUNCATION_MARKER`]
+pub(crate) fn truncate_message(message: &mut String, max_bytes: Option<usize>) {
+    let Some(max_bytes) = max_bytes else {
+        return;
+    };
+    if message.len() <= max_bytes {
+        return;
+    }
+    let end = (0..=max_bytes)
+        .rev()
+        .find(|&index| message.is_char_boundary(index))
+        .unwrap_or(0);
+    message.truncate(end);
+    message.push_str(TRUNCATION_MARKER);
+}
+
+/// Format events as JSON objects, one per line
+#[derive(Debug)]
+pub struct JsonFormat {
+    timer: Timer,
+}
+
+impl JsonFormat {
+    pub fn new(timer: Timer) -> Self {
+        Self { timer }
+    }
+}
+
+impl<C> FormatEvent<C, AppenderFields> for JsonFormat
+where
+    C: Collect + for<'a> LookupSpan<'a>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, C, AppenderFields>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let fields = ctx.field_format();
+        let mut visitor = RedactVisitor::new(
+            &fields.redact_fields,
+            TruncateVisitor::new(fields.max_field_value_bytes, JsonVisitor::new(fields)),
+        );
+        event.record(&mut visitor);
+        let mut visitor = visitor.inner.inner;
+        if visitor.collision {
+            return Err(fmt::Error);
+        }
+
+        let mut message = visitor.message.or_else(|| {
+            let field = fields.message_from_field.as_ref()?;
+            visitor.fields.remove(field).map(|value| match value {
+                Value::String(value) => value,
+                value => value.to_string(),
+            })
+        });
+        if let Some(message) = &mut message {
+            truncate_message(message, fields.max_message_bytes);
+        }
+
+        let mut timestamp = String::new();
+        self.timer.format_time(&mut Writer::new(&mut timestamp))?;
+
+        let metadata = event.metadata();
+        let spans: Vec<Value> = ctx
+            .event_scope()
+            .into_iter()
+            .flat_map(|scope| scope.from_root())
+            .map(|span| span.name().into())
+            .collect();
+
+        let mut object = Map::new();
+        let mut insert = |field, value: Value| {
+            object.insert(fields.field_name(field).into(), value);
+        };
+        insert("timestamp", timestamp.into());
+        insert("level", metadata.level().as_str().into());
+        insert("target", metadata.target().into());
+        if let Some(message) = message {
+            insert("message", message.into());
+        }
+        if let Some(version) = &fields.version {
+            insert("version", version.as_str().into());
+        }
+        if !spans.is_empty() {
+            insert("spans", spans.into());
+        }
+        for (key, value) in &fields.constant_fields {
+            object.insert(key.clone(), value.as_str().into());
+        }
+        if fields.include_context {
+            context::with_context(|context| {
+                for (key, value) in context {
+                    object.insert(key.clone(), value.as_str().into());
+                }
+            });
+        }
+        if let Some(ids) = EVENT_OTEL_IDS.with(Cell::get) {
+            object.insert("trace_id".into(), format!("{:032x}", ids.trace_id).into());
+            object.insert("span_id".into(), format!("{:016x}", ids.span_id).into());
+        }
+        EVENT_SPAN_FIELDS.with(|fields| {
+            for field in fields.borrow().iter() {
+                object.insert(field.name.clone(), field.json.clone());
+            }
+        });
+        object.extend(visitor.fields);
+
+        let line = match fields.max_line_bytes {
+            Some(max_line_bytes) => cap_line(object, fields, max_line_bytes)?,
+            None => Value::Object(object).to_string(),
+        };
+        writeln!(writer, "{line}")
+    }
+}
+
+/// Serialize a JSON event, truncating its message so that the line fits in a
+/// size in bytes and setting its `truncated` field if needed
+///
+/// The other fields are kept whole, so that a line may still exceed the size
+/// when they do not fit by themselves.
+fn cap_line(
+    mut object: Map<String, Value>,
+    fields: &AppenderFields,
+    max_line_bytes: usize,
+) -> Result<String, fmt::Error> {
+    let serialize =
+        |object: &Map<String, Value>| serde_json::to_string(object).map_err(|_| fmt::Error);
+    let mut line = serialize(&object)?;
+    let message_key = fields.field_name("message");
+    if line.len() <= max_line_bytes {
+        return Ok(line);
+    }
+    let Some(Value::String(message)) = object.get(message_key).cloned() else {
+        return Ok(line);
+    };
+
+    object.insert(fields.field_name("truncated").into(), true.into());
+    let mut max_message_bytes = message.len();
+    while line.len() > max_line_bytes && max_message_bytes > 0 {
+        // Escaped characters are at least as long as raw ones, so that
+        // removing the excess from the message shortens the line enough,
+        // except for the flag and marker added by the first truncation
+        let excess = line.len() - max_line_bytes;
+        max_message_bytes = max_message_bytes.saturating_sub(excess);
+        let mut truncated = message.clone();
+        truncate_message(&mut truncated, Some(max_message_bytes));
+        object.insert(message_key.into(), truncated.into());
+        line = serialize(&object)?;
+    }
+    Ok(line)
+}
+
+/// Collect the fields of an event as JSON values
+struct JsonVisitor<'a> {
+    policy: ReservedFieldPolicy,
+    /// Keys of the standard fields, which event fields must not override
+    reserved_keys: Vec<&'a str>,
+    message: Option<String>,
+    fields: Map<String, Value>,
+    collision: bool,
+}
+
+impl<'a> JsonVisitor<'a> {
+    fn new(fields: &'a AppenderFields) -> Self {
+        let reserved_keys = RESERVED_FIELDS
+            .iter()
+            .chain(
+                fields
+                    .field_names
+                    .contains_key("message")
+                    .then_some(&"message"),
+            )
+            .map(|field| fields.field_name(field))
+            .collect();
+        Self {
+            policy: fields.reserved_fields,
+            reserved_keys,
+            message: None,
+            fields: Map::new(),
+            collision: false,
+        }
+    }
+
+    fn insert(&mut self, field: &Field, value: Value) {
+        match field.name() {
+            "message" => {
+                self.message = Some(match value {
+                    Value::String(message) => message,
+                    value => value.to_string(),
+                })
+            }
+            name if name.starts_with("log.") => {}
+            name if !self.reserved_keys.contains(&name) => {
+                self.fields.insert(name.to_owned(), value);
+            }
+            name => match reserved_field_key(name, self.policy) {
+                Ok(Some(key)) => {
+                    self.fields.insert(key.into_owned(), value);
+                }
+                Ok(None) => {}
+                Err(_) => self.collision = true,
+            },
+        }
+    }
+}
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, format!("{value:?}").into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_truncated_at_a_character_boundary() {
+        let truncated = |max_bytes| {
+            // The emoji takes the bytes 2 to 5
+            let mut message = "ab😀cd".to_owned();
+            truncate_message(&mut message, Some(max_bytes));
+            message
+        };
+
+        assert_eq!(truncated(8), "ab😀cd");
+        assert_eq!(truncated(6), format!("ab😀{TRUNCATION_MARKER}"));
+        assert_eq!(truncated(5), format!("ab{TRUNCATION_MARKER}"));
+        assert_eq!(truncated(2), format!("ab{TRUNCATION_MARKER}"));
+    }
+}
@@ -0,0 +1,256 @@
+use std::{
+    fmt,
+    io::{self, Write},
+    net::{ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use indexmap::IndexMap;
+use serde_json::{Map, Value};
+use tracing::{
+    field::{Field, Visit},
+    Collect, Event, Level,
+};
+use tracing_subscriber::{
+    fmt::{format::Writer, FmtContext, FormatEvent, FormatFields},
+    registry::LookupSpan,
+};
+
+use super::{context, format::truncate_message};
+
+const GELF_VERSION: &str = "1.1";
+
+/// Map a tracing level to a syslog severity, as used by GELF
+pub fn syslog_severity(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 3,
+        Level::WARN => 4,
+        Level::INFO => 6,
+        Level::DEBUG | Level::TRACE => 7,
+    }
+}
+
+/// Format events as GELF JSON payloads, one per line
+#[derive(Debug)]
+pub struct GelfFormat {
+    host: String,
+    facility: Option<String>,
+    message_from_field: Option<String>,
+    version: Option<String>,
+    /// Fields added to every event, as additional fields
+    constant_fields: IndexMap<String, String>,
+    /// Add the context fields of the thread, as additional fields
+    include_context: bool,
+    /// Size in bytes beyond which `short_message` is truncated
+    max_message_bytes: Option<usize>,
+}
+
+impl GelfFormat {
+    pub fn new(
+        facility: Option<String>,
+        message_from_field: Option<String>,
+        version: Option<String>,
+    ) -> Self {
+        let host = gethostname::gethostname().to_string_lossy().into_owned();
+        Self {
+            host,
+            facility,
+            message_from_field,
+            version,
+            constant_fields: IndexMap::new(),
+            include_context: true,
+            max_message_bytes: None,
+        }
+    }
+
+    pub fn with_constant_fields(self, constant_fields: IndexMap<String, String>) -> Self {
+        Self {
+            constant_fields,
+            ..self
+        }
+    }
+
+    pub fn with_include_context(self, include_context: bool) -> Self {
+        Self {
+            include_context,
+            ..self
+        }
+    }
+
+    pub fn with_max_message_bytes(self, max_message_bytes: Option<usize>) -> Self {
+        Self {
+            max_message_bytes,
+            ..self
+        }
+    }
+}
+
+impl<C, N> FormatEvent<C, N> for GelfFormat
+where
+    C: Collect + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        _ctx: &FmtContext<'_, C, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut visitor = GelfVisitor::default();
+        event.record(&mut visitor);
+
+        let metadata = event.metadata();
+        let mut message = match visitor.message {
+            Some(message) => message,
+            None => self
+                .message_from_field
+                .as_ref()
+                .and_then(|field| visitor.fields.remove(&format!("_{field}")))
+                .map(|value| match value {
+                    Value::String(value) => value,
+                    value => value.to_string(),
+                })
+                .unwrap_or_default(),
+        };
+        truncate_message(&mut message, self.max_message_bytes);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        let mut payload = Map::new();
+        payload.insert("version".into(), GELF_VERSION.into());
+        payload.insert("host".into(), self.host.as_str().into());
+        payload.insert("short_message".into(), message.into());
+        payload.insert("timestamp".into(), timestamp.into());
+        payload.insert("level".into(), syslog_severity(metadata.level()).into());
+        payload.insert("_target".into(), metadata.target().into());
+        if let Some(facility) = &self.facility {
+            payload.insert("_facility".into(), facility.as_str().into());
+        }
+        if let Some(version) = &self.version {
+            payload.insert("_version".into(), version.as_str().into());
+        }
+        for (key, value) in &self.constant_fields {
+            payload.insert(format!("_{key}"), value.as_str().into());
+        }
+        if self.include_context {
+            context::with_context(|context| {
+                for (key, value) in context {
+                    payload.insert(format!("_{key}"), value.as_str().into());
+                }
+            });
+        }
+        payload.extend(visitor.fields);
+
+        writeln!(writer, "{}", Value::Object(payload))
+    }
+}
+
+/// Collect the fields of an event as GELF additional fields
+#[derive(Default)]
+struct GelfVisitor {
+    message: Option<String>,
+    fields: Map<String, Value>,
+}
+
+impl GelfVisitor {
+    fn insert(&mut self, field: &Field, value: Value) {
+        match field.name() {
+            "message" => {
+                self.message = Some(match value {
+                    Value::String(message) => message,
+                    value => value.to_string(),
+                })
+            }
+            // `_id` is reserved by GELF
+            "id" => {
+                self.fields.insert("_id_".into(), value);
+            }
+            name if name.starts_with("log.") => {}
+            name => {
+                self.fields.insert(format!("_{name}"), value);
+            }
+        }
+    }
+}
+
+impl Visit for GelfVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, format!("{value:?}").into());
+    }
+}
+
+/// Writer sending each formatted event as a GELF UDP datagram
+///
+/// Payloads are not chunked, so they should stay below the maximum datagram size.
+pub struct GelfWriter {
+    socket: UdpSocket,
+    /// Number of messages which could not be sent
+    dropped: Arc<AtomicU64>,
+}
+
+impl GelfWriter {
+    pub fn connect(host: &str, port: u16) -> io::Result<Self> {
+        let address = (host, port).to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("cannot resolve {host}"))
+        })?;
+        let bind_address = if address.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+
+        let socket = UdpSocket::bind(bind_address)?;
+        socket.connect(address)?;
+
+        Ok(Self {
+            socket,
+            dropped: Arc::default(),
+        })
+    }
+
+    /// Counter of the messages which could not be sent
+    pub fn dropped_counter(&self) -> Arc<AtomicU64> {
+        self.dropped.clone()
+    }
+}
+
+impl Write for GelfWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let payload = buf.strip_suffix(b"\n").unwrap_or(buf);
+        if self.socket.send(payload).is_err() {
+            // Never fail the non-blocking worker because of the network
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
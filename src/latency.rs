@@ -0,0 +1,105 @@
+use std::{
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use hdrhistogram::Histogram;
+use tracing::{
+    collect::{Collect, Interest},
+    level_filters::LevelFilter,
+    span::{Attributes, Id, Record},
+    Dispatch, Event, Metadata,
+};
+use tracing_subscriber::subscribe::{Context, Subscribe};
+
+/// Durations of the handling of the events recorded by an appender, from their
+/// formatting to their writing in the buffer of the appender, in nanoseconds
+///
+/// Clones share the same histogram.
+#[derive(Debug, Clone)]
+pub struct LatencyHistogram(Arc<Mutex<Histogram<u64>>>);
+
+impl LatencyHistogram {
+    pub(crate) fn new() -> Self {
+        let histogram = Histogram::new(3).expect("3 significant figures are supported");
+        Self(Arc::new(Mutex::new(histogram)))
+    }
+
+    fn record(&self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .saturating_record(nanos);
+    }
+
+    /// Copy of the durations recorded so far
+    pub fn snapshot(&self) -> Histogram<u64> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+/// Subscriber of an appender timing the handling of its events, when profiling
+///
+/// It is wrapped by the filter of the appender, so that only the events which
+/// the appender records are timed.
+pub struct TimedSubscriber<S> {
+    subscriber: S,
+    latency: Option<LatencyHistogram>,
+}
+
+impl<S> TimedSubscriber<S> {
+    pub fn new(subscriber: S, latency: Option<LatencyHistogram>) -> Self {
+        Self {
+            subscriber,
+            latency,
+        }
+    }
+}
+
+macro_rules! impl_subscribe {
+    ($(fn $method:ident(&self $(, $arg_name:ident: $arg_type:ty)*) $(-> $return_type:ty)?;)*) => {
+        $(
+            fn $method(&self $(, $arg_name: $arg_type)*) $(-> $return_type)? {
+                self.subscriber.$method($($arg_name),*)
+            }
+        )*
+    };
+}
+
+impl<S, C> Subscribe<C> for TimedSubscriber<S>
+where
+    S: Subscribe<C>,
+    C: Collect,
+{
+    fn on_subscribe(&mut self, collector: &C) {
+        self.subscriber.on_subscribe(collector);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, C>) {
+        let Some(latency) = &self.latency else {
+            return self.subscriber.on_event(event, ctx);
+        };
+        let start = Instant::now();
+        self.subscriber.on_event(event, ctx);
+        latency.record(start.elapsed());
+    }
+
+    impl_subscribe!(
+        fn on_register_dispatch(&self, collector: &Dispatch);
+        fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, C>);
+        fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest;
+        fn enabled(&self, metadata: &Metadata<'_>, ctx: Context<'_, C>) -> bool;
+        fn max_level_hint(&self) -> Option<LevelFilter>;
+        fn on_record(&self, span: &Id, values: &Record<'_>, ctx: Context<'_, C>);
+        fn on_follows_from(&self, span: &Id, follows: &Id, ctx: Context<'_, C>);
+        fn event_enabled(&self, event: &Event<'_>, ctx: Context<'_, C>) -> bool;
+        fn on_enter(&self, id: &Id, ctx: Context<'_, C>);
+        fn on_exit(&self, id: &Id, ctx: Context<'_, C>);
+        fn on_close(&self, id: Id, ctx: Context<'_, C>);
+        fn on_id_change(&self, old: &Id, new: &Id, ctx: Context<'_, C>);
+    );
+}
@@ -0,0 +1,34 @@
+pub mod buffer;
+pub mod config;
+pub mod context;
+#[cfg(all(unix, feature = "control"))]
+pub mod control;
+pub mod dedup;
+pub mod diagnostics;
+pub mod error;
+#[cfg(windows)]
+pub mod eventlog;
+pub mod filter;
+pub mod format;
+pub mod gelf;
+pub mod latency;
+pub mod log;
+pub mod log_crate;
+pub mod memory;
+pub mod metrics;
+#[cfg(feature = "otlp")]
+pub mod otlp;
+pub mod panic;
+pub mod platform;
+pub mod rate_limit;
+pub mod reload;
+pub mod rotation;
+#[cfg(unix)]
+pub mod signal;
+pub mod span_timing;
+pub mod syslog;
+pub mod tcp;
+pub mod testing;
+#[cfg(feature = "webhook")]
+pub mod webhook;
+pub mod write_error;
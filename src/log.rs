@@ -1,313 +1,5019 @@
 use std::{
-    env::{self, VarError},
-    fmt,
-    fs::File,
-    io,
-    path::Path,
+    collections::{hash_map::Entry, HashMap},
+    env, fmt,
+    fs::{self, File},
+    io::{self, Write},
+    mem,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
     str::FromStr,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex, MutexGuard, PoisonError, Weak,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
-use eyre::Context;
+use hdrhistogram::Histogram;
 use indexmap::IndexMap;
-use tracing::{dispatch, warn, Collect, Dispatch, Event};
-use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+use tracing::{dispatch, info, level_filters::LevelFilter, warn, Collect, Dispatch, Level};
 use tracing_subscriber::{
     filter::{EnvFilter, Filtered},
-    fmt::{
-        format::{Compact, DefaultFields, Format, Full, Pretty, Writer},
-        FmtContext, FormatEvent, FormatFields, Subscriber,
-    },
+    fmt::Subscriber,
     registry::{LookupSpan, Registry},
-    subscribe::{CollectExt, Layered, Subscribe},
+    subscribe::{CollectExt, Filter, Layered, Subscribe},
 };
 
+#[cfg(windows)]
+use super::eventlog::EventLogSubscriber;
+#[cfg(feature = "otlp")]
+use super::otlp::OtlpSubscriber;
 use super::{
+    buffer::{
+        self, BatchConfig, BufferSender, BufferedWriter, DroppedLinesReporter, PrefixedWriter,
+        WorkerGuard, DROPPED_LINES_REPORT_INTERVAL,
+    },
     config::{
-        AppenderLogConfig, ConsoleLogConfig, ConsoleTarget, FileLogConfig, FileWritingMode,
-        GlobalLogConfig, Log, LogConfig, LogConfigs, LogFormat,
+        max_appenders, AppenderLogConfig, AppenderOptions, BufferConfig, BufferMode,
+        ConsoleLogConfig, ConsoleTarget, FileLogConfig, FileWritingMode, GelfLogConfig,
+        GlobalLogConfig, LevelRange, Log, LogConfig, LogConfigs, LogFormat, MemoryLogConfig,
+        RingBufferLogConfig, SyslogLogConfig, SyslogTransport, TcpLogConfig, WriterLogConfig,
+    },
+    diagnostics::{
+        error_chain, AppenderDiagnostics, AppenderShutdown, AppenderSummary, AppenderTable,
+        DiagnosticsBundle, RecentErrors, ShutdownReport,
     },
+    error::{LogError, LogResult},
+    filter::{reload_env_filters, AppenderFilter, EnvFilterCell},
+    format::{AppenderFields, EventFormat, SpanFieldsRecorder, Timer},
+    gelf::{GelfFormat, GelfWriter},
+    latency::{LatencyHistogram, TimedSubscriber},
+    log_crate::LogCrateSubscriber,
+    memory::{ChannelWriter, SharedWriter},
     reload::{ReloadableSubscriber, WithReloadable},
+    rotation::{self, FileOutput, MirrorWriter, SizeRotatingWriter, SyncedFile},
+    syslog::{SyslogFormat, SyslogWriter},
+    tcp::TcpWriter,
+    write_error::{self, WriteErrorReporter},
+};
+#[cfg(feature = "webhook")]
+use super::{
+    config::WebhookLogConfig,
+    webhook::{WebhookFormat, WebhookWriter},
 };
 
 type BaseCollector<S> = Layered<S, Registry>;
 
-type FilteredSubscriber<C> =
-    Filtered<Subscriber<C, DefaultFields, EventFormat, NonBlocking>, EnvFilter, C>;
+type FilteredSubscriber<C> = Filtered<
+    TimedSubscriber<
+        Layered<SpanFieldsRecorder, Subscriber<C, AppenderFields, EventFormat, PrefixedWriter>, C>,
+    >,
+    AppenderFilter,
+    C,
+>;
+
+/// Subscriber of an appender, which may not be a `fmt` subscriber
+type BoxedSubscriber<C> = Box<dyn Subscribe<C> + Send + Sync>;
 
 type SubscriberHandle<S> =
-    ReloadableSubscriber<Vec<FilteredSubscriber<Arc<BaseCollector<S>>>>, BaseCollector<S>>;
+    ReloadableSubscriber<Vec<BoxedSubscriber<Arc<BaseCollector<S>>>>, BaseCollector<S>>;
 
-#[must_use]
-pub struct LogGuard<S> {
-    subscriber_handle: SubscriberHandle<S>,
-    worker_guards: Vec<WorkerGuard>,
+/// Maximum duration of [`LogGuard::flush`]
+pub const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+/// Delay between two attempts of [`LogGuard::shutdown`] to send the queued lines
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Identify a writer, so that it can be shared by appenders and reused across reloads
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum WriterKey {
+    Appender(String),
+    Destination(PathBuf),
+    /// Stderr writer of a console appender splitting its streams
+    ErrorStream(String),
+}
+
+/// Writers used by the subscribers of an appender
+#[derive(Clone, Default)]
+struct Writers {
+    /// Missing for native subscribers
+    writer: Option<BufferSender>,
+    /// Writer of the warnings and errors of a console appender splitting its streams
+    error_writer: Option<BufferSender>,
+}
+
+/// Writer created for an appender, reused on reload while the appender is unchanged
+///
+/// The common options, such as the level or the format, only affect the
+/// subscriber of the appender, so changing them keeps the writer along with
+/// its open file or network connection.
+///
+/// Clones share the worker, which stops once every clone is dropped, so that
+/// the writers can be reused by a reload building its appenders unlocked.
+#[derive(Clone)]
+struct AppenderWriter {
+    /// Configuration of the appender, without its common options
+    config: AppenderLogConfig,
+    buffer_config: BufferConfig,
+    writer: BufferSender,
+    worker_guard: Arc<WorkerGuard>,
+    queued_lines: Option<Arc<AtomicUsize>>,
+    synced_file: Option<SyncedFile>,
+}
+
+impl AppenderWriter {
+    /// Number of lines written by the worker but not sent yet
+    fn queued_lines(&self) -> usize {
+        self.queued_lines
+            .as_ref()
+            .map_or(0, |queued_lines| queued_lines.load(Ordering::Relaxed))
+    }
+
+    /// Flush the writer, then sync its file to the storage device, if any
+    fn sync(&self, deadline: Instant) -> bool {
+        self.writer.flush_worker(deadline)
+            && self.synced_file.as_ref().map_or(true, |file| {
+                file.lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .sync_data()
+                    .is_ok()
+            })
+    }
+
+    /// Flush the writer until it has no line left to send, or until the deadline
+    fn drain(&self, deadline: Instant) -> bool {
+        loop {
+            if self.writer.flush_worker(deadline) && self.queued_lines() == 0 {
+                return true;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            thread::sleep(remaining.min(DRAIN_POLL_INTERVAL));
+        }
+    }
+}
+
+type AppenderWriters = HashMap<WriterKey, AppenderWriter>;
+
+/// Configuration of an appender which its writer depends on
+fn writer_config(appender: &AppenderLogConfig) -> AppenderLogConfig {
+    let mut config = appender.clone();
+    *config.options_mut() = AppenderOptions::default();
+    config
+}
+
+fn into_worker_guards(appender_writers: AppenderWriters) -> Vec<WorkerGuard> {
+    appender_writers
+        .into_values()
+        // Writers are only shared while a reload is built
+        .filter_map(|appender_writer| Arc::try_unwrap(appender_writer.worker_guard).ok())
+        .collect()
 }
 
+/// Result of a reload, reported to the callback registered with
+/// [`LogGuard::with_reload_callback`]
 #[derive(Debug)]
-pub enum EventFormat {
-    Full(Format<Full>),
-    Pretty(Format<Pretty>),
-    Compact(Format<Compact>),
-    System(Format<Compact, ()>),
+pub struct ReloadOutcome<'a> {
+    /// Number of active appenders after the reload
+    pub appenders: usize,
+    /// Error which prevented the new configuration from being applied
+    pub error: Option<&'a LogError>,
+    /// Whether the current configuration was kept because of the error
+    pub kept_current_config: bool,
+}
+
+/// Origin of the active configuration
+#[derive(Debug, Clone)]
+pub enum ConfigSource {
+    /// The given configuration was applied
+    File,
+    /// The given configuration was invalid, and the default one, or the one
+    /// given to [`init_log_with_fallback`], was applied instead
+    DefaultFallback(Arc<eyre::Report>),
+    /// Logging was initialized without any appender, by [`init_log_disabled`] or
+    /// by [`init_log_lenient`] when even the default configuration failed, and
+    /// not reloaded since
+    Disabled,
+}
+
+impl ConfigSource {
+    pub fn is_default_fallback(&self) -> bool {
+        matches!(self, ConfigSource::DefaultFallback(_))
+    }
+}
+
+/// Emit an event as with [`tracing::event!`], then wait for the appenders to
+/// write it, returning whether they did in time as with [`LogGuard::sync`]
+///
+/// This is meant for critical events, such as an audit record which must be
+/// persisted before a risky action, for instance
+/// `log_and_flush!(log_guard, target: "audit", Level::WARN, user, "Deleting the account")`.
+/// Each appender receives the flush request after the event, so that the
+/// file appenders have written it to their file and synced it to the storage
+/// device once this returns. The network appenders only guarantee that the
+/// event was handed to their socket.
+#[macro_export]
+macro_rules! log_and_flush {
+    ($log_guard:expr, $($event:tt)+) => {{
+        ::tracing::event!($($event)+);
+        $log_guard.sync()
+    }};
+}
+
+/// Configuration of the active appenders at some point, which
+/// [`LogGuard::restore`] applies again, for instance after an experiment
+#[derive(Debug, Clone)]
+pub struct LogSnapshot {
+    config: Log,
+}
+
+impl LogSnapshot {
+    /// Configuration captured by the snapshot
+    pub fn config(&self) -> &Log {
+        &self.config
+    }
+}
+
+type ReloadCallback = Arc<dyn Fn(&ReloadOutcome<'_>) + Send + Sync>;
+
+/// Outcome of a reload, kept until the callback is called with the state unlocked
+struct RecordedReload {
+    appenders: usize,
+    error: Option<LogError>,
+}
+
+impl RecordedReload {
+    fn outcome(&self) -> ReloadOutcome<'_> {
+        ReloadOutcome {
+            appenders: self.appenders,
+            error: self.error.as_ref(),
+            kept_current_config: self.error.is_some(),
+        }
+    }
+}
+
+/// State of the active appenders, shared by a guard and its reload handles
+struct LogState<S> {
+    /// Global dispatcher, routing events to the subscribers of the handle
+    dispatch: Dispatch,
+    subscriber_handle: SubscriberHandle<S>,
+    appender_writers: AppenderWriters,
+    dropped_lines_reporter: Option<DroppedLinesReporter>,
+    /// Writers of each appender, in configuration order
+    writers: Vec<Writers>,
+    /// `EnvFilter` cells of each appender, in configuration order
+    filter_cells: Vec<Vec<EnvFilterCell>>,
+    /// Latencies of each appender, in configuration order, when profiling
+    latencies: Vec<Option<LatencyHistogram>>,
+    config: Log,
+    recent_errors: RecentErrors,
+    /// Appenders which failed to initialize, missing from `config`
+    failed_appenders: Vec<String>,
+    config_source: ConfigSource,
+    reload_callback: Option<ReloadCallback>,
+    /// Outcome of the reload in progress, reported once the state is unlocked
+    reload_outcome: Option<RecordedReload>,
+    /// Temporary levels of appenders, by appender name
+    elevations: HashMap<String, Elevation>,
+    /// Wakes up the thread ending the elevations, started by the first one
+    elevation_timer: Option<mpsc::Sender<()>>,
+}
+
+/// Level of an appender raised by [`LogGuard::elevate_level`]
+struct Elevation {
+    /// Level of the appender before the first elevation
+    previous_level: Option<String>,
+    /// Time at which the previous level is restored
+    deadline: Instant,
 }
 
-impl From<LogFormat> for EventFormat {
-    fn from(format: LogFormat) -> Self {
-        match format {
-            LogFormat::Full => Self::Full(Format::default()),
-            LogFormat::Pretty => Self::Pretty(Format::default().pretty()),
-            LogFormat::Compact => Self::Compact(Format::default().compact()),
-            LogFormat::System => Self::System(Format::default().compact().without_time()),
+impl<S> LogState<S> {
+    /// Record the outcome of a reload, `error` having prevented the new
+    /// configuration from being applied
+    fn record_reload(&mut self, error: Option<LogError>) {
+        self.reload_outcome = Some(RecordedReload {
+            appenders: self.config.configs.appenders.len(),
+            error,
+        });
+    }
+
+    /// Change the level of several appenders, `None` removing their own level
+    fn reload_filters(&mut self, levels: &[(&str, Option<&str>)]) -> LogResult<()> {
+        let mut config = self.config.clone();
+        for &(appender_name, level) in levels {
+            let appender = config
+                .configs
+                .appenders
+                .get_mut(appender_name)
+                .ok_or_else(|| LogError::UnknownAppender {
+                    appender: appender_name.to_owned(),
+                })?;
+            appender.options_mut().level = level.map(str::to_owned);
+        }
+        config.validate_filters()?;
+
+        let mut reloads = Vec::new();
+        for &(appender_name, _) in levels {
+            let (index, _, appender) = config
+                .configs
+                .appenders
+                .get_full(appender_name)
+                .expect("the appender exists");
+            for cell in &self.filter_cells[index] {
+                let env_filter = env_filter(appender.log_config(), &config.global)?;
+                reloads.push((cell.clone(), env_filter));
+            }
+        }
+        reload_env_filters(reloads);
+        self.config = config;
+        Ok(())
+    }
+
+    /// Restore the level the appenders had before being elevated, for the
+    /// elevations whose deadline is reached, returning the next deadline
+    fn end_elevations(&mut self, now: Instant) -> Option<Instant> {
+        let ended: Vec<_> = self
+            .elevations
+            .iter()
+            .filter(|(_, elevation)| elevation.deadline <= now)
+            .map(|(appender_name, _)| appender_name.clone())
+            .collect();
+        for appender_name in ended {
+            let elevation = self
+                .elevations
+                .remove(&appender_name)
+                .expect("the elevation exists");
+            let previous_level = elevation.previous_level.as_deref();
+            if let Err(error) = self.reload_filters(&[(&appender_name, previous_level)]) {
+                warn!(appender = appender_name, error = %error_chain(&error), "Unable to restore the level of an appender");
+            }
+        }
+        self.elevations
+            .values()
+            .map(|elevation| elevation.deadline)
+            .min()
+    }
+
+    fn appenders_by_tag(&self, tag: &str) -> Vec<String> {
+        self.config
+            .configs
+            .appenders
+            .iter()
+            .filter(|(_, appender)| appender.log_config().tags().iter().any(|t| t == tag))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Write the pending lines of every appender, as with [`LogGuard::flush`]
+    fn flush(&self) -> bool {
+        let deadline = Instant::now() + FLUSH_TIMEOUT;
+        self.appender_writers
+            .values()
+            .filter(|appender_writer| !appender_writer.writer.flush_worker(deadline))
+            .count()
+            == 0
+    }
+
+    /// Write the pending lines of every appender and sync their files, as with
+    /// [`LogGuard::sync`]
+    fn sync(&self) -> bool {
+        let deadline = Instant::now() + FLUSH_TIMEOUT;
+        self.appender_writers
+            .values()
+            .filter(|appender_writer| !appender_writer.sync(deadline))
+            .count()
+            == 0
+    }
+
+    /// Record the appenders which failed to initialize, replacing the previous
+    /// ones, along with an invalid `RUST_LOG` which the installed configuration ignores
+    fn report_failures(&mut self, failures: Vec<(String, LogError)>) {
+        self.failed_appenders.clear();
+        for (appender, error) in failures {
+            warn!(%appender, %error, "Skipping appender which failed to initialize");
+            self.recent_errors.push(&error);
+            self.failed_appenders.push(appender);
+        }
+        if let Some(error) = self.config.global.invalid_level_from_env.clone() {
+            let error = LogError::InvalidEnv(error);
+            warn!(error = %error_chain(&error), "Ignoring the `RUST_LOG` environment variable");
+            self.recent_errors.push(&error);
+        }
+    }
+
+    /// Detach the subscribers, then close the writers in a deterministic order
+    fn shut_down(&mut self) {
+        // Detach the writers first, so that late events never reach a writer
+        // whose worker is shutting down
+        self.subscriber_handle.clear();
+
+        // Dropping a worker guard waits for its worker thread to write pending logs
+        let mut appender_writers: Vec<_> =
+            mem::take(&mut self.appender_writers).into_iter().collect();
+        appender_writers.sort_by(|(key, _), (other_key, _)| key.cmp(other_key));
+        for (_, appender_writer) in appender_writers {
+            drop(appender_writer);
         }
     }
 }
 
-impl<C, N> FormatEvent<C, N> for EventFormat
+impl<S> LogState<S>
 where
-    C: Collect + for<'a> LookupSpan<'a>,
-    N: for<'a> FormatFields<'a> + 'static,
+    S: Subscribe<Registry> + Send + Sync,
 {
-    fn format_event(
-        &self,
-        ctx: &FmtContext<'_, C, N>,
-        writer: Writer<'_>,
-        event: &Event<'_>,
-    ) -> fmt::Result {
-        match self {
-            EventFormat::Full(format) => format.format_event(ctx, writer, event),
-            EventFormat::Pretty(format) => format.format_event(ctx, writer, event),
-            EventFormat::Compact(format) => format.format_event(ctx, writer, event),
-            EventFormat::System(format) => format.format_event(ctx, writer, event),
+    /// Rebuild the subscribers of the current appenders, whose writers are kept,
+    /// from a configuration differing by its settings but not by its appenders
+    fn apply_config(&mut self, config: Log) -> LogResult<()> {
+        config.validate_filters()?;
+        let max_level = config.global.max_level_filter()?;
+
+        let setups = config
+            .configs
+            .appenders
+            .values()
+            .zip(&self.writers)
+            .map(|(appender, writers)| {
+                AppenderSetup::new(appender, &config.global, writers.clone())
+            })
+            .collect::<LogResult<Vec<_>>>()?;
+
+        self.filter_cells = setups.iter().map(AppenderSetup::filter_cells).collect();
+        self.latencies = setups.iter().map(AppenderSetup::latency).collect();
+        let subscribers = setups
+            .into_iter()
+            .map(AppenderSetup::into_subscriber)
+            .collect();
+        self.subscriber_handle.reload(subscribers, max_level);
+        write_error::set_policy(config.global.on_appender_error);
+        self.config = config;
+        Ok(())
+    }
+
+    /// Install the subscribers of a new configuration, returning the configuration
+    /// which was replaced along with the appenders which failed to initialize
+    fn install(&mut self, mut subscribers: Subscribers) -> (Log, Vec<(String, LogError)>) {
+        self.dropped_lines_reporter = subscribers.dropped_lines_reporter();
+        let previous_config = mem::replace(&mut self.config, mem::take(&mut subscribers.config));
+        write_error::set_policy(self.config.global.on_appender_error);
+        self.writers = mem::take(&mut subscribers.writers);
+        self.filter_cells = mem::take(&mut subscribers.filter_cells);
+        self.latencies = mem::take(&mut subscribers.latencies);
+        let failures = mem::take(&mut subscribers.failures);
+        let (appender_writers, subscribers) = subscribers.into_components();
+        self.appender_writers = appender_writers;
+        let max_level = global_max_level(&self.config);
+        SubscriberHandle::reload_batch([(&self.subscriber_handle, subscribers, max_level)]);
+        (previous_config, failures)
+    }
+
+    /// Apply a configuration differing from the current one by a single appender,
+    /// the other appenders keeping their writers
+    ///
+    /// Unlike a reload, the configuration is not applied if the appender fails
+    /// to initialize.
+    fn change_appenders(&mut self, mut config: Log) -> LogResult<()> {
+        config.resolve_profiles()?;
+        config.validate_filters()?;
+        self.install_all(config).map(drop)
+    }
+
+    /// Install a configuration only if every one of its appenders initializes,
+    /// returning the configuration which was replaced
+    ///
+    /// Every writer is opened before the subscribers are swapped, so that the
+    /// current appenders are left untouched on failure.
+    fn install_all(&mut self, config: Log) -> LogResult<Log> {
+        let mut previous_writers = mem::take(&mut self.appender_writers);
+        let mut subscribers = match Subscribers::new(config, &mut previous_writers) {
+            Ok(subscribers) => subscribers,
+            Err(error) => {
+                self.appender_writers = previous_writers;
+                return Err(error);
+            }
+        };
+        if !subscribers.failures.is_empty() {
+            subscribers.restore_writers(&mut previous_writers);
+            self.appender_writers = previous_writers;
+            let (_, error) = subscribers.failures.swap_remove(0);
+            return Err(error);
         }
+
+        let (previous_config, _) = self.install(subscribers);
+        self.record_reload(None);
+
+        // Flush and close the writers of the removed or changed appenders, now unused
+        drop(previous_writers);
+        Ok(previous_config)
     }
 }
 
-trait AppenderConfig: LogConfig {
-    fn non_blocking(&self) -> io::Result<(NonBlocking, WorkerGuard)>;
-}
+/// Run a reload with the state locked, then call the reload callback with the
+/// recorded outcome once the state is unlocked, so that the callback can use
+/// the guard
+///
+/// The recorded outcome is returned, along with the result of the reload.
+fn with_reload<S, T>(
+    state: &Mutex<LogState<S>>,
+    reload: impl FnOnce(&mut LogState<S>) -> T,
+) -> (T, Option<RecordedReload>) {
+    let (result, recorded, callback) = {
+        let mut state = lock(state);
+        let result = reload(&mut state);
+        let recorded = state.reload_outcome.take();
+        (result, recorded, state.reload_callback.clone())
+    };
 
-impl AppenderConfig for ConsoleLogConfig {
-    /// Create a non-blocking writer able to write logs in stdout or stderr
-    fn non_blocking(&self) -> io::Result<(NonBlocking, WorkerGuard)> {
-        match self.target {
-            ConsoleTarget::Stdout => Ok(tracing_appender::non_blocking(std::io::stdout())),
-            ConsoleTarget::Stderr => Ok(tracing_appender::non_blocking(std::io::stderr())),
+    if let (Some(recorded), Some(callback)) = (&recorded, callback) {
+        let outcome = recorded.outcome();
+        if panic::catch_unwind(AssertUnwindSafe(|| callback(&outcome))).is_err() {
+            warn!("The reload callback panicked");
         }
     }
+    (result, recorded)
 }
 
-impl AppenderConfig for FileLogConfig {
-    /// Create a non-blocking writer able to write logs in a file
-    fn non_blocking(&self) -> io::Result<(NonBlocking, WorkerGuard)> {
-        let path = &self.path;
+fn lock<S>(state: &Mutex<LogState<S>>) -> MutexGuard<'_, LogState<S>> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
 
-        let file = match self.mode {
-            // Append to file
-            FileWritingMode::Append => File::options().append(true).create(true).open(path)?,
-            // Troncate and overwrite file
-            FileWritingMode::Overwrite => File::create(path)?,
+/// End the elevations as their deadline is reached, until the state is dropped
+///
+/// `woken_up` receives a message whenever an elevation is added or replaced.
+fn end_elevations<S>(state: &Weak<Mutex<LogState<S>>>, woken_up: &mpsc::Receiver<()>) {
+    loop {
+        let Some(strong_state) = state.upgrade() else {
+            return;
         };
+        let next_deadline = lock(&strong_state).end_elevations(Instant::now());
+        drop(strong_state);
 
-        Ok(tracing_appender::non_blocking(file))
+        let received = match next_deadline {
+            Some(deadline) => woken_up
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                .or_else(|error| match error {
+                    mpsc::RecvTimeoutError::Timeout => Ok(()),
+                    mpsc::RecvTimeoutError::Disconnected => Err(mpsc::RecvError),
+                }),
+            None => woken_up.recv(),
+        };
+        // The sender is dropped along with the state
+        if received.is_err() {
+            return;
+        }
     }
 }
 
-struct SubscriberSetup {
-    writer: NonBlocking,
-    color: bool,
-    filter: EnvFilter,
-    format: EventFormat,
+#[must_use]
+pub struct LogGuard<S> {
+    state: Arc<Mutex<LogState<S>>>,
 }
 
-impl SubscriberSetup {
-    fn new(writer: NonBlocking, color: bool, filter: EnvFilter, format: EventFormat) -> Self {
+impl<S> LogGuard<S> {
+    fn new(state: LogState<S>) -> Self {
         Self {
-            writer,
-            color,
-            filter,
-            format,
+            state: Arc::new(Mutex::new(state)),
         }
     }
 
-    fn from_appender(
-        config: &impl AppenderConfig,
-        global_config: &GlobalLogConfig,
-    ) -> eyre::Result<(Self, WorkerGuard)> {
-        let level = global_config
-            .level_from_env
-            .as_deref()
-            .or(config.level())
-            .unwrap_or(&global_config.level);
+    fn state(&self) -> MutexGuard<'_, LogState<S>> {
+        lock(&self.state)
+    }
 
-        let color = config.color();
-        let format = config.format().unwrap_or(global_config.format);
-        let (non_blocking, worker_guard) = config.non_blocking()?;
-        let filter = EnvFilter::from_str(level)?;
-        let subscriber_setup = SubscriberSetup::new(non_blocking, color, filter, format.into());
+    /// Handle able to reload the configuration from another thread, such as a
+    /// signal handler, while this guard keeps owning the writers
+    pub fn reload_handle(&self) -> ReloadHandle<S> {
+        ReloadHandle {
+            state: Arc::downgrade(&self.state),
+        }
+    }
 
-        Ok((subscriber_setup, worker_guard))
+    /// Configuration of the active appenders
+    pub fn current_config(&self) -> Log {
+        self.state().config.clone()
     }
 
-    fn into_subscriber<C>(self) -> FilteredSubscriber<C>
-    where
-        C: Collect + for<'a> LookupSpan<'a>,
-    {
-        tracing_subscriber::fmt::subscriber()
-            .with_ansi(self.color)
-            .with_writer(self.writer)
-            .event_format(self.format)
-            .with_filter(self.filter)
+    /// Capture the active configuration, including the levels changed at runtime
+    pub fn snapshot(&self) -> LogSnapshot {
+        LogSnapshot {
+            config: self.current_config(),
+        }
     }
-}
 
-#[derive(Default)]
-struct Subscribers {
-    subscribers: Vec<SubscriberSetup>,
-    worker_guards: Vec<WorkerGuard>,
-}
+    /// Whether the active configuration is the given one or the default fallback
+    ///
+    /// A failed reload keeps the current configuration, and therefore its source.
+    pub fn config_source(&self) -> ConfigSource {
+        self.state().config_source.clone()
+    }
 
-impl Subscribers {
-    fn set_global_dispatch(collector: impl Into<Dispatch>) -> eyre::Result<()> {
-        // Filter level for `tracing_log` is global and cannot be reconfigured,
-        // so we inline the `init()` method to keep the default level.
-        dispatch::set_global_default(collector.into())?;
-        tracing_log::LogTracer::init()?;
-        Ok(())
+    /// Errors which occurred while (re)configuring logging
+    pub fn recent_errors(&self) -> Vec<String> {
+        self.state()
+            .recent_errors
+            .iter()
+            .map(str::to_owned)
+            .collect()
     }
 
-    fn into_components<C>(self) -> (Vec<WorkerGuard>, Vec<FilteredSubscriber<C>>)
-    where
-        C: Collect + for<'a> LookupSpan<'a>,
-    {
-        let subscribers = self
-            .subscribers
-            .into_iter()
-            .map(SubscriberSetup::into_subscriber)
-            .collect();
+    /// Call a function after each reload, for instance to update a metric
+    ///
+    /// The callback runs once the new appenders are installed and the guard is
+    /// unlocked, so that it can use a [`ReloadHandle`]. Its panics are caught
+    /// and logged.
+    pub fn with_reload_callback(
+        self,
+        callback: impl Fn(&ReloadOutcome<'_>) + Send + Sync + 'static,
+    ) -> Self {
+        self.state().reload_callback = Some(Arc::new(callback));
+        self
+    }
 
-        (self.worker_guards, subscribers)
+    /// Call a function with the write errors of the file and network appenders,
+    /// along with the name of the appender, whatever the `on_appender_error` policy
+    ///
+    /// The callback runs in the writer threads, and must not log with the
+    /// failing appender.
+    pub fn with_write_error_callback(
+        self,
+        callback: impl Fn(&str, &io::Error) + Send + Sync + 'static,
+    ) -> Self {
+        write_error::set_callback(Some(Arc::new(callback)));
+        self
     }
 
-    fn build<S>(self, base_collector: BaseCollector<S>) -> eyre::Result<LogGuard<S>>
-    where
-        S: Subscribe<Registry> + Send + Sync,
-    {
-        let (worker_guards, subscribers) = self.into_components();
-        let (collector, subscriber_handle) = base_collector.with_reloadable(subscribers);
-        Self::set_global_dispatch(collector)?;
+    /// Names of the appenders which failed to initialize and are not active
+    pub fn failed_appenders(&self) -> Vec<String> {
+        self.state().failed_appenders.clone()
+    }
 
-        Ok(LogGuard {
-            subscriber_handle,
-            worker_guards,
-        })
+    /// Lines written by the memory appenders, in configuration order
+    pub fn captured_lines(&self) -> Vec<String> {
+        self.state()
+            .config
+            .configs
+            .appenders
+            .values()
+            .filter_map(|appender| match appender {
+                AppenderLogConfig::Memory(config) => Some(config.writer.lines()),
+                _ => None,
+            })
+            .flatten()
+            .collect()
     }
-}
 
-impl TryFrom<Log> for Subscribers {
-    type Error = eyre::Error;
+    /// Most recent lines kept by the ring buffer appenders, in configuration order
+    pub fn recent_lines(&self) -> Vec<String> {
+        self.state()
+            .config
+            .configs
+            .appenders
+            .values()
+            .filter_map(|appender| match appender {
+                AppenderLogConfig::RingBuffer(config) => Some(config.writer.lines()),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
 
-    fn try_from(log: Log) -> Result<Self, Self::Error> {
-        let len = log.configs.appenders.len();
+    /// Number of lines dropped so far by the active writers because their buffer was full
+    ///
+    /// Lines are only dropped with the lossy buffer mode, or with the blocking
+    /// mode when they wait longer than its timeout.
+    pub fn dropped_count(&self) -> u64 {
+        self.state()
+            .appender_writers
+            .values()
+            .map(|appender_writer| appender_writer.writer.dropped_lines() as u64)
+            .sum()
+    }
 
-        let mut subscribers = Subscribers {
-            subscribers: Vec::with_capacity(len),
-            worker_guards: Vec::with_capacity(len),
+    /// Names of the active appenders having a tag, in configuration order
+    pub fn appenders_by_tag(&self, tag: &str) -> Vec<String> {
+        self.state().appenders_by_tag(tag)
+    }
+
+    /// Latencies of the active appenders, by appender name, when the global
+    /// `profiling` setting is enabled
+    ///
+    /// Each histogram holds the durations, in nanoseconds, of the formatting and
+    /// writing of the events recorded by the appender since its subscriber was
+    /// last built.
+    pub fn appender_latencies(&self) -> Vec<(String, Histogram<u64>)> {
+        let state = self.state();
+        state
+            .config
+            .configs
+            .appenders
+            .keys()
+            .zip(&state.latencies)
+            .filter_map(|(name, latency)| Some((name.clone(), latency.as_ref()?.snapshot())))
+            .collect()
+    }
+
+    /// Most verbose level of each active appender, once `RUST_LOG`, the
+    /// configuration and the global settings are resolved
+    ///
+    /// Directives enabling a level for some targets only are taken into account,
+    /// so that an appender at `info,my_crate=debug` is reported at `debug`.
+    pub fn effective_levels(&self) -> Vec<(String, LevelFilter)> {
+        let state = self.state();
+        let global_config = &state.config.global;
+        state
+            .config
+            .configs
+            .appenders
+            .iter()
+            .map(|(name, appender)| {
+                let level = effective_level(appender.log_config(), global_config);
+                (name.clone(), level)
+            })
+            .collect()
+    }
+
+    /// Global dispatcher installed by this guard, for instance to enter it with
+    /// [`dispatch::with_default`] on threads which get another default one
+    pub fn dispatch(&self) -> Dispatch {
+        self.state().dispatch.clone()
+    }
+
+    /// Gather the state of logging, for instance to attach it to a support request
+    pub fn diagnostics(&self) -> DiagnosticsBundle {
+        let state = self.state();
+        DiagnosticsBundle::new(
+            &state.config,
+            &state.recent_errors,
+            state.appender_writers.len(),
+        )
+    }
+
+    /// Write the pending lines of every appender, waiting at most [`FLUSH_TIMEOUT`]
+    ///
+    /// Return whether every appender has been flushed in time. This is best-effort
+    /// for the network appenders, whose lines may still be in transit, and for
+    /// lossy appenders whose buffer is full. See [`log_and_flush!`](crate::log_and_flush)
+    /// to wait for a critical event.
+    pub fn flush(&self) -> bool {
+        self.state().flush()
+    }
+
+    /// Write the pending lines of every appender as with [`LogGuard::flush`],
+    /// then sync the files of the file appenders to the storage device
+    ///
+    /// Return whether every appender has been flushed and synced in time. The
+    /// mirrors of the files are not synced.
+    pub fn sync(&self) -> bool {
+        self.state().sync()
+    }
+
+    /// Stop logging, waiting at most `timeout` for the network appenders to
+    /// send their pending lines
+    ///
+    /// Unlike [`LogGuard::flush_timeout`], lines written by the workers but
+    /// queued by the network writers, such as while a TCP server is unreachable,
+    /// are waited for. The other appenders are flushed in the background, and
+    /// OTLP appenders export their remaining records in the background as well.
+    pub fn shutdown(self, timeout: Duration) -> ShutdownReport {
+        let deadline = Instant::now() + timeout;
+        let appender_writers = {
+            let mut state = self.state();
+            state.subscriber_handle.clear();
+            mem::take(&mut state.appender_writers)
         };
 
-        for appender in log.configs.appenders.values() {
-            let (subscriber, worker_guard) = match appender {
-                AppenderLogConfig::Console(appender) => {
-                    SubscriberSetup::from_appender(appender, &log.global)?
-                }
-                AppenderLogConfig::File(appender) => {
-                    SubscriberSetup::from_appender(appender, &log.global)?
-                }
-            };
+        let mut appenders: Vec<_> = appender_writers
+            .iter()
+            .filter(|(_, appender_writer)| appender_writer.config.is_network())
+            .filter_map(|(key, appender_writer)| {
+                let WriterKey::Appender(name) = key else {
+                    return None;
+                };
+                let drained = appender_writer.drain(deadline);
+                Some(AppenderShutdown {
+                    appender: name.clone(),
+                    drained,
+                    queued_lines: appender_writer.queued_lines(),
+                })
+            })
+            .collect();
+        appenders.sort_by(|appender, other| appender.appender.cmp(&other.appender));
 
-            subscribers.subscribers.push(subscriber);
-            subscribers.worker_guards.push(worker_guard);
-        }
+        // Dropping a worker guard waits for its worker thread, which a blocked
+        // network write would hold up
+        thread::spawn(move || drop(appender_writers));
+        ShutdownReport { appenders }
+    }
 
-        Ok(subscribers)
+    /// Stop logging, waiting at most `timeout` for the appenders to flush pending logs
+    ///
+    /// Return whether every appender has been flushed in time. Otherwise, the
+    /// remaining logs keep being flushed in the background and may be lost if the
+    /// process exits.
+    pub fn flush_timeout(self, timeout: Duration) -> bool {
+        let appender_writers = {
+            let mut state = self.state();
+            state.subscriber_handle.clear();
+            mem::take(&mut state.appender_writers)
+        };
+        let (flushed, flush_done) = mpsc::channel();
+
+        // Dropping a worker guard waits for its worker thread to write pending logs
+        thread::spawn(move || {
+            drop(appender_writers);
+            let _ = flushed.send(());
+        });
+
+        flush_done.recv_timeout(timeout).is_ok()
     }
 }
 
-fn build_appenders(file_contents: &str, data_dir: &Path) -> eyre::Result<Subscribers> {
-    let log = Log::parse(file_contents, data_dir).context("invalid logging configuration file")?;
-    Subscribers::try_from(log).context("unable to initialize appenders")
+/// Summarize the active appenders, without their writers
+impl<S> fmt::Debug for LogGuard<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state();
+        f.debug_struct("LogGuard")
+            .field(
+                "appenders",
+                &AppenderDiagnostics::from_config(&state.config),
+            )
+            .field("failed_appenders", &state.failed_appenders)
+            .field("config_source", &state.config_source)
+            .finish()
+    }
 }
 
-fn build_default_appenders() -> eyre::Result<Subscribers> {
-    let level_from_env = match env::var("RUST_LOG") {
-        Ok(level) => Some(level),
-        Err(VarError::NotPresent) => None,
-        Err(err) => return Err(err.into()),
-    };
+/// Write the active appenders as a table, with their kind, destination, level and format
+impl<S> fmt::Display for LogGuard<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let appenders = AppenderDiagnostics::from_config(&self.state().config);
+        write!(f, "{}", AppenderTable(&appenders))
+    }
+}
 
-    Subscribers::try_from(Log {
-        global: GlobalLogConfig {
-            level_from_env,
-            ..Default::default()
-        },
-        configs: LogConfigs {
-            appenders: IndexMap::from([(
-                "stdout".into(),
-                AppenderLogConfig::Console(ConsoleLogConfig::default()),
-            )]),
-        },
-    })
-    .context("unable to initialize default appenders")
+impl<S> Drop for LogGuard<S> {
+    fn drop(&mut self) {
+        self.state().shut_down();
+    }
 }
 
-pub fn init_log<S>(
-    file_contents: &str,
-    data_dir: &Path,
-    platform_subscriber: S,
-) -> eyre::Result<LogGuard<S>>
+impl<S> LogGuard<S>
 where
     S: Subscribe<Registry> + Send + Sync,
 {
-    let (subscribers, error) = match build_appenders(file_contents, data_dir) {
-        Ok(subscribers) => (subscribers, None),
-        Err(e) => (build_default_appenders()?, Some(e)),
-    };
+    /// Reload the logging configuration, returning the configuration which was replaced
+    ///
+    /// Appenders which are unchanged keep their writers. If the new configuration
+    /// is invalid, the current appenders are kept and the current configuration
+    /// is returned.
+    pub fn reload(&mut self, file_contents: &str, data_dir: &Path) -> Log {
+        match parse_log(file_contents, data_dir, None) {
+            Ok(log) => self.reload_with(log),
+            Err(error) => with_reload(&self.state, |state| reload_log_inner(Err(error), state)).0,
+        }
+    }
 
-    let base_collector = tracing_subscriber::registry().with(platform_subscriber);
-    let log_guard = subscribers.build(base_collector)?;
+    /// Reload the logging configuration from a configuration which is already
+    /// parsed, such as one built or edited by the application, as with
+    /// [`LogGuard::reload`]
+    ///
+    /// The paths of the configuration are used as they are, so that they must
+    /// not be relative to a data directory.
+    pub fn reload_with(&mut self, log: Log) -> Log {
+        with_reload(&self.state, |state| reload_log_inner(Ok(log), state)).0
+    }
 
-    if let Some(error) = error {
-        warn!(%error, "Using default logging configuration");
+    /// Apply the configuration of a snapshot again, returning the configuration
+    /// which was replaced
+    ///
+    /// Appenders which did not change since the snapshot keep their writers,
+    /// and temporary level elevations are ended.
+    pub fn restore(&mut self, snapshot: LogSnapshot) -> Log {
+        self.reload_with(snapshot.config)
     }
 
-    Ok(log_guard)
-}
+    /// Reload the logging configuration as with [`LogGuard::reload`], unless
+    /// any of its appenders fails to initialize
+    ///
+    /// Unlike [`LogGuard::reload`], which skips the failed appenders, the new
+    /// configuration is applied entirely or not at all: every writer is opened
+    /// before the current appenders are replaced, and the error is returned
+    /// with the current configuration left untouched.
+    pub fn try_reload(&mut self, file_contents: &str, data_dir: &Path) -> LogResult<Log> {
+        let log = parse_log(file_contents, data_dir, None);
+        let (previous_config, recorded) = with_reload(&self.state, |state| {
+            match log.and_then(|log| state.install_all(log)) {
+                Ok(previous_config) => {
+                    state.elevations.clear();
+                    state.config_source = ConfigSource::File;
+                    state.report_failures(Vec::new());
+                    Some(previous_config)
+                }
+                Err(error) => {
+                    warn!(%error, "Keeping the current logging configuration");
+                    state.recent_errors.push(&error);
+                    state.record_reload(Some(error));
+                    None
+                }
+            }
+        });
+        match (
+            previous_config,
+            recorded.and_then(|recorded| recorded.error),
+        ) {
+            (Some(previous_config), _) => Ok(previous_config),
+            (None, Some(error)) => Err(error),
+            (None, None) => unreachable!("failed reloads record their error"),
+        }
+    }
 
-pub fn reload_log<S>(
-    file_contents: &str,
-    data_dir: &Path,
-    mut log_guard: LogGuard<S>,
-) -> eyre::Result<LogGuard<S>>
-where
-    S: Subscribe<Registry> + Send + Sync,
-{
-    // Flush and clear current appenders
-    log_guard.worker_guards.clear();
+    /// Reload the logging configuration from a file
+    ///
+    /// An unreadable file is an error, while an invalid one keeps the current
+    /// configuration as with [`LogGuard::reload`].
+    pub fn reload_from_path(&mut self, path: &Path, data_dir: &Path) -> LogResult<Log> {
+        let file_contents = read_config(path)?;
+        let log = parse_log(&file_contents, data_dir, Some(path));
+        Ok(with_reload(&self.state, |state| reload_log_inner(log, state)).0)
+    }
 
-    let (subscribers, error) = match build_appenders(file_contents, data_dir) {
-        Ok(subscribers) => (subscribers, None),
-        Err(e) => (build_default_appenders()?, Some(e)),
-    };
+    /// Reload the logging configuration by applying a sparse configuration onto
+    /// the current one, as described by [`Log::merge`]
+    ///
+    /// If the merged configuration is invalid, the current appenders are kept as
+    /// with [`LogGuard::reload`].
+    pub fn merge_reload(&mut self, patch_contents: &str, data_dir: &Path) -> Log {
+        with_reload(&self.state, |state| {
+            let log = state.config.merge(patch_contents, data_dir);
+            reload_log_inner(log, state)
+        })
+        .0
+    }
 
-    let (worker_guards, subscribers) = subscribers.into_components();
-    log_guard.subscriber_handle.reload(subscribers);
+    /// Apply new global settings to the current appenders, keeping their writers
+    ///
+    /// Appenders without an explicit level or format pick up the new global ones.
+    pub fn set_global(&mut self, mut global_config: GlobalLogConfig) -> LogResult<()> {
+        let mut state = self.state();
+        if global_config.level_from_env.is_none() {
+            global_config.level_from_env = state.config.global.level_from_env.clone();
+            global_config.invalid_level_from_env =
+                state.config.global.invalid_level_from_env.clone();
+        }
 
-    if let Some(error) = error {
-        warn!(%error, "Using default logging configuration");
+        let mut config = state.config.clone();
+        config.global = global_config;
+        state.apply_config(config)?;
+        state.elevations.clear();
+        Ok(())
     }
 
-    Ok(LogGuard {
-        worker_guards,
-        ..log_guard
-    })
+    /// Change the level of an appender, such as `debug` or `info,my_crate=trace`,
+    /// keeping its writer
+    pub fn set_level(&mut self, appender_name: &str, level: &str) -> LogResult<()> {
+        self.reload_filters(&[(appender_name, level)])
+    }
+
+    /// Change the level of every appender having a tag, as with
+    /// [`LogGuard::reload_filters`]
+    pub fn set_level_for_tag(&mut self, tag: &str, level: &str) -> LogResult<()> {
+        let appenders = self.state().appenders_by_tag(tag);
+        if appenders.is_empty() {
+            return Err(LogError::UnknownTag {
+                tag: tag.to_owned(),
+            });
+        }
+        let levels: Vec<_> = appenders
+            .iter()
+            .map(|appender_name| (appender_name.as_str(), level))
+            .collect();
+        self.reload_filters(&levels)
+    }
+
+    /// Change the level of several appenders at once, replacing only their
+    /// `EnvFilter` rather than rebuilding their subscribers
+    ///
+    /// No level is changed if any of them is invalid.
+    ///
+    /// This cancels the pending restoration of the levels raised by
+    /// [`LogGuard::elevate_level`].
+    pub fn reload_filters(&mut self, levels: &[(&str, &str)]) -> LogResult<()> {
+        let mut state = self.state();
+        let levels: Vec<_> = levels
+            .iter()
+            .map(|&(appender_name, level)| (appender_name, Some(level)))
+            .collect();
+        state.reload_filters(&levels)?;
+        for (appender_name, _) in levels {
+            state.elevations.remove(appender_name);
+        }
+        Ok(())
+    }
+
+    /// Change the level of an appender for a limited time, such as `debug` for
+    /// a few minutes, after which its current level is restored
+    ///
+    /// Elevating an appender again before the end of the duration replaces the
+    /// level and the duration, the level restored being still the one before
+    /// the first elevation. Changing the level with [`LogGuard::set_level`],
+    /// [`LogGuard::set_global_level`] or [`LogGuard::set_global`], or reloading
+    /// the configuration cancels the restoration. A single thread, started by
+    /// the first elevation, restores the levels.
+    pub fn elevate_level(
+        &mut self,
+        appender_name: &str,
+        level: &str,
+        duration: Duration,
+    ) -> LogResult<()>
+    where
+        S: 'static,
+    {
+        let mut state = self.state();
+        let previous_level = match state.elevations.get(appender_name) {
+            Some(elevation) => elevation.previous_level.clone(),
+            None => state
+                .config
+                .configs
+                .appenders
+                .get(appender_name)
+                .and_then(|appender| appender.log_config().options().level.clone()),
+        };
+        state.reload_filters(&[(appender_name, Some(level))])?;
+
+        state.elevations.insert(
+            appender_name.to_owned(),
+            Elevation {
+                previous_level,
+                deadline: Instant::now() + duration,
+            },
+        );
+
+        match &state.elevation_timer {
+            // The timer computes its next deadline again once the state is unlocked
+            Some(wake_up) => {
+                let _ = wake_up.send(());
+            }
+            None => {
+                let (wake_up, woken_up) = mpsc::channel();
+                let weak_state = Arc::downgrade(&self.state);
+                thread::spawn(move || end_elevations(&weak_state, &woken_up));
+                state.elevation_timer = Some(wake_up);
+            }
+        }
+        Ok(())
+    }
+
+    /// Change the level of every appender, replacing their own level
+    pub fn set_global_level(&mut self, level: &str) -> LogResult<()> {
+        let mut state = self.state();
+        let mut config = state.config.clone();
+        config.global.level = level.to_owned();
+        for appender in config.configs.appenders.values_mut() {
+            appender.options_mut().level = None;
+        }
+        state.apply_config(config)?;
+        state.elevations.clear();
+        Ok(())
+    }
+
+    /// Add an appender to the current configuration, leaving the writers of the
+    /// other appenders untouched
+    ///
+    /// The configuration is used as given: unlike in a configuration file, file
+    /// paths are not relative to the data directory.
+    pub fn add_appender(
+        &mut self,
+        name: impl Into<String>,
+        appender: AppenderLogConfig,
+    ) -> LogResult<()> {
+        let name = name.into();
+        with_reload(&self.state, |state| {
+            let mut config = state.config.clone();
+            if config.configs.appenders.contains_key(&name) {
+                return Err(LogError::DuplicateAppender { appender: name });
+            }
+            let max = max_appenders();
+            if config.configs.appenders.len() >= max {
+                return Err(LogError::TooManyAppenders {
+                    appender: name,
+                    max,
+                });
+            }
+            config.configs.appenders.insert(name, appender);
+            state.change_appenders(config)
+        })
+        .0
+    }
+
+    /// Remove an appender from the current configuration, flushing and closing
+    /// its writer while the other appenders keep theirs
+    pub fn remove_appender(&mut self, appender_name: &str) -> LogResult<()> {
+        with_reload(&self.state, |state| {
+            let mut config = state.config.clone();
+            if config
+                .configs
+                .appenders
+                .shift_remove(appender_name)
+                .is_none()
+            {
+                return Err(LogError::UnknownAppender {
+                    appender: appender_name.to_owned(),
+                });
+            }
+            state.change_appenders(config)
+        })
+        .0
+    }
+}
+
+/// Handle reloading the configuration of a [`LogGuard`], which can be cloned
+/// and sent to other threads
+///
+/// The handle does not own the writers: once the guard is dropped, reloading
+/// fails with [`LogError::LoggingStopped`].
+pub struct ReloadHandle<S> {
+    state: Weak<Mutex<LogState<S>>>,
+}
+
+impl<S> Clone for ReloadHandle<S> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<S> ReloadHandle<S> {
+    fn state(&self) -> LogResult<Arc<Mutex<LogState<S>>>> {
+        self.state.upgrade().ok_or(LogError::LoggingStopped)
+    }
+
+    /// Change the level of an appender as with [`LogGuard::set_level`]
+    pub fn set_level(&self, appender_name: &str, level: &str) -> LogResult<()> {
+        let state = self.state()?;
+        let mut state = lock(&state);
+        state.reload_filters(&[(appender_name, Some(level))])?;
+        state.elevations.remove(appender_name);
+        Ok(())
+    }
+
+    /// Write the pending lines of every appender as with [`LogGuard::flush`]
+    pub fn flush(&self) -> LogResult<bool> {
+        Ok(lock(&self.state()?).flush())
+    }
+
+    /// Table of the active appenders, as displayed by [`LogGuard`]
+    pub fn status(&self) -> LogResult<String> {
+        let state = self.state()?;
+        let appenders = AppenderDiagnostics::from_config(&lock(&state).config);
+        Ok(AppenderTable(&appenders).to_string())
+    }
+}
+
+impl<S> ReloadHandle<S>
+where
+    S: Subscribe<Registry> + Send + Sync,
+{
+    /// Reload the logging configuration as with [`LogGuard::reload`], returning
+    /// the configuration which was replaced
+    pub fn reload(&self, file_contents: &str, data_dir: &Path) -> LogResult<Log> {
+        let state = self.state.upgrade().ok_or(LogError::LoggingStopped)?;
+        let log = parse_log(file_contents, data_dir, None);
+        let (previous_config, _) = with_reload(&state, |state| reload_log_inner(log, state));
+        Ok(previous_config)
+    }
+
+    /// Reload the logging configuration from a configuration which is already
+    /// parsed as with [`LogGuard::reload_with`]
+    pub fn reload_with(&self, log: Log) -> LogResult<Log> {
+        let state = self.state.upgrade().ok_or(LogError::LoggingStopped)?;
+        let (previous_config, _) = with_reload(&state, |state| reload_log_inner(Ok(log), state));
+        Ok(previous_config)
+    }
+
+    /// Reload the logging configuration from a file as with [`LogGuard::reload_from_path`]
+    pub fn reload_from_path(&self, path: &Path, data_dir: &Path) -> LogResult<Log> {
+        let state = self.state.upgrade().ok_or(LogError::LoggingStopped)?;
+        let file_contents = read_config(path)?;
+        let log = parse_log(&file_contents, data_dir, Some(path));
+        let (previous_config, _) = with_reload(&state, |state| reload_log_inner(log, state));
+        Ok(previous_config)
+    }
+}
+
+trait AppenderConfig: LogConfig {
+    /// Identify the destination of the appender, so that appenders writing
+    /// to the same destination can share a single writer
+    fn destination(&self) -> io::Result<Option<PathBuf>> {
+        Ok(None)
+    }
+
+    fn non_blocking(
+        &self,
+        appender_name: &str,
+        buffer_config: &BufferConfig,
+    ) -> io::Result<BufferedWriter>;
+
+    /// Levels of the events recorded by the appender, besides its filter
+    fn level_range(&self) -> Option<&LevelRange> {
+        None
+    }
+
+    /// Create the event formatter of the appender
+    fn event_format(&self, global_config: &GlobalLogConfig) -> EventFormat {
+        let format = global_config.format(self);
+        let timer = Timer::new(global_config.timestamp.clone());
+        let event_format = if self.display().align == Some(true) {
+            let default_colors = IndexMap::new();
+            EventFormat::aligned(
+                format,
+                timer,
+                global_config.color(self).then_some(&default_colors),
+            )
+        } else {
+            EventFormat::new(format, timer)
+        };
+        event_format.with_display(self.display())
+    }
+}
+
+impl AppenderConfig for ConsoleLogConfig {
+    /// Create a non-blocking writer able to write logs in stdout or stderr
+    ///
+    /// When splitting streams, this is the stdout writer.
+    fn non_blocking(
+        &self,
+        _appender_name: &str,
+        buffer_config: &BufferConfig,
+    ) -> io::Result<BufferedWriter> {
+        match self.target {
+            ConsoleTarget::Stderr if !self.splits_streams() => {
+                Ok(buffer::non_blocking(std::io::stderr(), buffer_config))
+            }
+            _ => Ok(buffer::non_blocking(std::io::stdout(), buffer_config)),
+        }
+    }
+
+    /// Color the level labels with the custom palette, if any and if colors are enabled
+    fn event_format(&self, global_config: &GlobalLogConfig) -> EventFormat {
+        let format = global_config.format(self);
+        let timer = Timer::new(global_config.timestamp.clone());
+        let event_format = match &self.level_colors {
+            _ if self.display().align == Some(true) => {
+                let default_colors = IndexMap::new();
+                let level_colors = self.level_colors.as_ref().unwrap_or(&default_colors);
+                EventFormat::aligned(
+                    format,
+                    timer,
+                    global_config.color(self).then_some(level_colors),
+                )
+            }
+            Some(level_colors) if global_config.color(self) => {
+                EventFormat::with_level_colors(format, timer, level_colors)
+            }
+            _ => EventFormat::new(format, timer),
+        };
+        event_format.with_display(self.display())
+    }
+}
+
+impl AppenderConfig for FileLogConfig {
+    /// Canonicalize the file path, which may not exist yet, after creating its
+    /// parent directories
+    fn destination(&self) -> io::Result<Option<PathBuf>> {
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(|e| {
+                let msg = format!("unable to create the directory `{}`: {e}", parent.display());
+                io::Error::new(e.kind(), msg)
+            })?;
+        }
+
+        let path = match fs::canonicalize(&self.path) {
+            Ok(path) => path,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let parent = match self.path.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => fs::canonicalize(parent)?,
+                    _ => env::current_dir()?,
+                };
+                match self.path.file_name() {
+                    Some(file_name) => parent.join(file_name),
+                    None => return Err(e),
+                }
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(Some(path))
+    }
+
+    fn level_range(&self) -> Option<&LevelRange> {
+        self.level_range.as_ref()
+    }
+
+    /// Create a non-blocking writer able to write logs in a file
+    fn non_blocking(
+        &self,
+        appender_name: &str,
+        buffer_config: &BufferConfig,
+    ) -> io::Result<BufferedWriter> {
+        let path = &self.path;
+        let file = self.open(path)?;
+
+        let file_output = FileOutput {
+            compress: self.compress,
+            line_ending: self.line_ending,
+            write_buffer_bytes: self.write_buffer_bytes,
+            strip_ansi: self.strip_ansi,
+            bom: self.bom,
+        };
+        let batch_config = self.batch_lines.map(|lines| BatchConfig {
+            lines,
+            interval: Duration::from_millis(self.batch_interval_ms),
+        });
+        let (writer, synced_file): (Box<dyn Write + Send>, _) = match self.max_size_bytes {
+            Some(max_size) => {
+                let writer = SizeRotatingWriter::new(
+                    path.clone(),
+                    file,
+                    file_output,
+                    max_size,
+                    self.max_files,
+                )?;
+                let synced_file = writer.synced_file();
+                (Box::new(writer), synced_file)
+            }
+            None => {
+                let synced_file = Arc::new(Mutex::new(file.try_clone()?));
+                (rotation::output(file, file_output)?, synced_file)
+            }
+        };
+        let writer = WriteErrorReporter::new(writer, appender_name);
+
+        // The mirror is neither rotated, reported by the error policy nor synced
+        let mut buffered_writer = match &self.mirror_path {
+            Some(mirror_path) => {
+                let mirror = rotation::output(self.open(mirror_path)?, file_output)?;
+                buffer::non_blocking_batched(
+                    MirrorWriter::new(writer, mirror, mirror_path.clone()),
+                    buffer_config,
+                    batch_config,
+                )
+            }
+            None => buffer::non_blocking_batched(writer, buffer_config, batch_config),
+        };
+        buffered_writer.synced_file = Some(synced_file);
+        Ok(buffered_writer)
+    }
+}
+
+impl FileLogConfig {
+    /// Open the file for writing, retrying up to `open_retries` times after
+    /// transient failures
+    ///
+    /// The file is opened when the appender is built rather than on the first
+    /// write, so that an unwritable path fails the initialization or the reload
+    /// instead of losing the logs in the worker.
+    fn open(&self, path: &Path) -> io::Result<File> {
+        let mut retries = self.open_retries;
+        loop {
+            let result = match self.mode {
+                // Append to file
+                FileWritingMode::Append => File::options().append(true).create(true).open(path),
+                // Troncate and overwrite file
+                FileWritingMode::Overwrite => File::create(path),
+            };
+            match result {
+                Err(e) if retries > 0 && is_transient(&e) => {
+                    retries -= 1;
+                    thread::sleep(Duration::from_millis(self.open_retry_delay_ms));
+                }
+                Err(e) => {
+                    let msg = format!("unable to open the file `{}`: {e}", path.display());
+                    return Err(io::Error::new(e.kind(), msg));
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Whether opening a file may succeed when retried, unlike when it is denied
+/// or when the path is invalid
+fn is_transient(error: &io::Error) -> bool {
+    !matches!(
+        error.kind(),
+        io::ErrorKind::PermissionDenied
+            | io::ErrorKind::InvalidInput
+            | io::ErrorKind::AlreadyExists
+            | io::ErrorKind::Unsupported
+    )
+}
+
+impl AppenderConfig for GelfLogConfig {
+    /// Create a non-blocking writer able to send GELF payloads over UDP
+    fn non_blocking(
+        &self,
+        appender_name: &str,
+        buffer_config: &BufferConfig,
+    ) -> io::Result<BufferedWriter> {
+        let writer = GelfWriter::connect(&self.host, self.port)?;
+        Ok(buffer::non_blocking(
+            WriteErrorReporter::new(writer, appender_name),
+            buffer_config,
+        ))
+    }
+
+    /// Events are always formatted as GELF payloads
+    fn event_format(&self, global_config: &GlobalLogConfig) -> EventFormat {
+        let format = GelfFormat::new(
+            self.facility.clone(),
+            self.message_from_field().map(str::to_owned),
+            global_config.version(),
+        );
+        let format = format
+            .with_constant_fields(global_config.constant_fields(self))
+            .with_include_context(self.include_context())
+            .with_max_message_bytes(self.max_message_bytes());
+        EventFormat::Gelf(format)
+    }
+}
+
+impl AppenderConfig for SyslogLogConfig {
+    /// Create a non-blocking writer able to send messages to a syslog server
+    fn non_blocking(
+        &self,
+        appender_name: &str,
+        buffer_config: &BufferConfig,
+    ) -> io::Result<BufferedWriter> {
+        let writer = match self.transport {
+            SyslogTransport::Unix => SyslogWriter::unix(&self.path)?,
+            SyslogTransport::Udp => SyslogWriter::udp(&self.host, self.port)?,
+            SyslogTransport::Tcp => SyslogWriter::tcp(&self.host, self.port)?,
+        };
+        Ok(buffer::non_blocking(
+            WriteErrorReporter::new(writer, appender_name),
+            buffer_config,
+        ))
+    }
+
+    /// Events are always formatted as syslog messages
+    fn event_format(&self, _global_config: &GlobalLogConfig) -> EventFormat {
+        EventFormat::Syslog(SyslogFormat::new(self.facility))
+    }
+}
+
+impl AppenderConfig for TcpLogConfig {
+    /// Create a non-blocking writer sending lines over TCP, connecting lazily
+    fn non_blocking(
+        &self,
+        appender_name: &str,
+        buffer_config: &BufferConfig,
+    ) -> io::Result<BufferedWriter> {
+        let writer = TcpWriter::new(
+            self.host.clone(),
+            self.port,
+            Duration::from_millis(self.reconnect_backoff_ms),
+            Duration::from_millis(self.max_reconnect_backoff_ms),
+        );
+        let queued_lines = writer.queued_counter();
+        let mut buffered_writer = buffer::non_blocking(
+            WriteErrorReporter::new(writer, appender_name),
+            buffer_config,
+        );
+        buffered_writer.queued_lines = Some(queued_lines);
+        Ok(buffered_writer)
+    }
+}
+
+impl AppenderConfig for MemoryLogConfig {
+    /// Create a non-blocking writer able to keep logs in memory
+    fn non_blocking(
+        &self,
+        _appender_name: &str,
+        buffer_config: &BufferConfig,
+    ) -> io::Result<BufferedWriter> {
+        Ok(buffer::non_blocking(self.writer.clone(), buffer_config))
+    }
+}
+
+impl AppenderConfig for RingBufferLogConfig {
+    /// Create a non-blocking writer keeping the most recent logs in memory
+    fn non_blocking(
+        &self,
+        _appender_name: &str,
+        buffer_config: &BufferConfig,
+    ) -> io::Result<BufferedWriter> {
+        let writer = self.writer.with_capacity(self.capacity);
+        Ok(buffer::non_blocking(writer, buffer_config))
+    }
+}
+
+impl AppenderConfig for WriterLogConfig {
+    /// Create a non-blocking writer able to write logs to the writer of the application
+    fn non_blocking(
+        &self,
+        _appender_name: &str,
+        buffer_config: &BufferConfig,
+    ) -> io::Result<BufferedWriter> {
+        Ok(buffer::non_blocking(self.writer.clone(), buffer_config))
+    }
+}
+
+#[cfg(feature = "webhook")]
+impl AppenderConfig for WebhookLogConfig {
+    /// Create a non-blocking writer able to post messages to a webhook
+    fn non_blocking(
+        &self,
+        _appender_name: &str,
+        buffer_config: &BufferConfig,
+    ) -> io::Result<BufferedWriter> {
+        let writer = WebhookWriter::new(self.url.clone(), self.rate_limit)?;
+        Ok(buffer::non_blocking(writer, buffer_config))
+    }
+
+    /// Events are always rendered with the message template
+    fn event_format(&self, global_config: &GlobalLogConfig) -> EventFormat {
+        let format = WebhookFormat::new(self.template.clone());
+        EventFormat::Webhook(format.with_constant_fields(global_config.constant_fields(self)))
+    }
+}
+
+/// Configuration of an appender writing formatted events, if any
+fn appender_config(appender: &AppenderLogConfig) -> Option<&dyn AppenderConfig> {
+    match appender {
+        AppenderLogConfig::Console(appender) => Some(appender),
+        AppenderLogConfig::File(appender) => Some(appender),
+        AppenderLogConfig::Gelf(appender) => Some(appender),
+        AppenderLogConfig::Syslog(appender) => Some(appender),
+        AppenderLogConfig::Tcp(appender) => Some(appender),
+        AppenderLogConfig::Memory(appender) => Some(appender),
+        AppenderLogConfig::RingBuffer(appender) => Some(appender),
+        AppenderLogConfig::Writer(appender) => Some(appender),
+        AppenderLogConfig::LogCrate(_) => None,
+        #[cfg(target_os = "linux")]
+        AppenderLogConfig::Journald(_) => None,
+        #[cfg(windows)]
+        AppenderLogConfig::EventLog(_) => None,
+        #[cfg(feature = "otlp")]
+        AppenderLogConfig::Otlp(_) => None,
+        #[cfg(feature = "webhook")]
+        AppenderLogConfig::Webhook(appender) => Some(appender),
+    }
+}
+
+pub(crate) fn appender_filter(
+    config: &(impl LogConfig + ?Sized),
+    global_config: &GlobalLogConfig,
+) -> LogResult<AppenderFilter> {
+    Ok(AppenderFilter::new(env_filter(config, global_config)?)
+        .with_min_fields(config.min_fields())
+        .with_required_field(config.require_field().cloned())
+        .with_thread_name_prefix(config.thread_name_prefix().map(str::to_owned))
+        .with_levels(config.levels())
+        .with_sample(config.sample())
+        .with_max_per_second(config.max_per_second()))
+}
+
+fn env_filter(
+    config: &(impl LogConfig + ?Sized),
+    global_config: &GlobalLogConfig,
+) -> LogResult<EnvFilter> {
+    let directives = global_config.filter_directives(config);
+    EnvFilter::from_str(&directives).map_err(|source| LogError::InvalidFilter {
+        directive: directives.clone(),
+        source,
+    })
+}
+
+/// Global ceiling of a configuration, applied before the filters of the appenders
+fn global_max_level(config: &Log) -> LevelFilter {
+    // The configuration is validated, so that this cannot fail
+    config
+        .global
+        .max_level_filter()
+        .unwrap_or(LevelFilter::TRACE)
+}
+
+/// Most verbose level enabled by the filter of an appender
+fn effective_level(
+    config: &(impl LogConfig + ?Sized),
+    global_config: &GlobalLogConfig,
+) -> LevelFilter {
+    // The configuration is validated, so that neither can fail
+    let max_level = global_config
+        .max_level_filter()
+        .unwrap_or(LevelFilter::TRACE);
+    EnvFilter::from_str(&global_config.filter_directives(config))
+        .ok()
+        .and_then(|env_filter| Filter::<Registry>::max_level_hint(&env_filter))
+        .map_or(max_level, |hint| hint.min(max_level))
+}
+
+/// Subscriber of an appender, before being installed
+enum AppenderSetup {
+    Fmt(SubscriberSetup),
+    /// Console appender splitting its streams
+    Split {
+        stdout: SubscriberSetup,
+        stderr: SubscriberSetup,
+    },
+    LogCrate(NativeSetup<LogCrateSubscriber>),
+    #[cfg(target_os = "linux")]
+    Journald(NativeSetup<tracing_journald::Subscriber>),
+    #[cfg(windows)]
+    EventLog(NativeSetup<EventLogSubscriber>),
+    #[cfg(feature = "otlp")]
+    Otlp(NativeSetup<OtlpSubscriber>),
+}
+
+impl AppenderSetup {
+    fn new(
+        appender: &AppenderLogConfig,
+        global_config: &GlobalLogConfig,
+        writers: Writers,
+    ) -> LogResult<Self> {
+        if let AppenderLogConfig::LogCrate(config) = appender {
+            let subscriber = LogCrateSubscriber::new(config.logger.clone());
+            return NativeSetup::new(subscriber, config, global_config).map(Self::LogCrate);
+        }
+
+        #[cfg(target_os = "linux")]
+        if let AppenderLogConfig::Journald(config) = appender {
+            let mut subscriber =
+                tracing_journald::Subscriber::new()?.with_field_prefix(config.field_prefix.clone());
+            if let Some(syslog_identifier) = &config.syslog_identifier {
+                subscriber = subscriber.with_syslog_identifier(syslog_identifier.clone());
+            }
+            return NativeSetup::new(subscriber, config, global_config).map(Self::Journald);
+        }
+
+        #[cfg(windows)]
+        if let AppenderLogConfig::EventLog(config) = appender {
+            let subscriber = EventLogSubscriber::new(config.source.as_deref())?;
+            return NativeSetup::new(subscriber, config, global_config).map(Self::EventLog);
+        }
+
+        #[cfg(feature = "otlp")]
+        if let AppenderLogConfig::Otlp(config) = appender {
+            let subscriber = OtlpSubscriber::new(
+                &config.endpoint,
+                config.headers.clone(),
+                config.service_name.clone(),
+            );
+            return NativeSetup::new(subscriber, config, global_config).map(Self::Otlp);
+        }
+
+        let (Some(config), Some(writer)) = (appender_config(appender), writers.writer) else {
+            unreachable!("formatted appenders always have a writer");
+        };
+        let mut setup = SubscriberSetup::from_appender(config, global_config, writer)?;
+
+        let Some(error_writer) = writers.error_writer else {
+            return Ok(Self::Fmt(setup));
+        };
+        let mut error_setup = SubscriberSetup::from_appender(config, global_config, error_writer)?;
+        // Both streams are timed together, as a single appender
+        error_setup.latency = setup.latency.clone();
+        setup.filter = setup.filter.with_min_level(Some(Level::INFO));
+        error_setup.filter = error_setup.filter.with_max_level(LevelFilter::WARN);
+        Ok(Self::Split {
+            stdout: setup,
+            stderr: error_setup,
+        })
+    }
+
+    /// Cells of the `EnvFilter` of the subscribers, which all share the same directives
+    fn filter_cells(&self) -> Vec<EnvFilterCell> {
+        match self {
+            AppenderSetup::Fmt(setup) => vec![setup.filter.env_filter_cell()],
+            AppenderSetup::Split { stdout, stderr } => vec![
+                stdout.filter.env_filter_cell(),
+                stderr.filter.env_filter_cell(),
+            ],
+            AppenderSetup::LogCrate(setup) => vec![setup.filter.env_filter_cell()],
+            #[cfg(target_os = "linux")]
+            AppenderSetup::Journald(setup) => vec![setup.filter.env_filter_cell()],
+            #[cfg(windows)]
+            AppenderSetup::EventLog(setup) => vec![setup.filter.env_filter_cell()],
+            #[cfg(feature = "otlp")]
+            AppenderSetup::Otlp(setup) => vec![setup.filter.env_filter_cell()],
+        }
+    }
+
+    /// Latencies of the subscribers, when profiling
+    fn latency(&self) -> Option<LatencyHistogram> {
+        match self {
+            AppenderSetup::Fmt(setup) => setup.latency.clone(),
+            AppenderSetup::Split { stdout, .. } => stdout.latency.clone(),
+            AppenderSetup::LogCrate(setup) => setup.latency.clone(),
+            #[cfg(target_os = "linux")]
+            AppenderSetup::Journald(setup) => setup.latency.clone(),
+            #[cfg(windows)]
+            AppenderSetup::EventLog(setup) => setup.latency.clone(),
+            #[cfg(feature = "otlp")]
+            AppenderSetup::Otlp(setup) => setup.latency.clone(),
+        }
+    }
+
+    fn into_subscriber<C>(self) -> BoxedSubscriber<C>
+    where
+        C: Collect + for<'a> LookupSpan<'a> + 'static,
+    {
+        match self {
+            AppenderSetup::Fmt(setup) => Box::new(setup.into_subscriber()),
+            AppenderSetup::Split { stdout, stderr } => {
+                Box::new(stdout.into_subscriber().and_then(stderr.into_subscriber()))
+            }
+            AppenderSetup::LogCrate(setup) => Box::new(setup.into_subscriber()),
+            #[cfg(target_os = "linux")]
+            AppenderSetup::Journald(setup) => Box::new(setup.into_subscriber()),
+            #[cfg(windows)]
+            AppenderSetup::EventLog(setup) => Box::new(setup.into_subscriber()),
+            #[cfg(feature = "otlp")]
+            AppenderSetup::Otlp(setup) => Box::new(setup.into_subscriber()),
+        }
+    }
+}
+
+/// Subscriber of an appender handling events by itself rather than formatting them,
+/// keeping their structure
+struct NativeSetup<S> {
+    subscriber: S,
+    filter: AppenderFilter,
+    latency: Option<LatencyHistogram>,
+}
+
+impl<S> NativeSetup<S> {
+    fn new(
+        subscriber: S,
+        config: &(impl LogConfig + ?Sized),
+        global_config: &GlobalLogConfig,
+    ) -> LogResult<Self> {
+        let filter = appender_filter(config, global_config)?;
+        Ok(Self {
+            subscriber,
+            filter,
+            latency: global_config.profiling.then(LatencyHistogram::new),
+        })
+    }
+
+    fn into_subscriber<C>(self) -> Filtered<TimedSubscriber<S>, AppenderFilter, C>
+    where
+        S: Subscribe<C>,
+        C: Collect + for<'a> LookupSpan<'a>,
+    {
+        TimedSubscriber::new(self.subscriber, self.latency).with_filter(self.filter)
+    }
+}
+
+struct SubscriberSetup {
+    writer: PrefixedWriter,
+    color: bool,
+    filter: AppenderFilter,
+    format: EventFormat,
+    fields: AppenderFields,
+    latency: Option<LatencyHistogram>,
+}
+
+impl SubscriberSetup {
+    fn new(
+        writer: PrefixedWriter,
+        color: bool,
+        filter: AppenderFilter,
+        format: EventFormat,
+        fields: AppenderFields,
+        latency: Option<LatencyHistogram>,
+    ) -> Self {
+        Self {
+            writer,
+            color,
+            filter,
+            format,
+            fields,
+            latency,
+        }
+    }
+
+    fn from_appender(
+        config: &(impl AppenderConfig + ?Sized),
+        global_config: &GlobalLogConfig,
+        writer: BufferSender,
+    ) -> LogResult<Self> {
+        let color = global_config.color(config);
+        let format = config.event_format(global_config);
+        let mut filter = appender_filter(config, global_config)?;
+        if let Some(level_range) = config.level_range() {
+            filter = filter
+                .with_max_level(level_range.most_verbose())
+                .with_min_level(level_range.least_verbose());
+        }
+
+        // The lines of JSON events cannot be prefixed without breaking them,
+        // so that the prefix is written as a field instead
+        let mut constant_fields = global_config.constant_fields(config);
+        let mut line_prefix = global_config.line_prefix(config).map(Arc::<str>::from);
+        if let EventFormat::Json(_) = format {
+            if let Some(line_prefix) = line_prefix.take() {
+                constant_fields.insert("line_prefix".to_owned(), line_prefix.to_string());
+            }
+        } else if !format.is_text() {
+            line_prefix = None;
+        }
+        // The other formats write each event as a single payload, which a
+        // summary of repeats cannot be added to
+        let dedup_window = config
+            .dedup_window()
+            .filter(|_| format.is_text() || matches!(format, EventFormat::Json(_)));
+
+        let fields = AppenderFields::new(
+            config.message_from_field().map(str::to_owned),
+            global_config.version(),
+            config
+                .reserved_fields()
+                .unwrap_or(global_config.reserved_fields),
+        )
+        .with_redact_fields(config.redact_fields().to_vec())
+        .with_field_names(global_config.field_names(config))
+        .with_constant_fields(constant_fields)
+        .with_include_context(config.include_context())
+        .with_otel_ids(config.otel_ids())
+        .with_max_message_bytes(config.max_message_bytes())
+        .with_max_field_value_bytes(global_config.max_field_value_bytes(config))
+        .with_max_line_bytes(
+            (global_config.format(config) == LogFormat::Ndjson)
+                .then(|| config.max_line_bytes())
+                .flatten(),
+        )
+        .with_flatten_spans(config.flatten_spans())
+        .with_dedup_window(dedup_window);
+
+        let latency = global_config.profiling.then(LatencyHistogram::new);
+
+        Ok(SubscriberSetup::new(
+            PrefixedWriter::new(writer, line_prefix),
+            color,
+            filter,
+            format,
+            fields,
+            latency,
+        ))
+    }
+
+    fn into_subscriber<C>(self) -> FilteredSubscriber<C>
+    where
+        C: Collect + for<'a> LookupSpan<'a>,
+    {
+        let span_fields_recorder = SpanFieldsRecorder::new(self.fields.flattens_spans());
+        let subscriber = tracing_subscriber::fmt::subscriber()
+            .with_ansi(self.color)
+            .with_writer(self.writer)
+            .fmt_fields(self.fields)
+            .event_format(self.format)
+            .and_then(span_fields_recorder);
+        TimedSubscriber::new(subscriber, self.latency).with_filter(self.filter)
+    }
+}
+
+#[derive(Default)]
+struct Subscribers {
+    subscribers: Vec<AppenderSetup>,
+    appender_writers: AppenderWriters,
+    /// Writers reused from the previous configuration
+    reused_writers: Vec<WriterKey>,
+    writers: Vec<Writers>,
+    filter_cells: Vec<Vec<EnvFilterCell>>,
+    latencies: Vec<Option<LatencyHistogram>>,
+    senders: Vec<BufferSender>,
+    /// Appenders which failed to initialize, along with the reason
+    failures: Vec<(String, LogError)>,
+    config: Log,
+}
+
+impl Subscribers {
+    /// Build the subscribers of a configuration, taking the writers of the
+    /// previous configuration whose appenders are unchanged
+    ///
+    /// Appenders which fail to initialize are removed from the configuration
+    /// and recorded as failures. The build only fails when no appender could be
+    /// initialized, in which case the previous writers are left untouched.
+    ///
+    /// Appenders disabled by the environment are removed from the configuration
+    /// without opening anything, the default console appender being used if
+    /// every appender is disabled.
+    fn new(mut log: Log, previous_writers: &mut AppenderWriters) -> LogResult<Self> {
+        let len = log.configs.appenders.len();
+
+        let mut subscribers = Subscribers {
+            subscribers: Vec::with_capacity(len),
+            appender_writers: HashMap::with_capacity(len),
+            reused_writers: Vec::new(),
+            writers: Vec::with_capacity(len),
+            filter_cells: Vec::with_capacity(len),
+            latencies: Vec::with_capacity(len),
+            senders: Vec::with_capacity(len),
+            failures: Vec::new(),
+            config: Log::default(),
+        };
+
+        let mut appenders = mem::take(&mut log.configs.appenders);
+        appenders.retain(|_, appender| appender.log_config().is_enabled());
+        if appenders.is_empty() && len > 0 {
+            appenders.insert(
+                "stdout".into(),
+                AppenderLogConfig::Console(ConsoleLogConfig::default()),
+            );
+        }
+
+        for (name, mut appender) in appenders {
+            match subscribers.push_appender(&name, &mut appender, &log.global, previous_writers) {
+                Ok(()) => {
+                    log.configs.appenders.insert(name, appender);
+                }
+                Err(error) => subscribers.failures.push((name, error)),
+            }
+        }
+
+        if log.configs.appenders.is_empty() && !subscribers.failures.is_empty() {
+            subscribers.restore_writers(previous_writers);
+            let (_, error) = subscribers.failures.swap_remove(0);
+            return Err(error);
+        }
+
+        subscribers.config = log;
+        Ok(subscribers)
+    }
+
+    /// Give back the writers reused from the previous configuration, when the
+    /// new one is not applied
+    fn restore_writers(&mut self, previous_writers: &mut AppenderWriters) {
+        for key in self.reused_writers.drain(..) {
+            if let Some(appender_writer) = self.appender_writers.remove(&key) {
+                previous_writers.insert(key, appender_writer);
+            }
+        }
+    }
+
+    /// Create the writers of an appender
+    ///
+    /// Appenders are identified by their name, or by their destination for
+    /// those writing to a file, so that a renamed appender gets a new writer.
+    fn writers(
+        &mut self,
+        name: &str,
+        appender: &mut AppenderLogConfig,
+        buffer_config: &BufferConfig,
+        previous_writers: &mut AppenderWriters,
+    ) -> LogResult<Writers> {
+        let Some(config) = appender_config(appender) else {
+            return Ok(Writers::default());
+        };
+        let key = match config.destination()? {
+            Some(destination) => WriterKey::Destination(destination),
+            None => WriterKey::Appender(name.to_owned()),
+        };
+        let writer = self.writer(name, key, appender, buffer_config, previous_writers)?;
+
+        let error_writer = match appender {
+            AppenderLogConfig::Console(config) if config.splits_streams() => {
+                let mut stderr = AppenderLogConfig::Console(ConsoleLogConfig {
+                    target: ConsoleTarget::Stderr,
+                    split_streams: false,
+                    ..config.clone()
+                });
+                let key = WriterKey::ErrorStream(name.to_owned());
+                Some(self.writer(name, key, &mut stderr, buffer_config, previous_writers)?)
+            }
+            _ => None,
+        };
+
+        Ok(Writers {
+            writer: Some(writer),
+            error_writer,
+        })
+    }
+
+    /// Create a writer, unless another appender already writes to the same
+    /// destination (in which case the writing mode of the first appender is
+    /// used) or the appender is unchanged since the previous configuration
+    fn writer(
+        &mut self,
+        name: &str,
+        key: WriterKey,
+        appender: &mut AppenderLogConfig,
+        buffer_config: &BufferConfig,
+        previous_writers: &mut AppenderWriters,
+    ) -> LogResult<BufferSender> {
+        if let Some(appender_writer) = self.appender_writers.get(&key) {
+            return Ok(appender_writer.writer.clone());
+        }
+
+        let appender_writer = match previous_writers.entry(key.clone()) {
+            Entry::Occupied(entry)
+                if entry.get().config == writer_config(appender)
+                    && entry.get().buffer_config == *buffer_config =>
+            {
+                let previous = entry.remove();
+                // Keep the state of the previous configuration, such as captured
+                // lines, along with the new options
+                let options = appender.options_mut().clone();
+                *appender = previous.config.clone();
+                *appender.options_mut() = options;
+                self.reused_writers.push(key.clone());
+                previous
+            }
+            _ => {
+                let config = appender_config(appender).expect("formatted appenders have a writer");
+                let BufferedWriter {
+                    writer,
+                    worker_guard,
+                    queued_lines,
+                    synced_file,
+                } = config.non_blocking(name, buffer_config)?;
+                AppenderWriter {
+                    config: writer_config(appender),
+                    buffer_config: buffer_config.clone(),
+                    writer,
+                    worker_guard: Arc::new(worker_guard),
+                    queued_lines,
+                    synced_file,
+                }
+            }
+        };
+
+        let writer = appender_writer.writer.clone();
+        self.senders.push(writer.clone());
+        self.appender_writers.insert(key, appender_writer);
+        Ok(writer)
+    }
+
+    /// Report the lines dropped by the writers, unless they block without
+    /// timeout when their buffer is full
+    fn dropped_lines_reporter(&mut self) -> Option<DroppedLinesReporter> {
+        let senders = mem::take(&mut self.senders);
+        let buffer = &self.config.global.buffer;
+        (buffer.mode == BufferMode::Lossy || buffer.blocking_timeout().is_some())
+            .then(|| DroppedLinesReporter::new(senders, DROPPED_LINES_REPORT_INTERVAL))
+    }
+
+    fn push_appender(
+        &mut self,
+        name: &str,
+        appender: &mut AppenderLogConfig,
+        global_config: &GlobalLogConfig,
+        previous_writers: &mut AppenderWriters,
+    ) -> LogResult<()> {
+        let writers = self.writers(name, appender, &global_config.buffer, previous_writers)?;
+        let subscriber = AppenderSetup::new(appender, global_config, writers.clone())?;
+        self.writers.push(writers);
+        self.filter_cells.push(subscriber.filter_cells());
+        self.latencies.push(subscriber.latency());
+        self.subscribers.push(subscriber);
+        Ok(())
+    }
+
+    /// Install the collector as the global dispatcher, returning it
+    fn set_global_dispatch(
+        collector: impl Into<Dispatch>,
+        bridge_log: bool,
+    ) -> LogResult<Dispatch> {
+        let dispatch = collector.into();
+        dispatch::set_global_default(dispatch.clone())?;
+
+        // Filter level for `tracing_log` is global and cannot be reconfigured,
+        // so we inline the `init()` method to keep the default level.
+        // Another logger may have been installed by the rest of the application,
+        // in which case its records are not forwarded.
+        if bridge_log && tracing_log::LogTracer::init().is_err() {
+            warn!("A `log` logger is already installed, its records are not bridged");
+        }
+        Ok(dispatch)
+    }
+
+    fn into_components<C>(self) -> (AppenderWriters, Vec<BoxedSubscriber<C>>)
+    where
+        C: Collect + for<'a> LookupSpan<'a> + 'static,
+    {
+        let subscribers = self
+            .subscribers
+            .into_iter()
+            .map(AppenderSetup::into_subscriber)
+            .collect();
+
+        (self.appender_writers, subscribers)
+    }
+
+    /// Install the subscribers globally, returning the guard of their appenders
+    fn build<S>(self, base_collector: BaseCollector<S>) -> LogResult<LogGuard<S>>
+    where
+        S: Subscribe<Registry> + Send + Sync,
+    {
+        let bridge_log = self.config.global.bridge_log;
+        let log_guard = self.build_local(base_collector);
+        Self::set_global_dispatch(log_guard.dispatch(), bridge_log)?;
+        Ok(log_guard)
+    }
+
+    /// Build the guard of the subscribers, whose dispatcher is not installed
+    /// globally, so that it can be entered on some threads only
+    fn build_local<S>(mut self, base_collector: BaseCollector<S>) -> LogGuard<S>
+    where
+        S: Subscribe<Registry> + Send + Sync,
+    {
+        let dropped_lines_reporter = self.dropped_lines_reporter();
+        write_error::set_policy(self.config.global.on_appender_error);
+        let config = mem::take(&mut self.config);
+        let writers = mem::take(&mut self.writers);
+        let filter_cells = mem::take(&mut self.filter_cells);
+        let latencies = mem::take(&mut self.latencies);
+        let failures = mem::take(&mut self.failures);
+        let max_level = global_max_level(&config);
+        let (appender_writers, subscribers) = self.into_components();
+        let (collector, subscriber_handle) = base_collector.with_reloadable(subscribers, max_level);
+        let dispatch = Dispatch::new(collector);
+
+        let mut state = LogState {
+            dispatch,
+            subscriber_handle,
+            appender_writers,
+            dropped_lines_reporter,
+            writers,
+            filter_cells,
+            latencies,
+            config,
+            recent_errors: RecentErrors::default(),
+            failed_appenders: Vec::new(),
+            config_source: ConfigSource::File,
+            reload_callback: None,
+            reload_outcome: None,
+            elevations: HashMap::new(),
+            elevation_timer: None,
+        };
+        state.report_failures(failures);
+        LogGuard::new(state)
+    }
+
+    /// Install the subscribers directly, without any reload indirection
+    fn build_static<S>(mut self, base_collector: BaseCollector<S>) -> LogResult<Vec<WorkerGuard>>
+    where
+        S: Subscribe<Registry> + Send + Sync,
+    {
+        // Static logging lasts as long as the process, and so does the reporter
+        mem::forget(self.dropped_lines_reporter());
+
+        let bridge_log = self.config.global.bridge_log;
+        write_error::set_policy(self.config.global.on_appender_error);
+        let failures = mem::take(&mut self.failures);
+        let invalid_level_from_env = self.config.global.invalid_level_from_env.take();
+        let (dispatch, worker_guards) = self.static_dispatch(base_collector);
+        Self::set_global_dispatch(dispatch, bridge_log)?;
+
+        for (appender, error) in failures {
+            warn!(%appender, %error, "Skipping appender which failed to initialize");
+        }
+        if let Some(error) = invalid_level_from_env {
+            let error = LogError::InvalidEnv(error);
+            warn!(error = %error_chain(&error), "Ignoring the `RUST_LOG` environment variable");
+        }
+        Ok(worker_guards)
+    }
+
+    /// Dispatcher of the subscribers stacked directly on the base collector,
+    /// along with the worker guards of their writers
+    fn static_dispatch<S>(self, base_collector: BaseCollector<S>) -> (Dispatch, Vec<WorkerGuard>)
+    where
+        S: Subscribe<Registry> + Send + Sync,
+    {
+        let max_level = global_max_level(&self.config);
+        let (appender_writers, subscribers) = self.into_components();
+        let dispatch = Dispatch::new(base_collector.with(max_level).with(subscribers));
+        (dispatch, into_worker_guards(appender_writers))
+    }
+}
+
+impl TryFrom<Log> for Subscribers {
+    type Error = LogError;
+
+    fn try_from(log: Log) -> Result<Self, Self::Error> {
+        Subscribers::new(log, &mut AppenderWriters::new())
+    }
+}
+
+/// Parse a configuration, attaching to errors the path of the file it was read from
+fn parse_log(file_contents: &str, data_dir: &Path, path: Option<&Path>) -> LogResult<Log> {
+    let config_dir = path.and_then(Path::parent).unwrap_or(data_dir);
+    Log::parse_in(file_contents, config_dir, data_dir).map_err(|source| match path {
+        Some(path) => LogError::ConfigFile {
+            path: path.to_owned(),
+            source: Box::new(source),
+        },
+        None => source,
+    })
+}
+
+fn read_config(path: &Path) -> LogResult<String> {
+    fs::read_to_string(path).map_err(|source| LogError::ReadConfig {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+fn build_appenders(
+    file_contents: &str,
+    data_dir: &Path,
+    path: Option<&Path>,
+) -> LogResult<Subscribers> {
+    let log = parse_log(file_contents, data_dir, path)?;
+    Subscribers::try_from(log)
+}
+
+/// Build the appenders of the fallback configuration, or the default stdout
+/// appender if there is none
+fn build_fallback_appenders(fallback: Option<Log>, data_dir: &Path) -> LogResult<Subscribers> {
+    match fallback {
+        // Resolving the fallback reads `RUST_LOG` as well
+        Some(fallback) => Subscribers::try_from(fallback.resolve(data_dir)?),
+        None => build_default_appenders(),
+    }
+}
+
+fn build_default_appenders() -> LogResult<Subscribers> {
+    let mut global = GlobalLogConfig::default();
+    global.read_level_from_env()?;
+    Subscribers::try_from(Log {
+        global,
+        configs: LogConfigs {
+            appenders: IndexMap::from([(
+                "stdout".into(),
+                AppenderLogConfig::Console(ConsoleLogConfig::default()),
+            )]),
+        },
+    })
+}
+
+/// Initialize logging, falling back to the default configuration if the given
+/// one is invalid
+///
+/// The fallback is reported by [`LogGuard::config_source`], so that callers can
+/// fail the startup or flag a degraded health instead.
+///
+/// The platform subscriber is installed below the appenders and is not affected
+/// by reloads. Use [`platform::no_op`] when there is none, and
+/// [`platform::compose`] to install several of them.
+///
+/// [`platform::no_op`]: crate::platform::no_op
+/// [`platform::compose`]: crate::platform::compose
+pub fn init_log<S>(
+    file_contents: &str,
+    data_dir: &Path,
+    platform_subscriber: S,
+) -> LogResult<LogGuard<S>>
+where
+    S: Subscribe<Registry> + Send + Sync,
+{
+    init_log_inner(
+        file_contents,
+        None,
+        data_dir,
+        None,
+        platform_subscriber,
+        false,
+    )
+}
+
+/// Reload handle of the logging initialized by [`try_init_log`], whatever its
+/// platform subscriber
+trait GlobalReload: Send {
+    fn reload(&self, file_contents: &str, data_dir: &Path) -> LogResult<Log>;
+}
+
+impl<S> GlobalReload for ReloadHandle<S>
+where
+    S: Subscribe<Registry> + Send + Sync,
+{
+    fn reload(&self, file_contents: &str, data_dir: &Path) -> LogResult<Log> {
+        ReloadHandle::reload(self, file_contents, data_dir)
+    }
+}
+
+static GLOBAL_RELOAD: Mutex<Option<Box<dyn GlobalReload>>> = Mutex::new(None);
+
+/// Initialize logging as with [`init_log`], unless it is already initialized
+/// by this function, for instance in test binaries initializing it in each test
+///
+/// Once initialized, the given configuration is applied by reloading the
+/// current one, and [`LogError::AlreadyInitialized`] is returned, which the
+/// caller can ignore. The configuration is no longer applied once the guard
+/// of the first initialization is dropped.
+pub fn try_init_log<S>(
+    file_contents: &str,
+    data_dir: &Path,
+    platform_subscriber: S,
+) -> LogResult<LogGuard<S>>
+where
+    S: Subscribe<Registry> + Send + Sync + 'static,
+{
+    let mut global_reload = GLOBAL_RELOAD.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(handle) = &*global_reload {
+        handle.reload(file_contents, data_dir)?;
+        return Err(LogError::AlreadyInitialized);
+    }
+
+    let log_guard = init_log(file_contents, data_dir, platform_subscriber)?;
+    *global_reload = Some(Box::new(log_guard.reload_handle()));
+    Ok(log_guard)
+}
+
+/// Initialize logging as with [`init_log`], falling back to the given
+/// configuration rather than to the default one, such as a file in a known
+/// location when stdout is not collected
+///
+/// The fallback is resolved as with [`Log::resolve`], so that `RUST_LOG`
+/// still applies to it.
+pub fn init_log_with_fallback<S>(
+    file_contents: &str,
+    data_dir: &Path,
+    fallback: Log,
+    platform_subscriber: S,
+) -> LogResult<LogGuard<S>>
+where
+    S: Subscribe<Registry> + Send + Sync,
+{
+    init_log_inner(
+        file_contents,
+        None,
+        data_dir,
+        Some(fallback),
+        platform_subscriber,
+        false,
+    )
+}
+
+/// Initialize logging from a configuration file
+///
+/// An unreadable file is an error, while an invalid one falls back to the
+/// default configuration as with [`init_log`].
+pub fn init_log_from_path<S>(
+    path: &Path,
+    data_dir: &Path,
+    platform_subscriber: S,
+) -> LogResult<LogGuard<S>>
+where
+    S: Subscribe<Registry> + Send + Sync,
+{
+    let file_contents = read_config(path)?;
+    init_log_inner(
+        &file_contents,
+        Some(path),
+        data_dir,
+        None,
+        platform_subscriber,
+        false,
+    )
+}
+
+/// Initialize logging as with [`init_log`], keeping the process running
+/// without any appender if even the default configuration fails to initialize
+///
+/// The failure is reported by a single line on stderr, and by
+/// [`LogGuard::config_source`]. Logging can still be enabled by a later reload.
+pub fn init_log_lenient<S>(
+    file_contents: &str,
+    data_dir: &Path,
+    platform_subscriber: S,
+) -> LogResult<LogGuard<S>>
+where
+    S: Subscribe<Registry> + Send + Sync,
+{
+    init_log_inner(
+        file_contents,
+        None,
+        data_dir,
+        None,
+        platform_subscriber,
+        true,
+    )
+}
+
+fn init_log_inner<S>(
+    file_contents: &str,
+    path: Option<&Path>,
+    data_dir: &Path,
+    fallback: Option<Log>,
+    platform_subscriber: S,
+    lenient: bool,
+) -> LogResult<LogGuard<S>>
+where
+    S: Subscribe<Registry> + Send + Sync,
+{
+    let mut default_error = None;
+    let (subscribers, error) = match build_appenders(file_contents, data_dir, path) {
+        Ok(subscribers) => (subscribers, None),
+        Err(e) => match build_fallback_appenders(fallback, data_dir) {
+            Ok(subscribers) => (subscribers, Some(e)),
+            Err(error) if lenient => {
+                // Nothing can be logged, so the failure is written directly
+                let line = format!(
+                    "logging disabled, the default configuration failed: {}\n",
+                    error_chain(&error)
+                );
+                let _ = io::stderr().write_all(line.as_bytes());
+                default_error = Some(error);
+                (Subscribers::try_from(Log::default())?, Some(e))
+            }
+            Err(error) => return Err(error),
+        },
+    };
+
+    let base_collector = tracing_subscriber::registry().with(platform_subscriber);
+    let log_guard = subscribers.build(base_collector)?;
+
+    if let Some(default_error) = default_error {
+        let mut state = log_guard.state();
+        for error in error.iter().chain([&default_error]) {
+            state.recent_errors.push(error);
+        }
+        state.config_source = ConfigSource::Disabled;
+    } else if let Some(error) = error {
+        warn!(%error, "Using default logging configuration");
+        let mut state = log_guard.state();
+        state.recent_errors.push(&error);
+        state.config_source = ConfigSource::DefaultFallback(Arc::new(error.into()));
+    }
+
+    log_startup_summary(&log_guard);
+    Ok(log_guard)
+}
+
+/// Log the active appenders through the freshly installed ones, if enabled
+///
+/// The event is emitted once the state is unlocked, so that handling it
+/// cannot wait for the state, for instance to report an appender failure.
+fn log_startup_summary<S>(log_guard: &LogGuard<S>) {
+    let appenders = {
+        let state = log_guard.state();
+        if !state.config.global.log_startup_summary {
+            return;
+        }
+        AppenderDiagnostics::from_config(&state.config)
+    };
+    info!(appenders = %AppenderSummary(&appenders), "Logging initialized");
+}
+
+/// Initialize logging without any appender, for libraries embedding it or
+/// test phases which must stay silent
+///
+/// No callsite is enabled, so that events are neither recorded nor formatted.
+/// Reloading the guard with a configuration enables its appenders.
+pub fn init_log_disabled<S>(platform_subscriber: S) -> LogResult<LogGuard<S>>
+where
+    S: Subscribe<Registry> + Send + Sync,
+{
+    let base_collector = tracing_subscriber::registry().with(platform_subscriber);
+    let log_guard = disabled_subscribers()?.build(base_collector)?;
+    log_guard.state().config_source = ConfigSource::Disabled;
+    Ok(log_guard)
+}
+
+/// Initialize logging without installing the global dispatcher, for the tests
+/// of other modules, which enter [`LogGuard::dispatch`] instead
+#[cfg(test)]
+pub(crate) fn init_log_local<S>(
+    file_contents: &str,
+    data_dir: &Path,
+    platform_subscriber: S,
+) -> LogResult<LogGuard<S>>
+where
+    S: Subscribe<Registry> + Send + Sync,
+{
+    let subscribers = Subscribers::try_from(parse_log(file_contents, data_dir, None)?)?;
+    Ok(subscribers.build_local(tracing_subscriber::registry().with(platform_subscriber)))
+}
+
+/// Subscribers without any appender, whose `OFF` ceiling disables every
+/// callsite rather than relying on the level hint of an empty set
+fn disabled_subscribers() -> LogResult<Subscribers> {
+    let mut log = Log::default();
+    log.global.max_level = Some(LevelFilter::OFF.to_string());
+    Subscribers::try_from(log)
+}
+
+/// Initialize logging without the reload machinery, for programs which never
+/// reload their configuration and want to avoid its overhead on every event
+pub fn init_log_static<S>(
+    file_contents: &str,
+    data_dir: &Path,
+    platform_subscriber: S,
+) -> LogResult<Vec<WorkerGuard>>
+where
+    S: Subscribe<Registry> + Send + Sync,
+{
+    let (subscribers, error) = match build_appenders(file_contents, data_dir, None) {
+        Ok(subscribers) => (subscribers, None),
+        Err(e) => (build_default_appenders()?, Some(e)),
+    };
+
+    let base_collector = tracing_subscriber::registry().with(platform_subscriber);
+    let worker_guards = subscribers.build_static(base_collector)?;
+
+    if let Some(error) = error {
+        warn!(%error, "Using default logging configuration");
+    }
+
+    Ok(worker_guards)
+}
+
+/// Programmatic alternative to a TOML configuration, for host applications
+/// which already have their configuration in another format
+///
+/// The resulting configuration goes through the same pipeline as [`init_log`],
+/// except that errors are returned instead of falling back to defaults.
+#[derive(Debug, Default)]
+pub struct LogBuilder {
+    log: Log,
+}
+
+impl LogBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn global(mut self, global: GlobalLogConfig) -> Self {
+        self.log.global = global;
+        self
+    }
+
+    pub fn appender(mut self, name: impl Into<String>, appender: AppenderLogConfig) -> Self {
+        self.log.configs.appenders.insert(name.into(), appender);
+        self
+    }
+
+    pub fn console(self, config: ConsoleLogConfig) -> Self {
+        self.unnamed_appender(AppenderLogConfig::Console(config))
+    }
+
+    pub fn file(self, config: FileLogConfig) -> Self {
+        self.unnamed_appender(AppenderLogConfig::File(config))
+    }
+
+    /// Add an appender writing to a writer of the application, such as a pipe,
+    /// which no configuration file can describe
+    ///
+    /// The writer is used from the thread of the appender, as for the other kinds.
+    pub fn writer_appender(
+        self,
+        name: impl Into<String>,
+        writer: impl Write + Send + 'static,
+        options: AppenderOptions,
+    ) -> Self {
+        let config = WriterLogConfig {
+            options,
+            writer: SharedWriter::new(writer),
+        };
+        self.appender(name, AppenderLogConfig::Writer(config))
+    }
+
+    /// Add an appender sending each formatted event to a bounded channel, such
+    /// as the live view of an admin interface, see [`ChannelWriter`]
+    pub fn channel_appender(
+        self,
+        name: impl Into<String>,
+        sender: mpsc::SyncSender<String>,
+        options: AppenderOptions,
+    ) -> Self {
+        self.writer_appender(name, ChannelWriter::new(sender), options)
+    }
+
+    /// Add an appender named after its kind, with a numeric suffix if the name is taken
+    fn unnamed_appender(self, appender: AppenderLogConfig) -> Self {
+        let kind = appender.kind();
+        let mut name = kind.to_owned();
+        let mut index = 1;
+        while self.log.configs.appenders.contains_key(&name) {
+            index += 1;
+            name = format!("{kind}{index}");
+        }
+        self.appender(name, appender)
+    }
+
+    /// Resolve the configuration as if it had been read from a file
+    pub fn into_log(self, data_dir: &Path) -> LogResult<Log> {
+        self.log.resolve(data_dir)
+    }
+
+    pub fn build<S>(self, data_dir: &Path, platform_subscriber: S) -> LogResult<LogGuard<S>>
+    where
+        S: Subscribe<Registry> + Send + Sync,
+    {
+        let subscribers = Subscribers::try_from(self.into_log(data_dir)?)?;
+        let base_collector = tracing_subscriber::registry().with(platform_subscriber);
+        subscribers.build(base_collector)
+    }
+}
+
+/// Build a dispatcher from a logging configuration, without installing it globally
+///
+/// The returned worker guards must be kept alive as long as the dispatcher is used.
+pub fn build_dispatch(log: Log) -> LogResult<(Dispatch, Vec<WorkerGuard>)> {
+    let max_level = log.global.max_level_filter()?;
+    let (appender_writers, subscribers) = Subscribers::try_from(log)?.into_components();
+    let collector = tracing_subscriber::registry()
+        .with(max_level)
+        .with(subscribers);
+    Ok((
+        Dispatch::new(collector),
+        into_worker_guards(appender_writers),
+    ))
+}
+
+/// Run a closure with a scoped dispatcher built from the given configuration,
+/// for instance to route the logs of a tenant to its own appenders
+///
+/// Events emitted outside of the closure still use the global dispatcher.
+/// The appenders are flushed when the closure returns.
+pub fn with_tenant_config<T>(log: Log, f: impl FnOnce() -> T) -> LogResult<T> {
+    let (dispatch, _worker_guards) = build_dispatch(log)?;
+    Ok(dispatch::with_default(&dispatch, f))
+}
+
+/// Spawn a thread using the dispatcher of the current thread, so that events
+/// emitted by the new thread within a scoped configuration reach the same appenders
+pub fn spawn_with_logging<F, T>(f: F) -> thread::JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let dispatch = dispatch::get_default(Dispatch::clone);
+    thread::spawn(move || dispatch::with_default(&dispatch, f))
+}
+
+/// Reload the logging configuration, returning the guard along with the
+/// configuration which was replaced
+#[deprecated(note = "use `LogGuard::reload`, which updates the guard in place")]
+pub fn reload_log<S>(
+    file_contents: &str,
+    data_dir: &Path,
+    mut log_guard: LogGuard<S>,
+) -> LogResult<(LogGuard<S>, Log)>
+where
+    S: Subscribe<Registry> + Send + Sync,
+{
+    let previous_config = log_guard.reload(file_contents, data_dir);
+    Ok((log_guard, previous_config))
+}
+
+/// Reload the logging configuration from a file, returning the guard along
+/// with the configuration which was replaced
+#[deprecated(note = "use `LogGuard::reload_from_path`, which updates the guard in place")]
+pub fn reload_log_from_path<S>(
+    path: &Path,
+    data_dir: &Path,
+    mut log_guard: LogGuard<S>,
+) -> LogResult<(LogGuard<S>, Log)>
+where
+    S: Subscribe<Registry> + Send + Sync,
+{
+    let previous_config = log_guard.reload_from_path(path, data_dir)?;
+    Ok((log_guard, previous_config))
+}
+
+/// Reload the logging configuration by applying a sparse configuration onto
+/// the current one, returning the guard along with the configuration which
+/// was replaced
+#[deprecated(note = "use `LogGuard::merge_reload`, which updates the guard in place")]
+pub fn merge_reload_log<S>(
+    patch_contents: &str,
+    data_dir: &Path,
+    mut log_guard: LogGuard<S>,
+) -> LogResult<(LogGuard<S>, Log)>
+where
+    S: Subscribe<Registry> + Send + Sync,
+{
+    let previous_config = log_guard.merge_reload(patch_contents, data_dir);
+    Ok((log_guard, previous_config))
+}
+
+/// Reload the logging configuration from an async context, without blocking
+/// the executor while the appenders are built
+///
+/// Parsing the configuration and building its appenders, which opens files and
+/// connects sockets, runs on the blocking thread pool of the current Tokio
+/// runtime without locking the guard. The subscribers are then swapped on the
+/// calling task, which is instant. The unchanged appenders keep their writers,
+/// shared with the current configuration while the new one is built; if another
+/// reload completes meanwhile, the last one to complete is applied.
+#[cfg(feature = "tokio")]
+pub async fn reload_log_async<S>(
+    file_contents: &str,
+    data_dir: &Path,
+    log_guard: &mut LogGuard<S>,
+) -> Log
+where
+    S: Subscribe<Registry> + Send + Sync + 'static,
+{
+    let (file_contents, data_dir) = (file_contents.to_owned(), data_dir.to_owned());
+    let (current_config, mut previous_writers) = {
+        let state = log_guard.state();
+        (state.config.clone(), state.appender_writers.clone())
+    };
+    let build = tokio::task::spawn_blocking(move || {
+        let log = parse_log(&file_contents, &data_dir, None);
+        if matches!(&log, Ok(log) if *log == current_config) {
+            return None;
+        }
+        // The writers which are not reused are dropped, their worker being
+        // stopped by the current configuration once replaced
+        Some(log.and_then(|log| Subscribers::new(log, &mut previous_writers)))
+    });
+    let subscribers = match build.await {
+        Ok(subscribers) => subscribers,
+        Err(error) => panic::resume_unwind(error.into_panic()),
+    };
+
+    let (previous_config, previous_writers) =
+        with_reload(&log_guard.state, |state| match subscribers {
+            Some(Ok(subscribers)) => {
+                let previous_writers = mem::take(&mut state.appender_writers);
+                (install_reload(Ok(subscribers), state), previous_writers)
+            }
+            Some(Err(error)) => (install_reload(Err(error), state), AppenderWriters::new()),
+            None => {
+                state.config_source = ConfigSource::File;
+                (state.config.clone(), AppenderWriters::new())
+            }
+        })
+        .0;
+
+    // Flush and close the writers of the removed or changed appenders off the
+    // executor, the others being shared with the new configuration
+    if !previous_writers.is_empty() {
+        let _ = tokio::task::spawn_blocking(move || drop(previous_writers)).await;
+    }
+    previous_config
+}
+
+/// Reload the configuration of the appenders, returning the configuration
+/// which was replaced, or the current one if it is kept
+///
+/// A configuration identical to the current one is not applied, so that
+/// touching a configuration file leaves the appenders untouched.
+fn reload_log_inner<S>(log: LogResult<Log>, state: &mut LogState<S>) -> Log
+where
+    S: Subscribe<Registry> + Send + Sync,
+{
+    if matches!(&log, Ok(log) if *log == state.config) {
+        state.config_source = ConfigSource::File;
+        return state.config.clone();
+    }
+
+    // Unchanged appenders keep their writers
+    let mut previous_writers = mem::take(&mut state.appender_writers);
+
+    let subscribers = log.and_then(|log| Subscribers::new(log, &mut previous_writers));
+    if subscribers.is_err() {
+        state.appender_writers = mem::take(&mut previous_writers);
+    }
+    let previous_config = install_reload(subscribers, state);
+
+    // Flush and close the writers of the removed or changed appenders, now unused
+    drop(previous_writers);
+
+    previous_config
+}
+
+/// Install the subscribers built for a reload, or keep the current ones if the
+/// configuration is invalid, returning the configuration which was replaced or
+/// the current one if it is kept
+fn install_reload<S>(subscribers: LogResult<Subscribers>, state: &mut LogState<S>) -> Log
+where
+    S: Subscribe<Registry> + Send + Sync,
+{
+    let subscribers = match subscribers {
+        Ok(subscribers) => subscribers,
+        Err(error) => {
+            warn!(%error, "Keeping the current logging configuration");
+            state.recent_errors.push(&error);
+            state.record_reload(Some(error));
+            return state.config.clone();
+        }
+    };
+
+    let (previous_config, failures) = state.install(subscribers);
+    // The levels of the new configuration are not restored when elevations end
+    state.elevations.clear();
+    state.config_source = ConfigSource::File;
+    state.report_failures(failures);
+    state.record_reload(None);
+    previous_config
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::Cell,
+        collections::BTreeMap,
+        io::Read,
+    };
+
+    use flate2::read::MultiGzDecoder;
+    use tracing_subscriber::subscribe::Identity;
+
+    use super::*;
+    use crate::{memory::MemoryWriter, platform, testing::temp_dir};
+
+    thread_local! {
+        /// Allocations of the current thread, counted by [`CountingAllocator`]
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Allocator counting the allocations of each thread, so that tests can
+    /// check that logging does not allocate
+    struct CountingAllocator;
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    /// Guard of a configuration whose dispatcher is not installed globally, so
+    /// that tests can run in parallel
+    fn local_guard(file_contents: &str, data_dir: &Path) -> LogGuard<Identity> {
+        init_log_local(file_contents, data_dir, platform::no_op())
+            .expect("the configuration is valid")
+    }
+
+    /// Emit events with the dispatcher of a guard, then wait for its appenders
+    /// to write them
+    fn emit<S>(log_guard: &LogGuard<S>, f: impl FnOnce()) {
+        dispatch::with_default(&log_guard.dispatch(), f);
+        assert!(log_guard.flush());
+    }
+
+    /// Lines written by a memory appender of a configuration
+    fn memory_lines(log: &Log, appender_name: &str) -> Vec<String> {
+        match &log.configs.appenders[appender_name] {
+            AppenderLogConfig::Memory(config) => config.writer.lines(),
+            _ => panic!("`{appender_name}` is not a memory appender"),
+        }
+    }
+
+    /// Workers of the writers of a guard, identified by their sender
+    fn workers<S>(log_guard: &LogGuard<S>) -> BTreeMap<WriterKey, BufferSender> {
+        log_guard
+            .state()
+            .appender_writers
+            .iter()
+            .map(|(key, writer)| (key.clone(), writer.writer.clone()))
+            .collect()
+    }
+
+    /// Whether two guards, or a guard at two points in time, use the same workers
+    fn same_workers(
+        workers: &BTreeMap<WriterKey, BufferSender>,
+        other_workers: &BTreeMap<WriterKey, BufferSender>,
+    ) -> bool {
+        workers.len() == other_workers.len()
+            && workers.iter().zip(other_workers).all(
+                |((key, sender), (other_key, other_sender))| {
+                    key == other_key && sender.same_worker(other_sender)
+                },
+            )
+    }
+
+    #[test]
+    fn file_appenders_share_the_writer_of_their_path() {
+        let data_dir = temp_dir("shared_writer");
+        let file_contents = r#"
+            [log.appenders.all]
+            kind = "file"
+            path = "app.log"
+
+            [log.appenders.warnings]
+            kind = "file"
+            path = "./app.log"
+            level = "warn"
+        "#;
+        let log = parse_log(file_contents, &data_dir, None).unwrap();
+
+        let (dispatch, worker_guards) = build_dispatch(log).unwrap();
+        assert_eq!(worker_guards.len(), 1);
+
+        dispatch::with_default(&dispatch, || warn!("written twice"));
+        drop(worker_guards);
+        let contents = fs::read_to_string(data_dir.join("app.log")).unwrap();
+        assert_eq!(contents.matches("written twice").count(), 2);
+    }
+
+    #[test]
+    fn message_from_field_replaces_a_missing_message() {
+        let data_dir = temp_dir("message_from_field");
+        let file_contents = r#"
+            [log.appenders.with_option]
+            kind = "memory"
+            format = "json"
+            message_from_field = "msg"
+
+            [log.appenders.without_option]
+            kind = "memory"
+            format = "json"
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        emit(&log_guard, || info!(msg = "from the field"));
+        let events: Vec<serde_json::Value> = log_guard
+            .captured_lines()
+            .iter()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["message"], "from the field");
+        assert!(events[0].get("msg").is_none());
+        assert!(events[1].get("message").is_none());
+        assert_eq!(events[1]["msg"], "from the field");
+    }
+
+    #[test]
+    fn static_appenders_write_to_their_file() {
+        let data_dir = temp_dir("static");
+        let file_contents = r#"
+            [log.appenders.file]
+            kind = "file"
+            path = "app.log"
+        "#;
+        let log = parse_log(file_contents, &data_dir, None).unwrap();
+        let base_collector = tracing_subscriber::registry().with(platform::no_op());
+
+        let subscribers = Subscribers::try_from(log).unwrap();
+        let (dispatch, worker_guards) = subscribers.static_dispatch(base_collector);
+        dispatch::with_default(&dispatch, || info!("static event"));
+        drop(worker_guards);
+
+        let contents = fs::read_to_string(data_dir.join("app.log")).unwrap();
+        assert!(contents.contains("static event"));
+    }
+
+    #[test]
+    fn version_tag_adds_the_version_to_events() {
+        let data_dir = temp_dir("version_tag");
+        let file_contents = r#"
+            [log]
+            version_tag = true
+
+            [log.appenders.memory]
+            kind = "memory"
+            format = "json"
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        emit(&log_guard, || info!("versioned"));
+        let lines = log_guard.captured_lines();
+        let event: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        let expected = env::var(crate::config::VERSION_TAG_ENV_VAR)
+            .unwrap_or_else(|_| env!("CARGO_PKG_VERSION").to_owned());
+        assert_eq!(event["version"], expected.as_str());
+    }
+
+    #[test]
+    fn min_fields_drops_the_events_with_fewer_fields() {
+        let data_dir = temp_dir("min_fields");
+        let file_contents = r#"
+            [log.appenders.memory]
+            kind = "memory"
+            min_fields = 2
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        emit(&log_guard, || {
+            info!("without fields");
+            info!(user = "alice", request = 7, "with two fields");
+        });
+        let lines = log_guard.captured_lines();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("with two fields"));
+    }
+
+    #[test]
+    fn directives_silence_a_module_in_one_appender_only() {
+        let data_dir = temp_dir("directives");
+        let file_contents = r#"
+            [log.appenders.quiet]
+            kind = "memory"
+            directives = "noisy=off"
+
+            [log.appenders.verbose]
+            kind = "memory"
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        emit(&log_guard, || {
+            info!(target: "noisy", "from the noisy module");
+            info!(target: "app", "from the application");
+        });
+        let config = log_guard.current_config();
+        let quiet = memory_lines(&config, "quiet");
+        assert_eq!(quiet.len(), 1);
+        assert!(quiet[0].contains("from the application"));
+        assert_eq!(memory_lines(&config, "verbose").len(), 2);
+    }
+
+    #[test]
+    fn tenant_configs_route_to_their_own_appenders() {
+        let data_dir = temp_dir("tenant_config");
+        let file_contents = r#"
+            [log.appenders.memory]
+            kind = "memory"
+        "#;
+        let first = parse_log(file_contents, &data_dir, None).unwrap();
+        let second = parse_log(file_contents, &data_dir, None).unwrap();
+        let default = crate::testing::init();
+
+        with_tenant_config(first.clone(), || info!("first tenant")).unwrap();
+        with_tenant_config(second.clone(), || info!("second tenant")).unwrap();
+        info!("no tenant");
+
+        let first_lines = memory_lines(&first, "memory");
+        assert_eq!(first_lines.len(), 1);
+        assert!(first_lines[0].contains("first tenant"));
+        let second_lines = memory_lines(&second, "memory");
+        assert_eq!(second_lines.len(), 1);
+        assert!(second_lines[0].contains("second tenant"));
+        assert_eq!(default.lines().len(), 1);
+        assert!(default.contains("no tenant"));
+    }
+
+    #[test]
+    fn diagnostics_bundle_gathers_every_section() {
+        let data_dir = temp_dir("diagnostics");
+        let file_contents = r#"
+            [log.appenders.memory]
+            kind = "memory"
+            level = "debug"
+        "#;
+        let mut log_guard = local_guard(file_contents, &data_dir);
+
+        emit(&log_guard, || info!("some activity"));
+        assert!(log_guard.try_reload("[log", &data_dir).is_err());
+
+        let bundle = log_guard.diagnostics().to_json().unwrap();
+        let bundle: serde_json::Value = serde_json::from_str(&bundle).unwrap();
+        assert_eq!(bundle["config"]["appenders"]["memory"]["kind"], "memory");
+        assert_eq!(bundle["appenders"][0]["name"], "memory");
+        assert_eq!(bundle["appenders"][0]["level"], "debug");
+        assert_eq!(bundle["recent_errors"].as_array().unwrap().len(), 1);
+        assert_eq!(bundle["writers"]["active_workers"], 1);
+    }
+
+    #[test]
+    fn set_global_changes_the_level_of_appenders_without_one() {
+        let data_dir = temp_dir("set_global");
+        let file_contents = r#"
+            [log]
+            level = "info"
+
+            [log.appenders.inherited]
+            kind = "memory"
+
+            [log.appenders.explicit]
+            kind = "memory"
+            level = "warn"
+        "#;
+        let mut log_guard = local_guard(file_contents, &data_dir);
+        let previous_workers = workers(&log_guard);
+
+        emit(&log_guard, || tracing::debug!("before the change"));
+        let mut global_config = log_guard.current_config().global;
+        global_config.level = "debug".to_owned();
+        log_guard.set_global(global_config).unwrap();
+        emit(&log_guard, || tracing::debug!("after the change"));
+
+        let config = log_guard.current_config();
+        let inherited = memory_lines(&config, "inherited");
+        assert_eq!(inherited.len(), 1);
+        assert!(inherited[0].contains("after the change"));
+        assert!(memory_lines(&config, "explicit").is_empty());
+        assert!(same_workers(&previous_workers, &workers(&log_guard)));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn journald_appender_sends_the_priority_and_fields() {
+        // Skipped where no journal is running, such as in most containers
+        if !Path::new("/run/systemd/journal/socket").exists() {
+            return;
+        }
+        let data_dir = temp_dir("journald");
+        let identifier = format!("tracing-reload-example-test-{}", std::process::id());
+        let file_contents = format!(
+            r#"
+            [log.appenders.journal]
+            kind = "journald"
+            syslog_identifier = "{identifier}"
+            "#
+        );
+        let log_guard = local_guard(&file_contents, &data_dir);
+
+        emit(&log_guard, || warn!(request_id = 42, "to the journal"));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let entry: serde_json::Value = loop {
+            let output = std::process::Command::new("journalctl")
+                .args(["--output=json", &format!("SYSLOG_IDENTIFIER={identifier}")])
+                .output()
+                .expect("journalctl runs");
+            if let Some(line) = String::from_utf8_lossy(&output.stdout).lines().next() {
+                break serde_json::from_str(line).unwrap();
+            }
+            assert!(Instant::now() < deadline, "the event reaches the journal");
+            thread::sleep(Duration::from_millis(100));
+        };
+        assert_eq!(entry["PRIORITY"], "4");
+        assert_eq!(entry["MESSAGE"], "to the journal");
+        assert_eq!(entry["F_REQUEST_ID"], "42");
+    }
+
+    #[test]
+    fn spawned_threads_keep_the_scoped_dispatcher() {
+        let data_dir = temp_dir("spawn_with_logging");
+        let file_contents = r#"
+            [log.appenders.memory]
+            kind = "memory"
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        emit(&log_guard, || {
+            spawn_with_logging(|| info!("from the spawned thread"))
+                .join()
+                .unwrap();
+        });
+        let lines = log_guard.captured_lines();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("from the spawned thread"));
+    }
+
+    #[test]
+    fn reserved_fields_follow_their_policy() {
+        let data_dir = temp_dir("reserved_fields");
+        let file_contents = r#"
+            [log.appenders.prefix]
+            kind = "memory"
+            format = "json"
+            reserved_fields = "prefix"
+
+            [log.appenders.drop]
+            kind = "memory"
+            format = "json"
+            reserved_fields = "drop"
+
+            [log.appenders.error]
+            kind = "memory"
+            format = "json"
+            reserved_fields = "error"
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        emit(&log_guard, || info!(level = "custom", "colliding field"));
+        let config = log_guard.current_config();
+        let event = |appender_name| -> serde_json::Value {
+            let lines = memory_lines(&config, appender_name);
+            assert_eq!(lines.len(), 1);
+            serde_json::from_str(&lines[0]).unwrap()
+        };
+        let prefixed = event("prefix");
+        assert_eq!(prefixed["level"], "INFO");
+        assert_eq!(prefixed["field.level"], "custom");
+        let dropped = event("drop");
+        assert_eq!(dropped["level"], "INFO");
+        assert!(dropped.get("field.level").is_none());
+        assert!(memory_lines(&config, "error").is_empty());
+    }
+
+    #[test]
+    fn captured_lines_gather_the_memory_appenders_in_order() {
+        let data_dir = temp_dir("captured_lines");
+        let file_contents = r#"
+            [log.appenders.errors]
+            kind = "memory"
+            level = "error"
+
+            [log.appenders.all]
+            kind = "memory"
+            format = "compact"
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        emit(&log_guard, || {
+            info!("first");
+            tracing::error!("second");
+        });
+        let lines = log_guard.captured_lines();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].contains("ERROR") && lines[0].contains("second"));
+        assert!(lines[1].contains("INFO") && lines[1].contains("first"));
+        assert!(lines[2].contains("ERROR") && lines[2].contains("second"));
+        assert!(lines.iter().all(|line| !line.ends_with('\n')));
+    }
+
+    #[test]
+    fn max_level_rejects_events_before_the_appender_filters() {
+        let data_dir = temp_dir("max_level");
+        let file_contents = r#"
+            [log]
+            max_level = "info"
+
+            [log.appenders.memory]
+            kind = "memory"
+            level = "trace"
+        "#;
+        let mut log_guard = local_guard(file_contents, &data_dir);
+        let max_level_hint =
+            |log_guard: &LogGuard<Identity>| log_guard.state().subscriber_handle.max_level_hint();
+        assert_eq!(max_level_hint(&log_guard), Some(LevelFilter::INFO));
+
+        emit(&log_guard, || {
+            tracing::debug!("above the ceiling");
+            info!("within the ceiling");
+        });
+        let lines = log_guard.captured_lines();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("within the ceiling"));
+
+        let mut global_config = log_guard.current_config().global;
+        global_config.max_level = None;
+        log_guard.set_global(global_config).unwrap();
+        assert_eq!(max_level_hint(&log_guard), Some(LevelFilter::TRACE));
+        emit(&log_guard, || tracing::debug!("without a ceiling"));
+        assert_eq!(log_guard.captured_lines().len(), 2);
+    }
+
+    #[test]
+    fn builder_matches_the_equivalent_file() {
+        let data_dir = temp_dir("builder");
+        let file_contents = r#"
+            [log]
+            level = "debug"
+
+            [log.appenders.console]
+            kind = "console"
+            format = "json"
+
+            [log.appenders.file]
+            kind = "file"
+            path = "app.log"
+            level = "warn"
+        "#;
+        let parsed = parse_log(file_contents, &data_dir, None).unwrap();
+
+        let built = LogBuilder::new()
+            .global(GlobalLogConfig {
+                level: "debug".to_owned(),
+                ..Default::default()
+            })
+            .console(ConsoleLogConfig {
+                options: AppenderOptions {
+                    format: Some(LogFormat::Json),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .file(FileLogConfig {
+                options: AppenderOptions {
+                    level: Some("warn".to_owned()),
+                    ..Default::default()
+                },
+                path: PathBuf::from("app.log"),
+                ..Default::default()
+            })
+            .into_log(&data_dir)
+            .unwrap();
+        assert_eq!(built, parsed);
+    }
+
+    #[test]
+    fn compressed_files_are_complete_once_the_workers_stop() {
+        let data_dir = temp_dir("compress");
+        let file_contents = r#"
+            [log.appenders.file]
+            kind = "file"
+            path = "app.log.gz"
+            mode = "append"
+            compress = true
+        "#;
+        // Each run appends its own gzip member
+        for message in ["first run", "second run"] {
+            let log = parse_log(file_contents, &data_dir, None).unwrap();
+            let (dispatch, worker_guards) = build_dispatch(log).unwrap();
+            dispatch::with_default(&dispatch, || info!("{message}"));
+            drop(worker_guards);
+        }
+
+        let file = File::open(data_dir.join("app.log.gz")).unwrap();
+        let mut contents = String::new();
+        MultiGzDecoder::new(file)
+            .read_to_string(&mut contents)
+            .unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("first run"));
+        assert!(lines[1].contains("second run"));
+    }
+
+    #[test]
+    fn redacted_fields_never_reach_the_output() {
+        let data_dir = temp_dir("redact_fields");
+        let file_contents = r#"
+            [log.appenders.redacted]
+            kind = "memory"
+            redact_fields = ["password", "token"]
+
+            [log.appenders.json]
+            kind = "memory"
+            format = "json"
+            redact_fields = ["password", "token"]
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        emit(&log_guard, || {
+            let _span = tracing::info_span!("request", token = "span-secret").entered();
+            info!(user = "alice", password = "event-secret", "logged in");
+        });
+        let lines = log_guard.captured_lines();
+        assert_eq!(lines.len(), 2);
+        for line in lines {
+            assert!(line.contains("alice"), "{line}");
+            assert!(line.contains("***"), "{line}");
+            assert!(!line.contains("secret"), "{line}");
+        }
+    }
+
+    #[test]
+    fn reload_callback_runs_with_the_guard_unlocked() {
+        let data_dir = temp_dir("reload_callback");
+        let file_contents = r#"
+            [log.appenders.memory]
+            kind = "memory"
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+        let reload_handle = log_guard.reload_handle();
+        let outcomes = Arc::new(Mutex::new(Vec::new()));
+        let recorded_outcomes = outcomes.clone();
+        let mut log_guard = log_guard.with_reload_callback(move |outcome| {
+            // Reading the guard from the callback would deadlock if it were locked
+            let status = reload_handle.status().unwrap();
+            let mut outcomes = recorded_outcomes.lock().unwrap();
+            outcomes.push((outcome.appenders, outcome.kept_current_config, status));
+        });
+
+        let new_contents = r#"
+            [log.appenders.memory]
+            kind = "memory"
+
+            [log.appenders.other]
+            kind = "memory"
+        "#;
+        log_guard.reload(new_contents, &data_dir);
+        assert!(log_guard.try_reload("[log", &data_dir).is_err());
+
+        let outcomes = outcomes.lock().unwrap();
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!((outcomes[0].0, outcomes[0].1), (2, false));
+        assert!(outcomes[0].2.contains("other"));
+        assert_eq!((outcomes[1].0, outcomes[1].1), (2, true));
+    }
+
+    #[test]
+    fn split_streams_send_warnings_and_errors_to_stderr_only() {
+        let data_dir = temp_dir("split_streams");
+        let file_contents = r#"
+            [log.appenders.console]
+            kind = "console"
+            split_streams = true
+        "#;
+        let log = parse_log(file_contents, &data_dir, None).unwrap();
+        // The standard streams are replaced by memory writers
+        let (stdout, stderr) = (MemoryWriter::new(), MemoryWriter::new());
+        let stdout_writer = buffer::non_blocking(stdout.clone(), &BufferConfig::default());
+        let stderr_writer = buffer::non_blocking(stderr.clone(), &BufferConfig::default());
+        let writers = Writers {
+            writer: Some(stdout_writer.writer),
+            error_writer: Some(stderr_writer.writer),
+        };
+
+        let setup =
+            AppenderSetup::new(&log.configs.appenders["console"], &log.global, writers).unwrap();
+        let collector = tracing_subscriber::registry().with(setup.into_subscriber());
+        dispatch::with_default(&collector.into(), || {
+            info!("to stdout");
+            tracing::error!("to stderr");
+        });
+        drop((stdout_writer.worker_guard, stderr_writer.worker_guard));
+
+        let stdout = stdout.lines();
+        assert_eq!(stdout.len(), 1);
+        assert!(stdout[0].contains("to stdout"));
+        let stderr = stderr.lines();
+        assert_eq!(stderr.len(), 1);
+        assert!(stderr[0].contains("to stderr"));
+    }
+
+    #[test]
+    fn reloads_deliver_every_event_to_the_unchanged_appenders() {
+        let data_dir = temp_dir("reload_delivery");
+        let first = parse_log(
+            r#"
+            [log.buffer]
+            mode = "blocking"
+
+            [log.appenders.shared]
+            kind = "memory"
+
+            [log.appenders.first]
+            kind = "memory"
+            "#,
+            &data_dir,
+            None,
+        )
+        .unwrap();
+        let mut second = parse_log(
+            r#"
+            [log.buffer]
+            mode = "blocking"
+
+            [log.appenders.shared]
+            kind = "memory"
+
+            [log.appenders.second]
+            kind = "memory"
+            "#,
+            &data_dir,
+            None,
+        )
+        .unwrap();
+        // Share the writer of the unchanged appender, which keeps its worker
+        second
+            .configs
+            .appenders
+            .insert("shared".into(), first.configs.appenders["shared"].clone());
+
+        let subscribers = Subscribers::try_from(first.clone()).unwrap();
+        let mut log_guard =
+            subscribers.build_local(tracing_subscriber::registry().with(platform::no_op()));
+        let dispatch = log_guard.dispatch();
+        let stop = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let emitter = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                dispatch::with_default(&dispatch, || {
+                    let mut emitted = 0;
+                    while !stop.load(Ordering::Relaxed) {
+                        info!(emitted, "event");
+                        emitted += 1;
+                    }
+                    emitted
+                })
+            }
+        });
+        for index in 0..50 {
+            log_guard.reload_with(if index % 2 == 0 {
+                second.clone()
+            } else {
+                first.clone()
+            });
+        }
+        stop.store(true, Ordering::Relaxed);
+        let emitted = emitter.join().unwrap();
+        assert!(log_guard.flush());
+
+        let lines = memory_lines(&log_guard.current_config(), "shared");
+        assert_eq!(lines.len(), emitted);
+        assert!(lines
+            .iter()
+            .enumerate()
+            .all(|(index, line)| line.contains(&format!("emitted={index}"))));
+    }
+
+    #[test]
+    fn file_appenders_create_their_parent_directories() {
+        let data_dir = temp_dir("nested_file");
+        let file_contents = r#"
+            [log.appenders.file]
+            kind = "file"
+            path = "nested/dir/app.log"
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+        emit(&log_guard, || info!("in a nested directory"));
+        drop(log_guard);
+
+        let contents = fs::read_to_string(data_dir.join("nested/dir/app.log")).unwrap();
+        assert!(contents.contains("in a nested directory"));
+    }
+
+    #[test]
+    fn file_appenders_fail_when_a_file_is_in_the_way() {
+        let data_dir = temp_dir("file_in_the_way");
+        fs::write(data_dir.join("nested"), "").unwrap();
+        let file_contents = r#"
+            [log.appenders.file]
+            kind = "file"
+            path = "nested/app.log"
+
+            [log.appenders.memory]
+            kind = "memory"
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        assert_eq!(log_guard.failed_appenders(), ["file"]);
+        let directory = data_dir.join("nested");
+        let message = format!("unable to create the directory `{}`", directory.display());
+        assert!(log_guard
+            .recent_errors()
+            .iter()
+            .any(|error| error.contains(&message)));
+    }
+
+    #[test]
+    fn merge_reload_keeps_the_writers_supplied_by_the_application() {
+        let data_dir = temp_dir("merge_writer");
+        let (writer, memory) = (MemoryWriter::new(), MemoryWriter::new());
+        let log = LogBuilder::new()
+            .writer_appender("custom", writer.clone(), AppenderOptions::default())
+            .appender(
+                "memory",
+                AppenderLogConfig::Memory(MemoryLogConfig {
+                    options: AppenderOptions::default(),
+                    writer: memory.clone(),
+                }),
+            )
+            .into_log(&data_dir)
+            .unwrap();
+        let subscribers = Subscribers::try_from(log).unwrap();
+        let mut log_guard =
+            subscribers.build_local(tracing_subscriber::registry().with(platform::no_op()));
+
+        let patch = r#"
+            [log.appenders.custom]
+            level = "debug"
+
+            [log.appenders.memory]
+            level = "debug"
+        "#;
+        log_guard.merge_reload(patch, &data_dir);
+        assert!(log_guard.failed_appenders().is_empty());
+        let config = log_guard.current_config();
+        assert_eq!(config.configs.appenders["custom"].kind(), "writer");
+        emit(&log_guard, || tracing::debug!("after the merge"));
+
+        for lines in [writer.lines(), memory.lines()] {
+            assert_eq!(lines.len(), 1);
+            assert!(lines[0].contains("after the merge"));
+        }
+    }
+
+    #[test]
+    fn field_names_rename_the_standard_json_keys() {
+        let data_dir = temp_dir("field_names");
+        let file_contents = r#"
+            [log.field_names]
+            message = "msg"
+            level = "severity"
+
+            [log.appenders.memory]
+            kind = "memory"
+            format = "json"
+            field_names = { target = "logger" }
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        emit(&log_guard, || info!(user = "admin", "renamed"));
+        let lines = log_guard.captured_lines();
+        let event: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(event["msg"], "renamed");
+        assert_eq!(event["severity"], "INFO");
+        assert_eq!(event["logger"], module_path!());
+        assert_eq!(event["user"], "admin");
+        for original in ["message", "level", "target"] {
+            assert!(event.get(original).is_none(), "`{original}` is not renamed");
+        }
+    }
+
+    #[test]
+    fn events_emitted_while_the_guard_drops_are_written_whole_or_discarded() {
+        let data_dir = temp_dir("drop_stress");
+        let file_contents = r#"
+            [log.appenders.memory]
+            kind = "memory"
+
+            [log.appenders.file]
+            kind = "file"
+            path = "app.log"
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+        let config = log_guard.current_config();
+        let dispatch = log_guard.dispatch();
+
+        let emitters: Vec<_> = (0..4)
+            .map(|thread_index| {
+                let dispatch = dispatch.clone();
+                thread::spawn(move || {
+                    dispatch::with_default(&dispatch, || {
+                        for index in 0..2000 {
+                            info!(thread_index, index, "stress");
+                        }
+                    })
+                })
+            })
+            .collect();
+        thread::sleep(Duration::from_millis(5));
+        drop(log_guard);
+        for emitter in emitters {
+            emitter.join().unwrap();
+        }
+
+        let lines = memory_lines(&config, "memory");
+        assert!(lines.len() <= 4 * 2000);
+        assert!(lines.iter().all(|line| line.contains("stress")));
+        let contents = fs::read_to_string(data_dir.join("app.log")).unwrap();
+        assert!(contents.lines().all(|line| line.contains("stress")));
+    }
+
+    #[test]
+    fn constant_fields_are_added_to_the_json_and_full_events() {
+        let data_dir = temp_dir("constant_fields");
+        let file_contents = r#"
+            [log.constant_fields]
+            service = "api"
+            region = "eu"
+
+            [log.appenders.json]
+            kind = "memory"
+            format = "json"
+
+            [log.appenders.full]
+            kind = "memory"
+            format = "full"
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        emit(&log_guard, || info!("stamped"));
+        let config = log_guard.current_config();
+        let json = memory_lines(&config, "json");
+        let event: serde_json::Value = serde_json::from_str(&json[0]).unwrap();
+        assert_eq!(event["service"], "api");
+        assert_eq!(event["region"], "eu");
+        let full = memory_lines(&config, "full");
+        assert!(full[0].contains(r#"stamped service="api" region="eu""#));
+    }
+
+    #[test]
+    fn adding_and_removing_an_appender_keeps_the_other_workers() {
+        let data_dir = temp_dir("add_appender_workers");
+        let file_contents = r#"
+            [log.appenders.file]
+            kind = "file"
+            path = "app.log"
+        "#;
+        let mut log_guard = local_guard(file_contents, &data_dir);
+        let before = workers(&log_guard);
+
+        let memory = AppenderLogConfig::Memory(MemoryLogConfig::default());
+        log_guard.add_appender("memory", memory).unwrap();
+        let after = workers(&log_guard);
+        assert_eq!(after.len(), 2);
+        assert!(before
+            .iter()
+            .all(|(key, sender)| after[key].same_worker(sender)));
+
+        log_guard.remove_appender("memory").unwrap();
+        assert!(same_workers(&before, &workers(&log_guard)));
+    }
+
+    #[test]
+    fn disabled_logging_neither_evaluates_nor_allocates_until_reloaded() {
+        let data_dir = temp_dir("disabled");
+        let subscribers = disabled_subscribers().unwrap();
+        let mut log_guard =
+            subscribers.build_local(tracing_subscriber::registry().with(platform::no_op()));
+        assert_eq!(
+            log_guard.state().subscriber_handle.max_level_hint(),
+            Some(LevelFilter::OFF)
+        );
+
+        let evaluations = AtomicUsize::new(0);
+        let evaluate = || evaluations.fetch_add(1, Ordering::Relaxed);
+        let log_error = || tracing::error!(value = evaluate(), "disabled");
+        dispatch::with_default(&log_guard.dispatch(), || {
+            // The first event registers the callsite
+            log_error();
+            let allocations = ALLOCATIONS.with(Cell::get);
+            log_error();
+            assert_eq!(ALLOCATIONS.with(Cell::get), allocations);
+        });
+        assert_eq!(evaluations.load(Ordering::Relaxed), 0);
+
+        let file_contents = r#"
+            [log.appenders.memory]
+            kind = "memory"
+        "#;
+        log_guard.reload(file_contents, &data_dir);
+        emit(&log_guard, || tracing::error!("enabled again"));
+        let lines = log_guard.captured_lines();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("enabled again"));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn async_reloads_keep_the_writers_of_the_unchanged_appenders() {
+        let data_dir = temp_dir("async_reload");
+        let file_contents = r#"
+            [log.appenders.file]
+            kind = "file"
+            path = "app.log"
+        "#;
+        let mut log_guard = local_guard(file_contents, &data_dir);
+        let before = workers(&log_guard);
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let file_contents = r#"
+            [log.appenders.file]
+            kind = "file"
+            path = "app.log"
+            level = "debug"
+
+            [log.appenders.memory]
+            kind = "memory"
+        "#;
+        runtime.block_on(reload_log_async(file_contents, &data_dir, &mut log_guard));
+        let after = workers(&log_guard);
+        assert_eq!(after.len(), 2);
+        assert!(before
+            .iter()
+            .all(|(key, sender)| after[key].same_worker(sender)));
+        emit(&log_guard, || info!("after the async reload"));
+        assert_eq!(log_guard.captured_lines().len(), 1);
+
+        // An invalid configuration keeps the current appenders
+        runtime.block_on(reload_log_async("[log", &data_dir, &mut log_guard));
+        assert!(same_workers(&after, &workers(&log_guard)));
+    }
+
+    #[test]
+    fn flattened_span_fields_are_prefixed_on_collision() {
+        let data_dir = temp_dir("flatten_spans");
+        let file_contents = r#"
+            [log.appenders.memory]
+            kind = "memory"
+            format = "json"
+            flatten_spans = true
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        emit(&log_guard, || {
+            let _outer = tracing::info_span!("outer", request_id = 1, user = "alice").entered();
+            let _inner = tracing::info_span!("inner", user = "bob", step = 2).entered();
+            info!(step = 3, "nested");
+        });
+        let lines = log_guard.captured_lines();
+        let event: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(event["request_id"], 1);
+        assert_eq!(event["user"], "alice");
+        assert_eq!(event["inner.user"], "bob");
+        assert_eq!(event["inner.step"], 2);
+        assert_eq!(event["step"], 3);
+    }
+
+    #[test]
+    fn conditional_appenders_follow_their_environment_variable() {
+        // Specific to this test, which may run along with the others
+        const VAR: &str = "TRACING_RELOAD_EXAMPLE_CONDITIONAL_APPENDERS";
+        let data_dir = temp_dir("enabled_if_env");
+        let appender_names = |file_contents: &str| -> Vec<String> {
+            let log = parse_log(file_contents, &data_dir, None).unwrap();
+            let subscribers = Subscribers::try_from(log).unwrap();
+            subscribers.config.configs.appenders.into_keys().collect()
+        };
+        let file_contents = format!(
+            r#"
+            [log.appenders.dev]
+            kind = "memory"
+            enabled_if_env = {{ var = "{VAR}", equals = "dev" }}
+
+            [log.appenders.always]
+            kind = "memory"
+            "#
+        );
+        let only_conditional = format!(
+            r#"
+            [log.appenders.dev]
+            kind = "memory"
+            enabled_if_env = {{ var = "{VAR}", equals = "dev" }}
+            "#
+        );
+
+        env::remove_var(VAR);
+        assert_eq!(appender_names(&file_contents), ["always"]);
+        // The default console appender replaces the disabled ones
+        assert_eq!(appender_names(&only_conditional), ["stdout"]);
+
+        env::set_var(VAR, "prod");
+        assert_eq!(appender_names(&file_contents), ["always"]);
+
+        env::set_var(VAR, "dev");
+        assert_eq!(appender_names(&file_contents), ["dev", "always"]);
+        assert_eq!(appender_names(&only_conditional), ["dev"]);
+        env::remove_var(VAR);
+    }
+
+    #[test]
+    fn ring_buffers_keep_the_last_lines_in_order() {
+        let data_dir = temp_dir("ring_buffer");
+        let file_contents = r#"
+            [log.buffer]
+            mode = "blocking"
+
+            [log.appenders.recent]
+            kind = "ring_buffer"
+            capacity = 10
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        emit(&log_guard, || {
+            for index in 0..15 {
+                info!("line {index}");
+            }
+        });
+        let lines = log_guard.recent_lines();
+        assert_eq!(lines.len(), 10);
+        for (line, index) in lines.iter().zip(5..) {
+            assert!(line.ends_with(&format!("line {index}")), "{line}");
+        }
+    }
+
+    #[test]
+    fn identical_reloads_keep_the_writers() {
+        let data_dir = temp_dir("identical_reload");
+        let file_contents = r#"
+            [log.appenders.file]
+            kind = "file"
+            path = "app.log"
+
+            [log.appenders.memory]
+            kind = "memory"
+        "#;
+        let reloads = Arc::new(AtomicUsize::new(0));
+        let mut log_guard = local_guard(file_contents, &data_dir).with_reload_callback({
+            let reloads = reloads.clone();
+            move |_| {
+                reloads.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        let before = workers(&log_guard);
+
+        let previous_config = log_guard.reload(file_contents, &data_dir);
+        assert_eq!(previous_config, log_guard.current_config());
+        assert!(same_workers(&before, &workers(&log_guard)));
+        // The subscribers are not even rebuilt
+        assert_eq!(reloads.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn required_fields_select_the_events_of_an_appender() {
+        let data_dir = temp_dir("require_field");
+        let file_contents = r#"
+            [log.appenders.pager]
+            kind = "memory"
+            require_field = { name = "alert", value = "true" }
+
+            [log.appenders.alerts]
+            kind = "memory"
+            require_field = { name = "alert" }
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        emit(&log_guard, || {
+            info!(alert = true, "paged");
+            info!(alert = false, "not paged");
+            info!("plain");
+        });
+        let config = log_guard.current_config();
+        let pager = memory_lines(&config, "pager");
+        assert_eq!(pager.len(), 1);
+        assert!(pager[0].contains("paged"));
+        let alerts = memory_lines(&config, "alerts");
+        assert_eq!(alerts.len(), 2);
+        assert!(alerts.iter().all(|line| !line.contains("plain")));
+    }
+
+    #[test]
+    fn appenders_cannot_be_added_beyond_the_maximum() {
+        let data_dir = temp_dir("too_many_appenders");
+        let file_contents = r#"
+            [log.appenders.memory0]
+            kind = "memory"
+        "#;
+        let mut log_guard = local_guard(file_contents, &data_dir);
+
+        let memory = || AppenderLogConfig::Memory(MemoryLogConfig::default());
+        for index in 1..max_appenders() {
+            log_guard
+                .add_appender(format!("memory{index}"), memory())
+                .unwrap();
+        }
+        let error = log_guard.add_appender("extra", memory()).unwrap_err();
+        assert!(matches!(
+            error,
+            LogError::TooManyAppenders { appender, max }
+                if appender == "extra" && max == max_appenders()
+        ));
+        assert_eq!(
+            log_guard.current_config().configs.appenders.len(),
+            max_appenders()
+        );
+        emit(&log_guard, || info!("still logging"));
+    }
+
+    #[test]
+    fn aligned_lines_start_their_messages_on_the_same_column() {
+        let data_dir = temp_dir("align");
+        let file_contents = r#"
+            [log.timestamp]
+            format = "%H:%M:%S"
+
+            [log.appenders.memory]
+            kind = "memory"
+            level = "debug"
+            align = true
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        emit(&log_guard, || {
+            // Widen the target column first
+            info!(target: "aligned::longest_target", "warm-up");
+            info!(target: "plain_target", "message");
+            tracing::warn!(target: "célèbre_target", "message");
+            tracing::debug!(target: "aligned::longest_target", "message");
+        });
+        let columns: Vec<usize> = log_guard.captured_lines()[1..]
+            .iter()
+            .map(|line| line[..line.find("message").unwrap()].chars().count())
+            .collect();
+        assert_eq!(columns.len(), 3);
+        assert!(columns.iter().all(|&column| column == columns[0]));
+    }
+
+    #[test]
+    fn elevated_levels_are_restored_unless_changed_explicitly() {
+        let data_dir = temp_dir("elevate_level");
+        let file_contents = r#"
+            [log.appenders.captured]
+            kind = "memory"
+            level = "info"
+        "#;
+        let mut log_guard = local_guard(file_contents, &data_dir);
+
+        log_guard
+            .elevate_level("captured", "debug", Duration::from_millis(100))
+            .unwrap();
+        emit(&log_guard, || tracing::debug!("while elevated"));
+        thread::sleep(Duration::from_millis(500));
+        emit(&log_guard, || tracing::debug!("once restored"));
+        let lines = log_guard.captured_lines();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].ends_with("while elevated"), "{}", lines[0]);
+
+        log_guard
+            .elevate_level("captured", "debug", Duration::from_millis(100))
+            .unwrap();
+        log_guard.set_global_level("trace").unwrap();
+        thread::sleep(Duration::from_millis(500));
+        emit(&log_guard, || tracing::trace!("after the explicit change"));
+        let lines = log_guard.captured_lines();
+        assert_eq!(lines.len(), 2);
+        assert!(
+            lines[1].ends_with("after the explicit change"),
+            "{}",
+            lines[1]
+        );
+    }
+
+    #[test]
+    fn debug_and_display_summarize_each_appender() {
+        let data_dir = temp_dir("summary");
+        let file_contents = r#"
+            [log.appenders.captured]
+            kind = "memory"
+            level = "debug"
+
+            [log.appenders.recent]
+            kind = "ring_buffer"
+            capacity = 10
+            level = "warn"
+            format = "json"
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        let debug = format!("{log_guard:?}");
+        let display = log_guard.to_string();
+        for summary in [&debug, &display] {
+            for expected in ["captured", "debug", "recent", "warn"] {
+                assert!(summary.contains(expected), "{expected} in {summary}");
+            }
+        }
+        assert!(!debug.contains("WorkerGuard"), "{debug}");
+    }
+
+    #[test]
+    fn opening_a_file_is_retried_until_its_directory_is_available() {
+        let data_dir = temp_dir("open_retries");
+        let directory = data_dir.join("mounted");
+        let path = directory.join("app.log");
+        let config = FileLogConfig {
+            open_retries: 50,
+            open_retry_delay_ms: 10,
+            ..FileLogConfig::default()
+        };
+
+        let mount = thread::spawn({
+            let directory = directory.clone();
+            move || {
+                thread::sleep(Duration::from_millis(50));
+                fs::create_dir_all(directory).unwrap();
+            }
+        });
+        config.open(&path).unwrap();
+        mount.join().unwrap();
+        assert!(path.exists());
+
+        let without_retries = FileLogConfig::default();
+        let error = without_retries
+            .open(&data_dir.join("missing").join("app.log"))
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert!(!is_transient(&io::Error::from(
+            io::ErrorKind::PermissionDenied
+        )));
+    }
+
+    #[test]
+    fn the_last_partial_batch_is_written_when_the_workers_stop() {
+        let data_dir = temp_dir("batch_tail");
+        let file_contents = r#"
+            [log.buffer]
+            mode = "blocking"
+
+            [log.appenders.batched]
+            kind = "file"
+            path = "app.log"
+            batch_lines = 100
+            batch_interval_ms = 60000
+        "#;
+        let log = parse_log(file_contents, &data_dir, None).unwrap();
+
+        let (dispatch, worker_guards) = build_dispatch(log).unwrap();
+        dispatch::with_default(&dispatch, || {
+            for index in 0..3 {
+                info!("batched {index}");
+            }
+        });
+        drop(worker_guards);
+        let contents = fs::read_to_string(data_dir.join("app.log")).unwrap();
+        assert_eq!(contents.lines().count(), 3, "{contents}");
+        assert!(contents.contains("batched 2"));
+    }
+
+    #[test]
+    fn invalid_configurations_fall_back_to_the_given_one() {
+        let data_dir = temp_dir("fallback");
+        let fallback = Log::parse(
+            r#"
+                [log.appenders.fallback]
+                kind = "file"
+                path = "fallback.log"
+            "#,
+            &data_dir,
+        )
+        .unwrap();
+
+        assert!(build_appenders("[log.appenders.broken]\nkind = 3", &data_dir, None).is_err());
+        let subscribers = build_fallback_appenders(Some(fallback), &data_dir).unwrap();
+        let log_guard =
+            subscribers.build_local(tracing_subscriber::registry().with(platform::no_op()));
+        emit(&log_guard, || warn!("written to the fallback"));
+        drop(log_guard);
+
+        let contents = fs::read_to_string(data_dir.join("fallback.log")).unwrap();
+        assert!(contents.contains("written to the fallback"), "{contents}");
+    }
+
+    #[test]
+    fn failed_try_reloads_keep_the_current_appenders() {
+        let data_dir = temp_dir("try_reload");
+        fs::write(data_dir.join("nested"), "").unwrap();
+        let file_contents = r#"
+            [log.appenders.current]
+            kind = "memory"
+        "#;
+        let mut log_guard = local_guard(file_contents, &data_dir);
+        let workers_before = workers(&log_guard);
+
+        let partially_failing = r#"
+            [log.appenders.opened]
+            kind = "memory"
+
+            [log.appenders.file]
+            kind = "file"
+            path = "nested/app.log"
+        "#;
+        assert!(log_guard.try_reload(partially_failing, &data_dir).is_err());
+
+        let names: Vec<_> = log_guard
+            .current_config()
+            .configs
+            .appenders
+            .keys()
+            .cloned()
+            .collect();
+        assert_eq!(names, ["current"]);
+        assert!(same_workers(&workers_before, &workers(&log_guard)));
+        emit(&log_guard, || info!("after the rollback"));
+        let lines = log_guard.captured_lines();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].ends_with("after the rollback"), "{}", lines[0]);
+    }
+
+    #[test]
+    fn the_dispatch_routes_the_events_of_a_spawned_thread() {
+        let data_dir = temp_dir("dispatch_thread");
+        let file_contents = r#"
+            [log.appenders.captured]
+            kind = "memory"
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        let dispatch = log_guard.dispatch();
+        thread::spawn(move || {
+            dispatch::with_default(&dispatch, || info!("from the spawned thread"));
+        })
+        .join()
+        .unwrap();
+        // Without the dispatch, the thread uses the global default one
+        thread::spawn(|| info!("not routed")).join().unwrap();
+
+        assert!(log_guard.flush());
+        let lines = log_guard.captured_lines();
+        assert_eq!(lines.len(), 1);
+        assert!(
+            lines[0].ends_with("from the spawned thread"),
+            "{}",
+            lines[0]
+        );
+    }
+
+    #[test]
+    fn source_locations_show_the_emitting_line() {
+        let data_dir = temp_dir("source_location");
+        let file_contents = r#"
+            [log.appenders.located]
+            kind = "file"
+            path = "located.log"
+            source_location = true
+
+            [log.appenders.unlocated]
+            kind = "file"
+            path = "unlocated.log"
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        let mut line = 0;
+        emit(&log_guard, || {
+            line = line!() + 1;
+            info!("located event");
+        });
+        drop(log_guard);
+
+        let location = format!("{}:{line}", file!());
+        let located = fs::read_to_string(data_dir.join("located.log")).unwrap();
+        assert!(located.contains(&location), "{location} in {located}");
+        let unlocated = fs::read_to_string(data_dir.join("unlocated.log")).unwrap();
+        assert!(unlocated.contains("located event"));
+        assert!(!unlocated.contains(&location), "{unlocated}");
+    }
+
+    #[test]
+    fn reloads_keep_the_connections_of_the_unchanged_tcp_appenders() {
+        let data_dir = temp_dir("tcp_reload");
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let file_contents = |level: &str| {
+            format!(
+                r#"
+                [log.buffer]
+                mode = "blocking"
+
+                [log.appenders.network]
+                kind = "tcp"
+                host = "127.0.0.1"
+                port = {port}
+
+                [log.appenders.captured]
+                kind = "memory"
+                level = "{level}"
+                "#
+            )
+        };
+        let mut log_guard = local_guard(&file_contents("info"), &data_dir);
+
+        emit(&log_guard, || info!("before the reload"));
+        log_guard.reload(&file_contents("debug"), &data_dir);
+        emit(&log_guard, || info!("after the reload"));
+
+        listener.set_nonblocking(true).unwrap();
+        let mut connections = 0;
+        while listener.accept().is_ok() {
+            connections += 1;
+        }
+        assert_eq!(connections, 1);
+    }
+
+    #[test]
+    fn thread_name_prefixes_select_the_events_of_the_named_threads() {
+        let data_dir = temp_dir("thread_name_prefix");
+        let file_contents = r#"
+            [log.appenders.io]
+            kind = "memory"
+            thread_name_prefix = "io-"
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        for name in ["io-1", "compute-1"] {
+            let dispatch = log_guard.dispatch();
+            thread::Builder::new()
+                .name(name.to_owned())
+                .spawn(move || dispatch::with_default(&dispatch, || info!("from {name}")))
+                .unwrap()
+                .join()
+                .unwrap();
+        }
+        // The thread of the test is named after it, without the prefix
+        emit(&log_guard, || info!("from the test thread"));
+
+        let lines = log_guard.captured_lines();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].ends_with("from io-1"), "{}", lines[0]);
+    }
+
+    #[test]
+    fn profiling_records_the_latencies_of_the_appenders() {
+        let data_dir = temp_dir("latencies");
+        let file_contents = |profiling: bool| {
+            format!(
+                r#"
+                [log]
+                profiling = {profiling}
+
+                [log.appenders.captured]
+                kind = "memory"
+                "#
+            )
+        };
+
+        let log_guard = local_guard(&file_contents(false), &data_dir);
+        emit(&log_guard, || info!("not profiled"));
+        assert!(log_guard.appender_latencies().is_empty());
+
+        let log_guard = local_guard(&file_contents(true), &data_dir);
+        emit(&log_guard, || {
+            for index in 0..5 {
+                info!("profiled {index}");
+            }
+        });
+        let latencies = log_guard.appender_latencies();
+        assert_eq!(latencies.len(), 1);
+        assert_eq!(latencies[0].0, "captured");
+        assert_eq!(latencies[0].1.len(), 5);
+    }
+
+    #[test]
+    fn byte_order_marks_start_the_new_files_only() {
+        let data_dir = temp_dir("bom");
+        fs::write(data_dir.join("appended.log"), "previous line\n").unwrap();
+        fs::write(data_dir.join("overwritten.log"), "previous line\n").unwrap();
+        let file_contents = r#"
+            [log.appenders.new]
+            kind = "file"
+            path = "new.log"
+            bom = true
+
+            [log.appenders.appended]
+            kind = "file"
+            path = "appended.log"
+            bom = true
+
+            [log.appenders.overwritten]
+            kind = "file"
+            path = "overwritten.log"
+            mode = "overwrite"
+            bom = true
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+        emit(&log_guard, || info!("with encoding"));
+        drop(log_guard);
+
+        let contents = |name: &str| fs::read(data_dir.join(name)).unwrap();
+        assert!(contents("new.log").starts_with(b"\xEF\xBB\xBF"));
+        assert!(contents("overwritten.log").starts_with(b"\xEF\xBB\xBF"));
+        let appended = contents("appended.log");
+        assert!(appended.starts_with(b"previous line\n"));
+        assert!(!appended.windows(3).any(|window| window == b"\xEF\xBB\xBF"));
+    }
+
+    /// The only test installing the global dispatcher, the other tests entering
+    /// the dispatcher of their guard instead
+    #[test]
+    fn initializing_twice_reloads_the_first_logging() {
+        let data_dir = temp_dir("try_init_log");
+        let first = r#"
+            [log.appenders.first]
+            kind = "memory"
+        "#;
+        let second = r#"
+            [log.appenders.second]
+            kind = "memory"
+        "#;
+
+        let log_guard = try_init_log(first, &data_dir, platform::no_op()).unwrap();
+        let again = try_init_log(second, &data_dir, platform::no_op());
+        assert!(matches!(again, Err(LogError::AlreadyInitialized)));
+
+        let names: Vec<_> = log_guard
+            .current_config()
+            .configs
+            .appenders
+            .keys()
+            .cloned()
+            .collect();
+        assert_eq!(names, ["second"]);
+    }
+
+    #[test]
+    fn level_ranges_route_the_severe_events_to_their_own_file() {
+        let data_dir = temp_dir("level_range");
+        let file_contents = r#"
+            [log.appenders.app]
+            kind = "file"
+            path = "app.log"
+
+            [log.appenders.errors]
+            kind = "file"
+            path = "errors.log"
+            level_range = { min = "warn" }
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+        emit(&log_guard, || {
+            info!("informational");
+            tracing::error!("failure");
+        });
+        drop(log_guard);
+
+        let app = fs::read_to_string(data_dir.join("app.log")).unwrap();
+        let errors = fs::read_to_string(data_dir.join("errors.log")).unwrap();
+        assert!(
+            app.contains("informational") && app.contains("failure"),
+            "{app}"
+        );
+        assert!(!errors.contains("informational"), "{errors}");
+        assert!(errors.contains("failure"), "{errors}");
+    }
+
+    #[test]
+    fn shutdown_reports_the_lines_left_by_unreachable_servers() {
+        let data_dir = temp_dir("shutdown_report");
+        // Bound then closed, so that nothing listens on the port
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let file_contents = format!(
+            r#"
+            [log.buffer]
+            mode = "blocking"
+
+            [log.appenders.network]
+            kind = "tcp"
+            host = "127.0.0.1"
+            port = {port}
+            reconnect_backoff_ms = 60000
+            max_reconnect_backoff_ms = 60000
+
+            [log.appenders.captured]
+            kind = "memory"
+            "#
+        );
+        let log_guard = local_guard(&file_contents, &data_dir);
+        dispatch::with_default(&log_guard.dispatch(), || {
+            for index in 0..3 {
+                info!("unsent {index}");
+            }
+        });
+
+        let started = Instant::now();
+        let report = log_guard.shutdown(Duration::from_millis(200));
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert!(!report.is_drained());
+        assert_eq!(report.appenders.len(), 1);
+        assert_eq!(report.appenders[0].appender, "network");
+        assert_eq!(report.appenders[0].queued_lines, 3);
+    }
+
+    #[test]
+    fn level_lists_let_only_their_exact_levels_pass() {
+        let data_dir = temp_dir("levels");
+        let file_contents = r#"
+            [log.appenders.selected]
+            kind = "memory"
+            level = "trace"
+            levels = ["debug", "error"]
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        emit(&log_guard, || {
+            tracing::trace!("trace event");
+            tracing::debug!("debug event");
+            info!("info event");
+            warn!("warn event");
+            tracing::error!("error event");
+        });
+        let lines = log_guard.captured_lines();
+        assert_eq!(lines.len(), 2, "{lines:?}");
+        assert!(lines[0].ends_with("debug event"), "{}", lines[0]);
+        assert!(lines[1].ends_with("error event"), "{}", lines[1]);
+    }
+
+    #[test]
+    fn line_prefixes_start_every_line_of_multi_line_events() {
+        let data_dir = temp_dir("line_prefix");
+        let file_contents = r#"
+            [log.appenders.pretty]
+            kind = "file"
+            path = "app.log"
+            format = "pretty"
+            line_prefix = "[api-7] "
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+        emit(&log_guard, || info!("first line\nsecond line"));
+        drop(log_guard);
+
+        let contents = fs::read_to_string(data_dir.join("app.log")).unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert!(lines.len() >= 2, "{contents}");
+        assert!(
+            lines.iter().all(|line| line.starts_with("[api-7] ")),
+            "{contents}"
+        );
+        assert!(lines.iter().any(|line| line.ends_with("second line")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unwritable_files_fail_the_initialization() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let data_dir = temp_dir("unwritable_file");
+        let read_only = data_dir.join("read_only");
+        fs::create_dir(&read_only).unwrap();
+        fs::set_permissions(&read_only, fs::Permissions::from_mode(0o555)).unwrap();
+        let file_contents = r#"
+            [log.appenders.read_only]
+            kind = "file"
+            path = "read_only/app.log"
+        "#;
+        let result = parse_log(file_contents, &data_dir, None).and_then(Subscribers::try_from);
+        // Privileged users such as root can write regardless of the permissions
+        let privileged = File::create(read_only.join("probe")).is_ok();
+        fs::set_permissions(&read_only, fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(result.is_err(), !privileged);
+
+        // Checking an appended file keeps its contents
+        fs::write(data_dir.join("kept.log"), "previous line\n").unwrap();
+        let file_contents = r#"
+            [log.appenders.kept]
+            kind = "file"
+            path = "kept.log"
+        "#;
+        drop(local_guard(file_contents, &data_dir));
+        let contents = fs::read_to_string(data_dir.join("kept.log")).unwrap();
+        assert_eq!(contents, "previous line\n");
+    }
+
+    #[test]
+    fn context_fields_are_attached_until_their_guard_drops() {
+        let data_dir = temp_dir("context_fields");
+        let file_contents = r#"
+            [log.appenders.captured]
+            kind = "memory"
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        emit(&log_guard, || {
+            let _request = crate::context::set_context("request_id", 42);
+            {
+                let _span = tracing::info_span!("handler").entered();
+                info!("within a span");
+            }
+            {
+                let _replaced = crate::context::set_context("request_id", 43);
+                info!("replaced");
+            }
+            info!("restored");
+        });
+        emit(&log_guard, || info!("without context"));
+
+        let lines = log_guard.captured_lines();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].contains("request_id=\"42\""), "{}", lines[0]);
+        assert!(lines[1].contains("request_id=\"43\""), "{}", lines[1]);
+        assert!(lines[2].contains("request_id=\"42\""), "{}", lines[2]);
+        assert!(!lines[3].contains("request_id"), "{}", lines[3]);
+    }
+
+    #[test]
+    fn tags_change_the_level_of_their_appenders_at_once() {
+        let data_dir = temp_dir("tags");
+        let file_contents = r#"
+            [log.appenders.first]
+            kind = "memory"
+            tags = ["network"]
+
+            [log.appenders.second]
+            kind = "ring_buffer"
+            capacity = 10
+            tags = ["network", "buffered"]
+
+            [log.appenders.untagged]
+            kind = "memory"
+        "#;
+        let mut log_guard = local_guard(file_contents, &data_dir);
+        assert_eq!(log_guard.appenders_by_tag("network"), ["first", "second"]);
+        assert_eq!(log_guard.appenders_by_tag("buffered"), ["second"]);
+
+        log_guard.set_level_for_tag("network", "debug").unwrap();
+        let levels = log_guard.effective_levels();
+        assert_eq!(
+            levels,
+            [
+                ("first".to_owned(), LevelFilter::DEBUG),
+                ("second".to_owned(), LevelFilter::DEBUG),
+                ("untagged".to_owned(), LevelFilter::INFO),
+            ]
+        );
+        assert!(matches!(
+            log_guard.set_level_for_tag("missing", "debug"),
+            Err(LogError::UnknownTag { .. })
+        ));
+    }
+
+    #[test]
+    fn ndjson_lines_are_capped_and_stay_valid_json() {
+        let data_dir = temp_dir("ndjson");
+        let file_contents = r#"
+            [log.appenders.aggregated]
+            kind = "memory"
+            format = "ndjson"
+            max_line_bytes = 300
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        let message = "line \"quoted\"\n".repeat(100);
+        emit(&log_guard, || info!(request = "kept whole", "{message}"));
+        emit(&log_guard, || info!("short"));
+
+        let lines = log_guard.captured_lines();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].len() <= 300, "{}", lines[0]);
+        assert!(!lines[0].contains('\n'));
+        let event: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(event["truncated"], true);
+        assert_eq!(event["request"], "kept whole");
+        assert!(event["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("line \"quoted\"\nline"));
+        let event: serde_json::Value = serde_json::from_str(&lines[1]).unwrap();
+        assert_eq!(event["message"], "short");
+        assert!(event.get("truncated").is_none());
+    }
+
+    #[test]
+    fn reloading_a_parsed_configuration_matches_reloading_its_file() {
+        let data_dir = temp_dir("reload_parity");
+        let initial = r#"
+            [log.appenders.captured]
+            kind = "memory"
+        "#;
+        let reloaded = r#"
+            [log]
+            level = "warn"
+
+            [log.appenders.captured]
+            kind = "memory"
+            format = "json"
+
+            [log.appenders.recent]
+            kind = "ring_buffer"
+            capacity = 5
+        "#;
+
+        let mut from_file = local_guard(initial, &data_dir);
+        let mut from_log = local_guard(initial, &data_dir);
+        from_file.reload(reloaded, &data_dir);
+        from_log.reload_with(parse_log(reloaded, &data_dir, None).unwrap());
+
+        assert_eq!(from_file.current_config(), from_log.current_config());
+        assert_eq!(from_file.to_string(), from_log.to_string());
+        for log_guard in [&from_file, &from_log] {
+            emit(log_guard, || {
+                info!("filtered out");
+                warn!("recorded");
+            });
+            let lines = log_guard.captured_lines();
+            assert_eq!(lines.len(), 1);
+            let event: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+            assert_eq!(event["message"], "recorded");
+        }
+    }
+
+    #[test]
+    fn repeated_events_are_summarized_once_a_different_one_is_written() {
+        let data_dir = temp_dir("dedup");
+        let file_contents = r#"
+            [log.appenders.captured]
+            kind = "memory"
+            dedup_window_ms = 60000
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        emit(&log_guard, || {
+            for _ in 0..5 {
+                warn!(disk = "sda", "disk almost full");
+            }
+            // Distinct fields make a different event
+            warn!(disk = "sdb", "disk almost full");
+        });
+
+        let captured = log_guard.captured_lines().join("\n");
+        let lines: Vec<_> = captured.lines().collect();
+        assert_eq!(lines.len(), 3, "{captured}");
+        assert!(lines[0].contains("disk=\"sda\""), "{}", lines[0]);
+        assert_eq!(lines[1], "... repeated 4 times");
+        assert!(lines[2].contains("disk=\"sdb\""), "{}", lines[2]);
+    }
+
+    #[test]
+    fn writer_appenders_write_to_the_supplied_writer() {
+        /// Bytes shared with the test, which the appender owns a clone of
+        #[derive(Clone, Default)]
+        struct SharedBytes(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for SharedBytes {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let data_dir = temp_dir("writer_appender");
+        let bytes = SharedBytes::default();
+        let log = LogBuilder::new()
+            .writer_appender("supplied", bytes.clone(), AppenderOptions::default())
+            .into_log(&data_dir)
+            .unwrap();
+
+        let (dispatch, worker_guards) = build_dispatch(log).unwrap();
+        dispatch::with_default(&dispatch, || {
+            info!(attempt = 1, "first");
+            warn!("second");
+        });
+        drop(worker_guards);
+
+        let contents = String::from_utf8(bytes.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines.len(), 2, "{contents}");
+        assert!(lines[0].contains("INFO") && lines[0].contains("first attempt=1"));
+        assert!(lines[1].contains("WARN") && lines[1].ends_with("second"));
+    }
+
+    #[test]
+    fn appenders_survive_a_panicking_write() {
+        /// Writer panicking on its first write, then keeping the lines
+        #[derive(Clone, Default)]
+        struct FaultyWriter {
+            writes: Arc<AtomicUsize>,
+            lines: MemoryWriter,
+        }
+
+        impl Write for FaultyWriter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if self.writes.fetch_add(1, Ordering::Relaxed) == 0 {
+                    panic!("faulty write");
+                }
+                self.lines.write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let data_dir = temp_dir("panicking_writer");
+        let writer = FaultyWriter::default();
+        let log = LogBuilder::new()
+            .writer_appender("faulty", writer.clone(), AppenderOptions::default())
+            .into_log(&data_dir)
+            .unwrap();
+        let log_guard = Subscribers::try_from(log)
+            .unwrap()
+            .build_local(tracing_subscriber::registry().with(platform::no_op()));
+
+        emit(&log_guard, || info!("lost in the panic"));
+        emit(&log_guard, || info!("written after the panic"));
+
+        let lines = writer.lines.lines();
+        assert_eq!(lines.len(), 1, "{lines:?}");
+        assert!(
+            lines[0].ends_with("written after the panic"),
+            "{}",
+            lines[0]
+        );
+    }
+
+    #[test]
+    fn oversized_field_values_are_truncated_but_not_their_keys() {
+        let data_dir = temp_dir("field_value_limit");
+        let file_contents = r#"
+            [log.appenders.text]
+            kind = "memory"
+            max_field_value_bytes = 8
+
+            [log.appenders.json]
+            kind = "memory"
+            format = "json"
+            max_field_value_bytes = 8
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        let body = "é".repeat(50);
+        emit(&log_guard, || {
+            info!(
+                user = "bob",
+                request_body = body.as_str(),
+                status = 200,
+                "handled"
+            )
+        });
+
+        let lines = log_guard.captured_lines();
+        assert_eq!(lines.len(), 2);
+        // Each `é` takes two bytes, so that four of them fit
+        let truncated = format!("{}{}", "é".repeat(4), crate::format::TRUNCATION_MARKER);
+        assert!(lines[0].contains("user=\"bob\""), "{}", lines[0]);
+        assert!(lines[0].contains("status=200"), "{}", lines[0]);
+        let expected = format!("request_body=\"{truncated}\"");
+        assert!(lines[0].contains(&expected), "{}", lines[0]);
+        let event: serde_json::Value = serde_json::from_str(&lines[1]).unwrap();
+        assert_eq!(event["message"], "handled");
+        assert_eq!(event["user"], "bob");
+        assert_eq!(event["status"], 200);
+        assert_eq!(event["request_body"], truncated.as_str());
+    }
+
+    #[test]
+    fn mirrored_files_receive_the_same_lines() {
+        let data_dir = temp_dir("mirror");
+        let file_contents = r#"
+            [log.appenders.mirrored]
+            kind = "file"
+            path = "primary/app.log"
+            mirror_path = "backup/app.log"
+        "#;
+        fs::create_dir(data_dir.join("primary")).unwrap();
+        fs::create_dir(data_dir.join("backup")).unwrap();
+        let log_guard = local_guard(file_contents, &data_dir);
+        emit(&log_guard, || {
+            info!("first");
+            warn!(attempt = 2, "second");
+        });
+        drop(log_guard);
+
+        let primary = fs::read_to_string(data_dir.join("primary/app.log")).unwrap();
+        let backup = fs::read_to_string(data_dir.join("backup/app.log")).unwrap();
+        assert_eq!(primary.lines().count(), 2, "{primary}");
+        assert_eq!(primary, backup);
+    }
+
+    #[test]
+    fn channel_appenders_deliver_the_events_in_order() {
+        let data_dir = temp_dir("channel_appender");
+        let (sender, receiver) = mpsc::sync_channel(16);
+        let log = LogBuilder::new()
+            .channel_appender("live", sender, AppenderOptions::default())
+            .into_log(&data_dir)
+            .unwrap();
+        let log_guard = Subscribers::try_from(log)
+            .unwrap()
+            .build_local(tracing_subscriber::registry().with(platform::no_op()));
+
+        emit(&log_guard, || {
+            for index in 0..3 {
+                info!("live {index}");
+            }
+        });
+
+        let received: Vec<_> = receiver.try_iter().collect();
+        assert_eq!(received.len(), 3, "{received:?}");
+        for (line, index) in received.iter().zip(0..) {
+            assert!(line.ends_with(&format!("live {index}")), "{line}");
+        }
+    }
+
+    #[test]
+    fn startup_summaries_mention_each_appender() {
+        let data_dir = temp_dir("startup_summary");
+        let file_contents = |enabled: bool| {
+            format!(
+                r#"
+                [log]
+                log_startup_summary = {enabled}
+
+                [log.appenders.captured]
+                kind = "memory"
+                level = "debug"
+
+                [log.appenders.recent]
+                kind = "ring_buffer"
+                capacity = 10
+                format = "json"
+                "#
+            )
+        };
+
+        let log_guard = local_guard(&file_contents(true), &data_dir);
+        emit(&log_guard, || log_startup_summary(&log_guard));
+        let lines = log_guard.captured_lines();
+        assert_eq!(lines.len(), 1);
+        for expected in ["Logging initialized", "captured", "debug", "recent", "json"] {
+            assert!(lines[0].contains(expected), "{expected} in {}", lines[0]);
+        }
+
+        let log_guard = local_guard(&file_contents(false), &data_dir);
+        emit(&log_guard, || log_startup_summary(&log_guard));
+        assert!(log_guard.captured_lines().is_empty());
+    }
+
+    #[test]
+    fn complete_filters_match_span_fields() {
+        let data_dir = temp_dir("span_field_filter");
+        let file_contents = r#"
+            [log.appenders.admin]
+            kind = "memory"
+            filter = "warn,[request{user=admin}]=debug"
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        emit(&log_guard, || {
+            for user in ["admin", "bob"] {
+                let _span = tracing::info_span!("request", user).entered();
+                tracing::debug!("debug for {user}");
+            }
+            warn!("outside of the requests");
+        });
+        let lines = log_guard.captured_lines();
+        assert_eq!(lines.len(), 2, "{lines:?}");
+        assert!(lines[0].ends_with("debug for admin"), "{}", lines[0]);
+        assert!(
+            lines[1].ends_with("outside of the requests"),
+            "{}",
+            lines[1]
+        );
+
+        let conflicting = r#"
+            [log.appenders.admin]
+            kind = "memory"
+            level = "info"
+            filter = "warn,[request{user=admin}]=debug"
+        "#;
+        assert!(matches!(
+            parse_log(conflicting, &data_dir, None),
+            Err(LogError::ConflictingFilter { appender }) if appender == "admin"
+        ));
+    }
+
+    #[test]
+    fn restoring_a_snapshot_applies_its_levels_again() {
+        let data_dir = temp_dir("snapshot");
+        let file_contents = r#"
+            [log.appenders.captured]
+            kind = "memory"
+            level = "debug"
+        "#;
+        let mut log_guard = local_guard(file_contents, &data_dir);
+        let original_levels = log_guard.effective_levels();
+        let snapshot = log_guard.snapshot();
+
+        log_guard.reload(
+            r#"
+                [log.appenders.captured]
+                kind = "memory"
+                level = "error"
+
+                [log.appenders.recent]
+                kind = "ring_buffer"
+                capacity = 5
+            "#,
+            &data_dir,
+        );
+        assert_ne!(log_guard.effective_levels(), original_levels);
+
+        let replaced = log_guard.restore(snapshot.clone());
+        assert_eq!(replaced.configs.appenders.len(), 2);
+        assert_eq!(log_guard.effective_levels(), original_levels);
+        assert_eq!(&log_guard.current_config(), snapshot.config());
+        emit(&log_guard, || tracing::debug!("recorded again"));
+        assert_eq!(log_guard.captured_lines().len(), 1);
+    }
+
+    #[test]
+    fn log_and_flush_writes_the_event_before_returning() {
+        let data_dir = temp_dir("log_and_flush");
+        let file_contents = r#"
+            [log.appenders.audit]
+            kind = "file"
+            path = "audit.log"
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        let flushed = dispatch::with_default(&log_guard.dispatch(), || {
+            log_and_flush!(
+                log_guard,
+                Level::WARN,
+                user = "alice",
+                "Deleting the account"
+            )
+        });
+        assert!(flushed);
+        let contents = fs::read_to_string(data_dir.join("audit.log")).unwrap();
+        assert!(contents.contains("Deleting the account"), "{contents}");
+        assert!(contents.contains("user=\"alice\""), "{contents}");
+    }
+
+    #[test]
+    fn appenders_excluding_the_context_omit_the_injected_fields() {
+        let data_dir = temp_dir("include_context");
+        let file_contents = r#"
+            [log.constant_fields]
+            service = "api"
+
+            [log.appenders.json]
+            kind = "memory"
+            format = "json"
+
+            [log.appenders.terse]
+            kind = "memory"
+            format = "full"
+            include_context = false
+        "#;
+        let log_guard = local_guard(file_contents, &data_dir);
+
+        emit(&log_guard, || {
+            let _request = crate::context::set_context("request_id", 42);
+            info!(user = "alice", "logged in");
+        });
+        let config = log_guard.current_config();
+        let json = memory_lines(&config, "json");
+        let event: serde_json::Value = serde_json::from_str(&json[0]).unwrap();
+        assert_eq!(event["service"], "api");
+        assert_eq!(event["request_id"], "42");
+        assert_eq!(event["user"], "alice");
+        let terse = memory_lines(&config, "terse");
+        assert!(
+            terse[0].contains(r#"logged in user="alice""#),
+            "{}",
+            terse[0]
+        );
+        assert!(!terse[0].contains("service"), "{}", terse[0]);
+        assert!(!terse[0].contains("request_id"), "{}", terse[0]);
+    }
 }
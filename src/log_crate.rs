@@ -0,0 +1,146 @@
+use std::{
+    cell::Cell,
+    fmt::{self, Write},
+    sync::Arc,
+};
+
+use tracing::{
+    field::{Field, Visit},
+    Collect, Event, Level,
+};
+use tracing_log::NormalizeEvent;
+use tracing_subscriber::subscribe::{Context, Subscribe};
+
+thread_local! {
+    /// Whether the current thread is forwarding a record, which the `log`
+    /// bridge may turn back into an event
+    static FORWARDING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Map a tracing level to a `log` level
+fn log_level(level: &Level) -> log::Level {
+    match *level {
+        Level::ERROR => log::Level::Error,
+        Level::WARN => log::Level::Warn,
+        Level::INFO => log::Level::Info,
+        Level::DEBUG => log::Level::Debug,
+        Level::TRACE => log::Level::Trace,
+    }
+}
+
+/// Logger of the `log` crate receiving the forwarded records, the global one if unset
+#[derive(Clone, Default)]
+pub struct LogCrateLogger(Option<Arc<dyn log::Log>>);
+
+impl LogCrateLogger {
+    pub fn new(logger: impl log::Log + 'static) -> Self {
+        Self(Some(Arc::new(logger)))
+    }
+
+    fn get(&self) -> &dyn log::Log {
+        match &self.0 {
+            Some(logger) => logger.as_ref(),
+            None => log::logger(),
+        }
+    }
+}
+
+impl fmt::Debug for LogCrateLogger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("LogCrateLogger(custom)"),
+            None => f.write_str("LogCrateLogger(global)"),
+        }
+    }
+}
+
+/// The logger is set programmatically rather than configured, so it is
+/// ignored when comparing appender configurations
+impl PartialEq for LogCrateLogger {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for LogCrateLogger {}
+
+/// Subscriber forwarding events as records of the `log` crate, for components
+/// consuming them
+///
+/// When forwarding to the global logger while the `log` bridge is installed,
+/// the bridged events are dropped by this subscriber rather than forwarded
+/// again, although the other appenders record them.
+pub struct LogCrateSubscriber {
+    logger: LogCrateLogger,
+}
+
+impl LogCrateSubscriber {
+    pub fn new(logger: LogCrateLogger) -> Self {
+        Self { logger }
+    }
+}
+
+impl<C: Collect> Subscribe<C> for LogCrateSubscriber {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, C>) {
+        if FORWARDING.with(Cell::get) {
+            return;
+        }
+
+        // Events bridged from `log` carry their original metadata as fields
+        let normalized_metadata = event.normalized_metadata();
+        let metadata = normalized_metadata
+            .as_ref()
+            .unwrap_or_else(|| event.metadata());
+        let level = log_level(metadata.level());
+        let logger = self.logger.get();
+        let log_metadata = log::Metadata::builder()
+            .level(level)
+            .target(metadata.target())
+            .build();
+        if !logger.enabled(&log_metadata) {
+            return;
+        }
+
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+
+        FORWARDING.with(|forwarding| forwarding.set(true));
+        logger.log(
+            &log::Record::builder()
+                .metadata(log_metadata)
+                .args(format_args!("{}{}", visitor.message, visitor.fields))
+                .module_path(metadata.module_path())
+                .file(metadata.file())
+                .line(metadata.line())
+                .build(),
+        );
+        FORWARDING.with(|forwarding| forwarding.set(false));
+    }
+}
+
+/// Render the message of an event followed by its fields, as `key=value` pairs
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_owned();
+        } else {
+            self.record_debug(field, &value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" => self.message = format!("{value:?}"),
+            name if name.starts_with("log.") => {}
+            name => {
+                let _ = write!(self.fields, " {name}={value:?}");
+            }
+        }
+    }
+}
@@ -1,21 +1,16 @@
-mod config;
-mod log;
-mod reload;
-
 use std::fs;
 use std::path::Path;
 use std::time::Duration;
 
 use tracing::{debug, debug_span, error, info, trace, trace_span, warn};
-use tracing_subscriber::subscribe::Identity;
 
-use self::log::{init_log, reload_log};
+use tracing_reload_example::{log::init_log, platform};
 
 fn main() -> eyre::Result<()> {
     let data_dir = Path::new("data");
     fs::create_dir_all(data_dir)?;
 
-    let mut log_guard = init_log(r#"[log]"#, data_dir, Identity::new())?;
+    let mut log_guard = init_log(r#"[log]"#, data_dir, platform::no_op())?;
 
     let _span = trace_span!("trace_span0").entered();
 
@@ -27,7 +22,7 @@ fn main() -> eyre::Result<()> {
         level = "trace"
         color = false
     "#;
-    log_guard = reload_log(file_contents, data_dir, log_guard)?;
+    log_guard.reload(file_contents, data_dir);
 
     {
         let _span = trace_span!("trace_span1").entered();
@@ -52,7 +47,7 @@ fn main() -> eyre::Result<()> {
                 level = "debug"
                 path = "log2.log"
             "#;
-            log_guard = reload_log(file_contents, data_dir, log_guard)?;
+            log_guard.reload(file_contents, data_dir);
 
             trace!("trace 2");
             debug!("debug 2");
@@ -72,7 +67,7 @@ fn main() -> eyre::Result<()> {
             level = "warn"
             path = "log2.log"
         "#;
-        log_guard = reload_log(file_contents, data_dir, log_guard)?;
+        log_guard.reload(file_contents, data_dir);
 
         {
             let _span = debug_span!("debug_span3").entered();
@@ -94,7 +89,7 @@ fn main() -> eyre::Result<()> {
                 level = "error"
                 path = "log2.log"
             "#;
-            log_guard = reload_log(file_contents, data_dir, log_guard)?;
+            log_guard.reload(file_contents, data_dir);
 
             trace!("trace 4");
             debug!("debug 4");
@@ -122,7 +117,7 @@ fn main() -> eyre::Result<()> {
         level = "debug"
         path = "log2.log"
     "#;
-    log_guard = reload_log(file_contents, data_dir, log_guard)?;
+    log_guard.reload(file_contents, data_dir);
 
     trace!("trace 6");
     debug!("debug 6");
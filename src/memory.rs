@@ -0,0 +1,188 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{self, Write},
+    sync::{mpsc::SyncSender, Arc, Mutex, PoisonError},
+};
+
+/// Writer supplied by the application, such as a pipe or an in-process channel
+///
+/// Clones share the same writer.
+#[derive(Clone)]
+pub struct SharedWriter(Arc<Mutex<Box<dyn Write + Send>>>);
+
+impl SharedWriter {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(Box::new(writer))))
+    }
+}
+
+/// Discard the lines, for appenders which were not given a writer
+impl Default for SharedWriter {
+    fn default() -> Self {
+        Self::new(io::sink())
+    }
+}
+
+impl fmt::Debug for SharedWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedWriter").finish_non_exhaustive()
+    }
+}
+
+/// Appenders are unchanged as long as they share the same writer
+impl PartialEq for SharedWriter {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for SharedWriter {}
+
+impl Write for SharedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .flush()
+    }
+}
+
+/// Writer sending each formatted event to a channel, for instance to stream
+/// the logs to a live view
+///
+/// Events are dropped while the channel is full or once the receiver is gone,
+/// so that a slow consumer never blocks the appender.
+pub struct ChannelWriter {
+    sender: SyncSender<String>,
+}
+
+impl ChannelWriter {
+    pub fn new(sender: SyncSender<String>) -> Self {
+        Self { sender }
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let line = String::from_utf8_lossy(buf)
+            .trim_end_matches('\n')
+            .to_owned();
+        // The event is dropped if the channel is full or disconnected
+        let _ = self.sender.try_send(line);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writer keeping each formatted event as a line in memory
+///
+/// Clones share the same lines.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryWriter {
+    lines: Arc<Mutex<Vec<String>>>,
+}
+
+/// The captured lines are state rather than configuration, so they are ignored
+/// when comparing appender configurations
+impl PartialEq for MemoryWriter {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for MemoryWriter {}
+
+impl MemoryWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lines written so far, oldest first
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl Write for MemoryWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let line = String::from_utf8_lossy(buf)
+            .trim_end_matches('\n')
+            .to_owned();
+        let mut lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        lines.push(line);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writer keeping the most recent formatted events in memory, the oldest ones
+/// being evicted once the capacity is reached
+///
+/// Clones share the same lines.
+#[derive(Debug, Clone, Default)]
+pub struct RingBufferWriter {
+    lines: Arc<Mutex<VecDeque<String>>>,
+    capacity: usize,
+}
+
+/// The kept lines are state rather than configuration, so they are ignored
+/// when comparing appender configurations
+impl PartialEq for RingBufferWriter {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for RingBufferWriter {}
+
+impl RingBufferWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writer sharing the lines of this one, keeping at most `capacity` of them
+    pub fn with_capacity(&self, capacity: usize) -> Self {
+        Self {
+            lines: self.lines.clone(),
+            capacity,
+        }
+    }
+
+    /// Lines kept, oldest first
+    pub fn lines(&self) -> Vec<String> {
+        let lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        lines.iter().cloned().collect()
+    }
+}
+
+impl Write for RingBufferWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let line = String::from_utf8_lossy(buf)
+            .trim_end_matches('\n')
+            .to_owned();
+        let mut lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        while lines.len() >= self.capacity.max(1) {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
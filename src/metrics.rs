@@ -0,0 +1,47 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use tracing::{Collect, Event, Level};
+use tracing_subscriber::subscribe::{Context, Subscribe};
+
+/// Platform subscriber counting the events by level, whatever the appenders
+/// which record them
+///
+/// Clones share the same counters, so that a clone can be kept to read them
+/// once the subscriber is given to `init_log`.
+#[derive(Debug, Clone, Default)]
+pub struct EventCounter {
+    /// Indexed like the array returned by `log_event_counts`
+    counts: Arc<[AtomicU64; 5]>,
+}
+
+impl EventCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of events emitted so far, ordered from `ERROR` to `TRACE`
+    pub fn log_event_counts(&self) -> [u64; 5] {
+        self.counts
+            .each_ref()
+            .map(|count| count.load(Ordering::Relaxed))
+    }
+}
+
+fn level_index(level: &Level) -> usize {
+    match *level {
+        Level::ERROR => 0,
+        Level::WARN => 1,
+        Level::INFO => 2,
+        Level::DEBUG => 3,
+        Level::TRACE => 4,
+    }
+}
+
+impl<C: Collect> Subscribe<C> for EventCounter {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, C>) {
+        self.counts[level_index(event.metadata().level())].fetch_add(1, Ordering::Relaxed);
+    }
+}
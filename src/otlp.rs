@@ -0,0 +1,247 @@
+use std::{
+    cell::Cell,
+    env, fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, SyncSender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use indexmap::IndexMap;
+use serde_json::{json, Value};
+use tracing::{
+    field::{Field, Visit},
+    Collect, Event, Level,
+};
+use tracing_subscriber::subscribe::{Context, Subscribe};
+
+/// Maximum number of records sent in a single request
+const BATCH_SIZE: usize = 512;
+/// Maximum number of records waiting to be exported, newer ones being dropped
+const QUEUE_CAPACITY: usize = 4096;
+const EXPORT_INTERVAL: Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+thread_local! {
+    /// Whether the current thread is an exporter, whose own events must not be exported
+    static IS_EXPORTER: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Map a tracing level to an OpenTelemetry severity number
+fn severity_number(level: &Level) -> u8 {
+    match *level {
+        Level::TRACE => 1,
+        Level::DEBUG => 5,
+        Level::INFO => 9,
+        Level::WARN => 13,
+        Level::ERROR => 17,
+    }
+}
+
+fn attribute(key: &str, value: Value) -> Value {
+    json!({ "key": key, "value": value })
+}
+
+/// Subscriber exporting events as OTLP log records, in batches sent over HTTP
+///
+/// Records are exported by a background thread, which sends the remaining
+/// records when the subscriber is dropped.
+pub struct OtlpSubscriber {
+    records: SyncSender<Value>,
+    /// Number of records dropped because the queue was full
+    dropped: Arc<AtomicU64>,
+}
+
+impl OtlpSubscriber {
+    pub fn new(
+        endpoint: &str,
+        headers: IndexMap<String, String>,
+        service_name: Option<String>,
+    ) -> Self {
+        let service_name = service_name.unwrap_or_else(|| {
+            env::current_exe()
+                .ok()
+                .and_then(|path| {
+                    path.file_stem()
+                        .map(|name| name.to_string_lossy().into_owned())
+                })
+                .unwrap_or_else(|| "unknown_service".to_owned())
+        });
+
+        let exporter = Exporter {
+            agent: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(),
+            url: format!("{}/v1/logs", endpoint.trim_end_matches('/')),
+            headers,
+            resource: json!({
+                "attributes": [attribute("service.name", json!({ "stringValue": service_name }))],
+            }),
+        };
+
+        let (records, received) = mpsc::sync_channel(QUEUE_CAPACITY);
+        thread::spawn(move || {
+            IS_EXPORTER.with(|is_exporter| is_exporter.set(true));
+            exporter.run(received);
+        });
+
+        Self {
+            records,
+            dropped: Arc::default(),
+        }
+    }
+
+    /// Counter of the records which could not be queued
+    pub fn dropped_counter(&self) -> Arc<AtomicU64> {
+        self.dropped.clone()
+    }
+}
+
+impl<C: Collect> Subscribe<C> for OtlpSubscriber {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, C>) {
+        // Avoid a feedback loop with the events of the HTTP client
+        if IS_EXPORTER.with(Cell::get) {
+            return;
+        }
+
+        if self.records.try_send(log_record(event)).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+fn log_record(event: &Event<'_>) -> Value {
+    let mut visitor = AttributesVisitor::default();
+    event.record(&mut visitor);
+
+    let metadata = event.metadata();
+    visitor.attributes.push(attribute(
+        "target",
+        json!({ "stringValue": metadata.target() }),
+    ));
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+
+    json!({
+        "timeUnixNano": time.to_string(),
+        "severityNumber": severity_number(metadata.level()),
+        "severityText": metadata.level().as_str(),
+        "body": { "stringValue": visitor.message },
+        "attributes": visitor.attributes,
+    })
+}
+
+/// Collect the fields of an event as OTLP attributes
+#[derive(Default)]
+struct AttributesVisitor {
+    message: String,
+    attributes: Vec<Value>,
+}
+
+impl AttributesVisitor {
+    fn insert(&mut self, field: &Field, value: Value) {
+        match field.name() {
+            name if name.starts_with("log.") => {}
+            name => self.attributes.push(attribute(name, value)),
+        }
+    }
+}
+
+impl Visit for AttributesVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, json!({ "doubleValue": value }));
+    }
+
+    // 64-bit integers are encoded as strings in OTLP/JSON
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, json!({ "intValue": value.to_string() }));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, json!({ "intValue": value.to_string() }));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, json!({ "boolValue": value }));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_owned();
+        } else {
+            self.insert(field, json!({ "stringValue": value }));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{value:?}");
+        } else {
+            self.insert(field, json!({ "stringValue": format!("{value:?}") }));
+        }
+    }
+}
+
+struct Exporter {
+    agent: ureq::Agent,
+    url: String,
+    headers: IndexMap<String, String>,
+    resource: Value,
+}
+
+impl Exporter {
+    /// Export the received records, until the subscriber is dropped
+    fn run(self, records: Receiver<Value>) {
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        let mut deadline = Instant::now() + EXPORT_INTERVAL;
+
+        loop {
+            match records.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(record) => {
+                    batch.push(record);
+                    if batch.len() < BATCH_SIZE {
+                        continue;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.export(&mut batch);
+                    return;
+                }
+            }
+
+            self.export(&mut batch);
+            deadline = Instant::now() + EXPORT_INTERVAL;
+        }
+    }
+
+    fn export(&self, batch: &mut Vec<Value>) {
+        if batch.is_empty() {
+            return;
+        }
+
+        let body = json!({
+            "resourceLogs": [{
+                "resource": self.resource,
+                "scopeLogs": [{
+                    "scope": { "name": env!("CARGO_PKG_NAME") },
+                    "logRecords": batch.drain(..).collect::<Vec<_>>(),
+                }],
+            }],
+        });
+
+        let mut request = self
+            .agent
+            .post(&self.url)
+            .set("Content-Type", "application/json");
+        for (name, value) in &self.headers {
+            request = request.set(name, value);
+        }
+
+        // Export failures are ignored, since the collector must never disrupt logging
+        let _ = request.send_string(&body.to_string());
+    }
+}
@@ -0,0 +1,51 @@
+use std::{
+    backtrace::Backtrace,
+    cell::Cell,
+    panic::{self, PanicHookInfo},
+};
+
+use tracing::error;
+
+thread_local! {
+    /// Whether the current thread is logging a panic, so that a panic raised
+    /// while logging is not logged again
+    static LOGGING_PANIC: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Log panics as `error` events, so that they reach the configured appenders
+/// rather than only stderr
+///
+/// The event has the `panic.message` and `panic.location` fields, along with
+/// the backtrace of the panicking thread. The previous hook is called
+/// afterwards, so that the default report is still printed.
+pub fn install_panic_logger() {
+    let previous_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if !LOGGING_PANIC.with(|logging| logging.replace(true)) {
+            log_panic(info);
+            LOGGING_PANIC.with(|logging| logging.set(false));
+        }
+        previous_hook(info);
+    }));
+}
+
+fn log_panic(info: &PanicHookInfo<'_>) {
+    let payload = info.payload();
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("Box<dyn Any>");
+    let location = info
+        .location()
+        .map(|location| location.to_string())
+        .unwrap_or_default();
+    let backtrace = Backtrace::force_capture();
+
+    error!(
+        panic.message = message,
+        panic.location = location,
+        %backtrace,
+        "A thread panicked"
+    );
+}
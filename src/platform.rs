@@ -0,0 +1,21 @@
+use tracing_subscriber::{
+    registry::Registry,
+    subscribe::{Identity, Layered, Subscribe},
+};
+
+/// Platform subscriber doing nothing, for programs without platform-specific needs
+pub fn no_op() -> Identity {
+    Identity::new()
+}
+
+/// Stack two platform subscribers, `first` handling each event before `second`
+///
+/// The result can itself be composed, so that any number of platform
+/// subscribers can be given to `init_log`.
+pub fn compose<A, B>(first: A, second: B) -> Layered<B, A, Registry>
+where
+    A: Subscribe<Registry>,
+    B: Subscribe<Registry>,
+{
+    first.and_then(second)
+}
@@ -0,0 +1,94 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use tracing::{callsite::Identifier, warn, Metadata};
+
+/// Period during which a callsite may emit the configured number of events
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(1);
+pub const SUPPRESSED_EVENTS_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+struct Budget {
+    window_start: Instant,
+    events: u32,
+}
+
+#[derive(Debug)]
+struct RateLimiterState {
+    max_per_second: u32,
+    budgets: Mutex<HashMap<Identifier, Budget>>,
+    suppressed: AtomicUsize,
+}
+
+/// Limit the number of events per second of each callsite, periodically
+/// reporting the number of suppressed events
+#[derive(Debug)]
+pub struct RateLimiter {
+    state: Arc<RateLimiterState>,
+    /// Stop the reporter thread when dropped
+    _stop: Sender<()>,
+}
+
+impl RateLimiter {
+    pub fn new(max_per_second: u32, interval: Duration) -> Self {
+        let state = Arc::new(RateLimiterState {
+            max_per_second,
+            budgets: Mutex::new(HashMap::new()),
+            suppressed: AtomicUsize::new(0),
+        });
+        let (stop, stopped) = mpsc::channel();
+
+        let reported_state = state.clone();
+        thread::spawn(move || loop {
+            // Report the last suppressed events before stopping
+            let stopping = !matches!(
+                stopped.recv_timeout(interval),
+                Err(RecvTimeoutError::Timeout)
+            );
+            let suppressed = reported_state.suppressed.swap(0, Ordering::Relaxed);
+            if suppressed > 0 {
+                warn!(
+                    suppressed,
+                    max_per_second = reported_state.max_per_second,
+                    "log events suppressed because of the rate limit"
+                );
+            }
+            if stopping {
+                return;
+            }
+        });
+
+        Self { state, _stop: stop }
+    }
+
+    /// Consume the budget of the callsite of an event, returning whether it may be logged
+    pub fn allow(&self, meta: &Metadata<'_>) -> bool {
+        let now = Instant::now();
+        let mut budgets = self.state.budgets.lock().unwrap_or_else(|e| e.into_inner());
+        let budget = budgets.entry(meta.callsite()).or_insert(Budget {
+            window_start: now,
+            events: 0,
+        });
+
+        if now - budget.window_start >= RATE_LIMIT_WINDOW {
+            budget.window_start = now;
+            budget.events = 0;
+        }
+
+        if budget.events >= self.state.max_per_second {
+            self.state.suppressed.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        budget.events += 1;
+        true
+    }
+}
@@ -10,9 +10,13 @@ use tracing::{
 };
 use tracing_subscriber::subscribe::{CollectExt, Context, Layered, Subscribe};
 
-#[derive(Default)]
+/// Subscriber whose inner subscriber can be replaced, under a global level ceiling
+///
+/// Events more verbose than the ceiling are rejected before the inner subscriber
+/// is called, and its level hint is clamped by the ceiling.
 pub struct ReloadableSubscriber<S, C> {
     subscriber: Arc<ArcSwap<S>>,
+    max_level: Arc<ArcSwap<LevelFilter>>,
     collector: Arc<C>,
 }
 
@@ -21,18 +25,42 @@ where
     S: Subscribe<Arc<C>>,
     C: Collect,
 {
-    fn new(mut subscriber: S, collector: Arc<C>) -> Self {
+    fn new(mut subscriber: S, collector: Arc<C>, max_level: LevelFilter) -> Self {
         subscriber.on_subscribe(&collector);
         let subscriber = ArcSwap::from_pointee(subscriber).into();
         Self {
             subscriber,
+            max_level: ArcSwap::from_pointee(max_level).into(),
             collector,
         }
     }
 
-    pub fn reload(&self, mut new_subscriber: S) {
-        new_subscriber.on_subscribe(&self.collector);
-        self.subscriber.store(new_subscriber.into());
+    pub fn reload(&self, new_subscriber: S, max_level: LevelFilter) {
+        Self::reload_batch([(self, new_subscriber, max_level)]);
+    }
+
+    /// Replace the subscribers of several handles, rebuilding the interest and
+    /// filter caches once every subscriber is replaced, so that no callsite is
+    /// evaluated against a partially reloaded set of subscribers
+    pub fn reload_batch<'a>(reloads: impl IntoIterator<Item = (&'a Self, S, LevelFilter)>)
+    where
+        Self: 'a,
+    {
+        for (handle, mut new_subscriber, max_level) in reloads {
+            new_subscriber.on_subscribe(&handle.collector);
+            handle.subscriber.store(new_subscriber.into());
+            handle.max_level.store(max_level.into());
+        }
+        callsite::rebuild_interest_cache();
+        span::rebuild_filter_cache();
+    }
+}
+
+impl<S: Default, C> ReloadableSubscriber<S, C> {
+    /// Replace the subscriber by an empty one, for instance before closing the
+    /// writers it references
+    pub fn clear(&self) {
+        self.subscriber.store(Arc::default());
         callsite::rebuild_interest_cache();
         span::rebuild_filter_cache();
     }
@@ -42,6 +70,7 @@ impl<S, C> Clone for ReloadableSubscriber<S, C> {
     fn clone(&self) -> Self {
         Self {
             subscriber: self.subscriber.clone(),
+            max_level: self.max_level.clone(),
             collector: self.collector.clone(),
         }
     }
@@ -57,6 +86,12 @@ macro_rules! impl_subscribe {
     };
 }
 
+impl<S, C> ReloadableSubscriber<S, C> {
+    fn max_level(&self) -> LevelFilter {
+        **self.max_level.load()
+    }
+}
+
 impl<S, C> Subscribe<Arc<C>> for ReloadableSubscriber<S, C>
 where
     S: Subscribe<Arc<C>>,
@@ -66,12 +101,26 @@ where
         // Do nothing, since `on_subscribe()` is already called in the `new()` method.
     }
 
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        if self.max_level() < *metadata.level() {
+            return Interest::never();
+        }
+        self.subscriber.load().register_callsite(metadata)
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, ctx: Context<'_, Arc<C>>) -> bool {
+        self.max_level() >= *metadata.level() && self.subscriber.load().enabled(metadata, ctx)
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        let max_level = self.max_level();
+        let hint = self.subscriber.load().max_level_hint();
+        Some(hint.map_or(max_level, |hint| hint.min(max_level)))
+    }
+
     impl_subscribe!(
         fn on_register_dispatch(&self, collector: &Dispatch);
         fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, Arc<C>>);
-        fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest;
-        fn enabled(&self, metadata: &Metadata<'_>, ctx: Context<'_, Arc<C>>) -> bool;
-        fn max_level_hint(&self) -> Option<LevelFilter>;
         fn on_record(&self, span: &Id, values: &Record<'_>, ctx: Context<'_, Arc<C>>);
         fn on_follows_from(&self, span: &Id, follows: &Id, ctx: Context<'_, Arc<C>>);
         fn event_enabled(&self, event: &Event<'_>, ctx: Context<'_, Arc<C>>) -> bool;
@@ -86,9 +135,11 @@ where
 type ReloadableLayered<S, C> = Layered<ReloadableSubscriber<S, C>, Arc<C>>;
 
 pub trait WithReloadable: Collect + Sized {
+    /// Stack a reloadable subscriber, rejecting the events more verbose than `max_level`
     fn with_reloadable<S>(
         self,
         subscriber: S,
+        max_level: LevelFilter,
     ) -> (ReloadableLayered<S, Self>, ReloadableSubscriber<S, Self>)
     where
         S: Subscribe<Arc<Self>>;
@@ -98,12 +149,13 @@ impl<C: Collect> WithReloadable for C {
     fn with_reloadable<S>(
         self,
         subscriber: S,
+        max_level: LevelFilter,
     ) -> (ReloadableLayered<S, Self>, ReloadableSubscriber<S, Self>)
     where
         S: Subscribe<Arc<Self>>,
     {
         let this = Arc::new(self);
-        let reloadable_subscriber = ReloadableSubscriber::new(subscriber, this.clone());
+        let reloadable_subscriber = ReloadableSubscriber::new(subscriber, this.clone(), max_level);
         let collector = this.with(reloadable_subscriber.clone());
         (collector, reloadable_subscriber)
     }
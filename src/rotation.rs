@@ -0,0 +1,393 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use flate2::{write::GzEncoder, Compression};
+use tracing::warn;
+
+use super::config::LineEnding;
+
+/// How lines are written to a file
+#[derive(Debug, Clone, Copy)]
+pub struct FileOutput {
+    pub compress: bool,
+    pub line_ending: LineEnding,
+    /// Capacity of the write buffer, if any
+    pub write_buffer_bytes: Option<usize>,
+    pub strip_ansi: bool,
+    /// Start the files with a UTF-8 byte order mark, unless appending to a non-empty one
+    pub bom: bool,
+}
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Handle on the file written by a file appender, sharing its descriptor, so
+/// that the lines flushed by the worker can be synced to the storage device
+pub type SyncedFile = Arc<Mutex<File>>;
+
+/// File writer rotating the file once it exceeds a size: `app.log` is renamed
+/// to `app.log.1`, `app.log.1` to `app.log.2` and so on, the oldest file being
+/// deleted
+///
+/// Rotation happens within `write`, which the non-blocking worker calls for
+/// whole lines, so that a line is never split between two files. A line larger
+/// than the maximum size is written entirely in a file of its own.
+pub struct SizeRotatingWriter {
+    path: PathBuf,
+    /// Maximum size of a file, in bytes before compression
+    max_size: u64,
+    /// Maximum number of rotated files kept besides the current one
+    max_files: usize,
+    file_output: FileOutput,
+    output: Box<dyn Write + Send>,
+    /// Current file, replaced on rotation
+    synced_file: SyncedFile,
+    written: u64,
+}
+
+impl SizeRotatingWriter {
+    pub fn new(
+        path: PathBuf,
+        file: File,
+        file_output: FileOutput,
+        max_size: u64,
+        max_files: usize,
+    ) -> io::Result<Self> {
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_size,
+            max_files,
+            file_output,
+            synced_file: Arc::new(Mutex::new(file.try_clone()?)),
+            output: output(file, file_output)?,
+            written,
+        })
+    }
+
+    /// Handle on the current file, following the rotations
+    pub fn synced_file(&self) -> SyncedFile {
+        self.synced_file.clone()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        // Dropping the previous output finishes its gzip stream, if any, and
+        // flushes its buffer
+        self.output.flush()?;
+        self.output = Box::new(io::sink());
+        // The lines of the previous file may be waited for by a sync request
+        let mut synced_file = self
+            .synced_file
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        synced_file.sync_data()?;
+
+        if self.max_files > 0 {
+            remove_if_exists(&rotated_path(&self.path, self.max_files))?;
+            for index in (1..self.max_files).rev() {
+                rename_if_exists(
+                    &rotated_path(&self.path, index),
+                    &rotated_path(&self.path, index + 1),
+                )?;
+            }
+            rename_if_exists(&self.path, &rotated_path(&self.path, 1))?;
+        }
+
+        let file = File::create(&self.path)?;
+        *synced_file = file.try_clone()?;
+        self.output = output(file, self.file_output)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRotatingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+
+        self.output.write_all(buf)?;
+        self.written += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// Appending to a compressed file adds a gzip member, which decoders concatenate
+///
+/// Buffered writes are flushed by the non-blocking worker once its pending
+/// lines are written, and when it stops, each flush ending the gzip member of
+/// compressed files.
+pub fn output(file: File, file_output: FileOutput) -> io::Result<Box<dyn Write + Send>> {
+    let is_empty = file.metadata()?.len() == 0;
+    let file: Box<dyn Write + Send> = match file_output.write_buffer_bytes {
+        Some(capacity) => Box::new(BufWriter::with_capacity(capacity, file)),
+        None => Box::new(file),
+    };
+    let mut output: Box<dyn Write + Send> = if file_output.compress {
+        Box::new(GzWriter::new(file))
+    } else {
+        file
+    };
+    // The mark belongs to the text, within the gzip stream of compressed files
+    if file_output.bom && is_empty {
+        output.write_all(UTF8_BOM)?;
+    }
+    let output: Box<dyn Write + Send> = match file_output.line_ending {
+        LineEnding::Lf => output,
+        LineEnding::Crlf => Box::new(CrlfWriter(output)),
+    };
+    if file_output.strip_ansi {
+        Ok(Box::new(AnsiStripWriter::new(output)))
+    } else {
+        Ok(output)
+    }
+}
+
+/// Gzip writer ending its member when flushed, the next write starting a new one
+///
+/// The worker flushes once its pending lines are written, so that the file is
+/// complete whenever the worker is idle, and before dropping its guard returns.
+/// Busy appenders, or batched ones, flush less often and compress better.
+struct GzWriter<W: Write> {
+    /// Encoder of the current member, if a line was written since the last flush
+    encoder: Option<GzEncoder<W>>,
+    /// Writer of the file between two members
+    inner: Option<W>,
+}
+
+impl<W: Write> GzWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            encoder: None,
+            inner: Some(inner),
+        }
+    }
+}
+
+/// Error of a gzip writer whose member could not be ended
+fn unfinished_member() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "unable to end the gzip member")
+}
+
+impl<W: Write> Write for GzWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(inner) = self.inner.take() {
+            self.encoder = Some(GzEncoder::new(inner, Compression::default()));
+        }
+        match &mut self.encoder {
+            Some(encoder) => encoder.write(buf),
+            None => Err(unfinished_member()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(encoder) = self.encoder.take() {
+            self.inner = Some(encoder.finish()?);
+        }
+        match &mut self.inner {
+            Some(inner) => inner.flush(),
+            None => Err(unfinished_member()),
+        }
+    }
+}
+
+/// Writer translating `\n` into `\r\n`
+struct CrlfWriter<W>(W);
+
+impl<W: Write> Write for CrlfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for (index, line) in buf.split(|&byte| byte == b'\n').enumerate() {
+            if index > 0 {
+                self.0.write_all(b"\r\n")?;
+            }
+            self.0.write_all(line)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Position of [`AnsiStripWriter`] relative to an escape sequence, which may
+/// span several writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AnsiState {
+    Text,
+    /// After `ESC`
+    Escape,
+    /// Within a Control Sequence Introducer sequence, after `ESC [`, until its final byte
+    Csi,
+}
+
+/// Writer removing ANSI escape sequences, such as `ESC [ 1 ; 31 m`
+struct AnsiStripWriter<W> {
+    inner: W,
+    state: AnsiState,
+    stripped: Vec<u8>,
+}
+
+impl<W> AnsiStripWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            state: AnsiState::Text,
+            stripped: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> Write for AnsiStripWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        const ESC: u8 = 0x1b;
+
+        self.stripped.clear();
+        for &byte in buf {
+            self.state = match (self.state, byte) {
+                (AnsiState::Text, ESC) => AnsiState::Escape,
+                (AnsiState::Text, _) => {
+                    self.stripped.push(byte);
+                    AnsiState::Text
+                }
+                (AnsiState::Escape, b'[') => AnsiState::Csi,
+                // Other escape sequences consist of a single byte after `ESC`
+                (AnsiState::Escape, _) => AnsiState::Text,
+                (AnsiState::Csi, 0x40..=0x7e) => AnsiState::Text,
+                (AnsiState::Csi, _) => AnsiState::Csi,
+            };
+        }
+        self.inner.write_all(&self.stripped)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut rotated = OsString::from(path);
+    rotated.push(format!(".{index}"));
+    rotated.into()
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Writer copying every line of a file appender to a second file
+///
+/// The errors of the mirror are counted but otherwise ignored, so that they
+/// neither block nor fail the writes of the main file, which in turn does not
+/// prevent writing the mirror when it fails. Only the first one is logged.
+pub struct MirrorWriter<W> {
+    main: W,
+    mirror: Box<dyn Write + Send>,
+    mirror_path: PathBuf,
+    mirror_errors: usize,
+}
+
+impl<W> MirrorWriter<W> {
+    pub fn new(main: W, mirror: Box<dyn Write + Send>, mirror_path: PathBuf) -> Self {
+        Self {
+            main,
+            mirror,
+            mirror_path,
+            mirror_errors: 0,
+        }
+    }
+
+    fn ignore_mirror_error(&mut self, result: io::Result<()>) {
+        let Err(error) = result else {
+            return;
+        };
+        self.mirror_errors += 1;
+        if self.mirror_errors == 1 {
+            warn!(
+                path = %self.mirror_path.display(),
+                %error,
+                "Unable to write the mirror of a log file, ignoring its errors"
+            );
+        }
+    }
+}
+
+impl<W: Write> Write for MirrorWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = self.main.write_all(buf);
+        let mirror_result = self.mirror.write_all(buf);
+        self.ignore_mirror_error(mirror_result);
+        result.map(|()| buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = self.main.flush();
+        let mirror_result = self.mirror.flush();
+        self.ignore_mirror_error(mirror_result);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ansi_sequences_are_stripped_even_across_writes() {
+        let mut writer = AnsiStripWriter::new(Vec::new());
+        writer
+            .write_all(b"\x1b[1;31mERROR\x1b[0m colored\n")
+            .unwrap();
+        writer.write_all(b"split \x1b[").unwrap();
+        writer.write_all(b"32mgreen\x1b[0m\n").unwrap();
+        assert_eq!(writer.inner, b"ERROR colored\nsplit green\n");
+    }
+
+    #[test]
+    fn the_synced_file_follows_the_rotations() {
+        let dir = crate::testing::temp_dir("synced_file");
+        let path = dir.join("app.log");
+        let file_output = FileOutput {
+            compress: false,
+            line_ending: LineEnding::Lf,
+            write_buffer_bytes: None,
+            strip_ansi: false,
+            bom: false,
+        };
+        let file = File::create(&path).unwrap();
+        let mut writer = SizeRotatingWriter::new(path.clone(), file, file_output, 10, 1).unwrap();
+        let synced_file = writer.synced_file();
+
+        writer.write_all(b"first line\n").unwrap();
+        writer.write_all(b"second\n").unwrap();
+        writer.flush().unwrap();
+        let rotated = fs::read_to_string(rotated_path(&path, 1)).unwrap();
+        assert_eq!(rotated, "first line\n");
+        let synced_file = synced_file.lock().unwrap();
+        synced_file.sync_data().unwrap();
+        assert_eq!(
+            synced_file.metadata().unwrap().len(),
+            "second\n".len() as u64
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use std::{
+    io,
+    path::PathBuf,
+    thread::{self, JoinHandle},
+};
+
+use signal_hook::{consts::SIGHUP, iterator::Signals};
+use tracing::{info, warn};
+use tracing_subscriber::{registry::Registry, subscribe::Subscribe};
+
+use super::{error::LogError, log::ReloadHandle};
+
+/// Handler reloading the logging configuration on `SIGHUP`, uninstalled when dropped
+pub struct SighupReload {
+    signals: signal_hook::iterator::Handle,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Reload the logging configuration from a file whenever the process receives
+/// `SIGHUP`, as daemons usually do
+///
+/// An invalid configuration keeps the current one, and an unreadable file is
+/// logged, so that the next signal can still reload a fixed file.
+pub fn install_sighup_reload<S>(
+    path: impl Into<PathBuf>,
+    data_dir: impl Into<PathBuf>,
+    handle: ReloadHandle<S>,
+) -> io::Result<SighupReload>
+where
+    S: Subscribe<Registry> + Send + Sync + 'static,
+{
+    let (path, data_dir) = (path.into(), data_dir.into());
+    let mut signals = Signals::new([SIGHUP])?;
+    let signals_handle = signals.handle();
+
+    let thread = thread::spawn(move || {
+        for _ in signals.forever() {
+            match handle.reload_from_path(&path, &data_dir) {
+                Ok(_) => info!(path = %path.display(), "Reloaded the logging configuration"),
+                Err(LogError::LoggingStopped) => break,
+                Err(error) => warn!(%error, "Unable to reload the logging configuration"),
+            }
+        }
+    });
+
+    Ok(SighupReload {
+        signals: signals_handle,
+        thread: Some(thread),
+    })
+}
+
+impl SighupReload {
+    /// Stop reloading on `SIGHUP`
+    ///
+    /// The signal is still caught afterwards, without any effect, since the
+    /// default action cannot be restored.
+    pub fn uninstall(self) {
+        drop(self);
+    }
+}
+
+impl Drop for SighupReload {
+    fn drop(&mut self) {
+        self.signals.close();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use tracing::{
+    info,
+    span::{Attributes, Id},
+    warn, Collect,
+};
+use tracing_subscriber::subscribe::{Context, Subscribe};
+
+/// Timing of an open span
+struct OpenSpan {
+    name: &'static str,
+    opened_at: Instant,
+    busy: Duration,
+    entered_at: Option<Instant>,
+    leak_reported: bool,
+}
+
+impl OpenSpan {
+    fn busy(&self, now: Instant) -> Duration {
+        let current = self.entered_at.map(|entered_at| now - entered_at);
+        self.busy + current.unwrap_or_default()
+    }
+}
+
+type OpenSpans = Arc<Mutex<HashMap<u64, OpenSpan>>>;
+
+/// Subscriber keeping track of long-running spans
+///
+/// Every `interval`, an interim timing event is emitted for each span open for
+/// longer than the interval, and a warning is emitted once for each span open
+/// for longer than `leak_threshold`, since it has possibly been leaked.
+pub struct LongSpanMonitor {
+    spans: OpenSpans,
+    /// Stop the scanner thread when dropped
+    _stop: Mutex<Sender<()>>,
+}
+
+impl LongSpanMonitor {
+    pub fn new(interval: Duration, leak_threshold: Duration) -> Self {
+        let spans = OpenSpans::default();
+        let (stop, stopped) = mpsc::channel();
+
+        let scanned_spans = spans.clone();
+        thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                scan(&scanned_spans, interval, leak_threshold);
+            }
+        });
+
+        Self {
+            spans,
+            _stop: Mutex::new(stop),
+        }
+    }
+}
+
+fn scan(spans: &OpenSpans, interval: Duration, leak_threshold: Duration) {
+    let now = Instant::now();
+    let mut interim = Vec::new();
+    let mut leaked = Vec::new();
+
+    {
+        let mut spans = spans.lock().unwrap_or_else(|e| e.into_inner());
+        for span in spans.values_mut() {
+            let open_for = now - span.opened_at;
+            if open_for < interval {
+                continue;
+            }
+
+            let busy = span.busy(now);
+            interim.push((span.name, open_for, busy));
+
+            if open_for >= leak_threshold && !span.leak_reported {
+                span.leak_reported = true;
+                leaked.push((span.name, open_for));
+            }
+        }
+    }
+
+    // Emit events once the lock is released
+    for (name, open_for, busy) in interim {
+        let idle = open_for.saturating_sub(busy);
+        info!(
+            span = name,
+            open_for = ?open_for,
+            busy = ?busy,
+            idle = ?idle,
+            "span still open"
+        );
+    }
+    for (name, open_for) in leaked {
+        warn!(span = name, open_for = ?open_for, "span open for too long, possibly leaked");
+    }
+}
+
+impl<C: Collect> Subscribe<C> for LongSpanMonitor {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, _ctx: Context<'_, C>) {
+        let span = OpenSpan {
+            name: attrs.metadata().name(),
+            opened_at: Instant::now(),
+            busy: Duration::ZERO,
+            entered_at: None,
+            leak_reported: false,
+        };
+        let mut spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        spans.insert(id.into_u64(), span);
+    }
+
+    fn on_enter(&self, id: &Id, _ctx: Context<'_, C>) {
+        let mut spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(span) = spans.get_mut(&id.into_u64()) {
+            span.entered_at.get_or_insert_with(Instant::now);
+        }
+    }
+
+    fn on_exit(&self, id: &Id, _ctx: Context<'_, C>) {
+        let mut spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(span) = spans.get_mut(&id.into_u64()) {
+            if let Some(entered_at) = span.entered_at.take() {
+                span.busy += entered_at.elapsed();
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, _ctx: Context<'_, C>) {
+        let mut spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        spans.remove(&id.into_u64());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn scan_reports_long_running_spans_and_leaks_once() {
+        let capture = testing::init();
+        let interval = Duration::from_millis(10);
+        let spans = OpenSpans::default();
+        let span = OpenSpan {
+            name: "request",
+            opened_at: Instant::now(),
+            busy: Duration::ZERO,
+            entered_at: None,
+            leak_reported: false,
+        };
+        spans.lock().unwrap().insert(1, span);
+
+        thread::sleep(interval * 3);
+        scan(&spans, interval, interval * 2);
+        scan(&spans, interval, interval * 2);
+
+        let lines = capture.lines();
+        let interim = lines.iter().filter(|line| line.contains("span still open"));
+        assert_eq!(interim.count(), 2);
+        let leaked = lines.iter().filter(|line| line.contains("possibly leaked"));
+        assert_eq!(leaked.count(), 1);
+    }
+}
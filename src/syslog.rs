@@ -0,0 +1,219 @@
+use std::{
+    env, fmt,
+    io::{self, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket},
+    path::Path,
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+
+use chrono::{SecondsFormat, Utc};
+use tracing::{Collect, Event};
+use tracing_subscriber::{
+    fmt::{format::Writer, FmtContext, FormatEvent, FormatFields},
+    registry::LookupSpan,
+};
+
+use super::{config::SyslogFacility, gelf::syslog_severity};
+
+/// Format events as RFC 5424 syslog messages, one per line
+#[derive(Debug)]
+pub struct SyslogFormat {
+    facility: SyslogFacility,
+    host: String,
+    app_name: String,
+    pid: u32,
+}
+
+impl SyslogFormat {
+    pub fn new(facility: SyslogFacility) -> Self {
+        let host = gethostname::gethostname().to_string_lossy().into_owned();
+        let app_name = env::current_exe()
+            .ok()
+            .and_then(|path| {
+                path.file_stem()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| "-".to_owned());
+
+        Self {
+            facility,
+            host,
+            app_name,
+            pid: process::id(),
+        }
+    }
+}
+
+impl<C, N> FormatEvent<C, N> for SyslogFormat
+where
+    C: Collect + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, C, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+        let priority = self.facility as u8 * 8 + syslog_severity(metadata.level());
+        let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true);
+
+        // No message ID nor structured data
+        write!(
+            writer,
+            "<{priority}>1 {timestamp} {} {} {} - - {}: ",
+            self.host,
+            self.app_name,
+            self.pid,
+            metadata.target(),
+        )?;
+        ctx.format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+enum Transport {
+    #[cfg(unix)]
+    Unix(UnixDatagram),
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+/// Writer sending each formatted event to a syslog server
+///
+/// Messages sent over TCP are framed with octet counting, as described in RFC 6587.
+pub struct SyslogWriter {
+    transport: Transport,
+    /// Number of messages which could not be sent
+    dropped: Arc<AtomicU64>,
+}
+
+impl SyslogWriter {
+    /// Connect to a local syslog daemon, usually listening on `/dev/log`
+    #[cfg(unix)]
+    pub fn unix(path: &Path) -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(Self::new(Transport::Unix(socket)))
+    }
+
+    #[cfg(not(unix))]
+    pub fn unix(_path: &Path) -> io::Result<Self> {
+        let msg = "unix syslog transport is not supported on this platform";
+        Err(io::Error::new(io::ErrorKind::Unsupported, msg))
+    }
+
+    pub fn udp(host: &str, port: u16) -> io::Result<Self> {
+        let address = resolve(host, port)?;
+        let bind_address = if address.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+
+        let socket = UdpSocket::bind(bind_address)?;
+        socket.connect(address)?;
+        Ok(Self::new(Transport::Udp(socket)))
+    }
+
+    pub fn tcp(host: &str, port: u16) -> io::Result<Self> {
+        let stream = TcpStream::connect(resolve(host, port)?)?;
+        Ok(Self::new(Transport::Tcp(stream)))
+    }
+
+    fn new(transport: Transport) -> Self {
+        Self {
+            transport,
+            dropped: Arc::default(),
+        }
+    }
+
+    /// Counter of the messages which could not be sent
+    pub fn dropped_counter(&self) -> Arc<AtomicU64> {
+        self.dropped.clone()
+    }
+}
+
+pub(crate) fn resolve(host: &str, port: u16) -> io::Result<SocketAddr> {
+    (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("cannot resolve {host}")))
+}
+
+impl Write for SyslogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let message = buf.strip_suffix(b"\n").unwrap_or(buf);
+        let result = match &mut self.transport {
+            #[cfg(unix)]
+            Transport::Unix(socket) => socket.send(message).map(drop),
+            Transport::Udp(socket) => socket.send(message).map(drop),
+            Transport::Tcp(stream) => {
+                write!(stream, "{} ", message.len()).and_then(|()| stream.write_all(message))
+            }
+        };
+        if result.is_err() {
+            // Never fail the non-blocking worker because of the syslog server
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.transport {
+            Transport::Tcp(stream) => stream.flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::time::Duration;
+
+    use tracing::dispatch;
+
+    use super::*;
+    use crate::{config::Log, log::build_dispatch, testing::temp_dir};
+
+    #[test]
+    fn unix_transport_sends_one_framed_message_per_event() {
+        let data_dir = temp_dir("syslog");
+        let socket_path = data_dir.join("syslog.sock");
+        let server = UnixDatagram::bind(&socket_path).unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let contents = format!(
+            r#"
+            [log.appenders.syslog]
+            kind = "syslog"
+            transport = "unix"
+            path = '{}'
+            facility = "daemon"
+            "#,
+            socket_path.display()
+        );
+        let log = Log::parse(&contents, &data_dir).unwrap();
+
+        let (dispatch, worker_guards) = build_dispatch(log).unwrap();
+        dispatch::with_default(&dispatch, || tracing::warn!(target: "app", "disk full"));
+        drop(worker_guards);
+
+        let mut buf = [0; 1024];
+        let len = server.recv(&mut buf).unwrap();
+        let message = std::str::from_utf8(&buf[..len]).unwrap();
+        // Daemon facility (3) and warning severity (4)
+        assert!(message.starts_with("<28>1 "), "{message}");
+        assert!(message.contains(&format!(" {} - - app: ", process::id())));
+        assert!(message.ends_with("disk full"), "{message}");
+    }
+}
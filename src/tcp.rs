@@ -0,0 +1,202 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use super::syslog::resolve;
+
+/// Maximum duration of a connection attempt, which blocks the non-blocking worker
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+/// Number of lines kept while disconnected, the oldest ones being dropped beyond it
+const MAX_PENDING_LINES: usize = 1024;
+
+/// Writer sending each formatted line over a TCP connection
+///
+/// The connection is opened on the first write. When it is lost, lines are kept until the
+/// server is reachable again, and reconnection attempts are spaced with an exponential backoff.
+pub struct TcpWriter {
+    host: String,
+    port: u16,
+    stream: Option<TcpStream>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    backoff: Duration,
+    next_attempt: Instant,
+    pending: VecDeque<Vec<u8>>,
+    /// Number of pending lines, readable from other threads
+    queued: Arc<AtomicUsize>,
+    /// Number of lines which could not be sent
+    dropped: Arc<AtomicU64>,
+}
+
+impl TcpWriter {
+    pub fn new(host: String, port: u16, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        Self {
+            host,
+            port,
+            stream: None,
+            initial_backoff,
+            max_backoff,
+            backoff: initial_backoff,
+            next_attempt: Instant::now(),
+            pending: VecDeque::new(),
+            queued: Arc::default(),
+            dropped: Arc::default(),
+        }
+    }
+
+    /// Counter of the lines waiting to be sent, such as while disconnected
+    pub fn queued_counter(&self) -> Arc<AtomicUsize> {
+        self.queued.clone()
+    }
+
+    /// Counter of the lines which could not be sent
+    pub fn dropped_counter(&self) -> Arc<AtomicU64> {
+        self.dropped.clone()
+    }
+
+    /// Try to connect if the backoff delay is elapsed, returning whether the writer is connected
+    fn connect(&mut self) -> bool {
+        if self.stream.is_some() {
+            return true;
+        }
+        let now = Instant::now();
+        if now < self.next_attempt {
+            return false;
+        }
+        let stream = resolve(&self.host, self.port)
+            .and_then(|address| TcpStream::connect_timeout(&address, CONNECT_TIMEOUT));
+        match stream {
+            Ok(stream) => {
+                self.stream = Some(stream);
+                self.backoff = self.initial_backoff;
+                true
+            }
+            Err(_) => {
+                self.schedule_reconnect(now);
+                false
+            }
+        }
+    }
+
+    fn schedule_reconnect(&mut self, now: Instant) {
+        self.next_attempt = now + self.backoff;
+        self.backoff = (self.backoff * 2).min(self.max_backoff);
+    }
+
+    /// Send the pending lines in order, keeping the remaining ones if the connection is lost
+    fn send_pending(&mut self) {
+        self.try_send_pending();
+        self.queued.store(self.pending.len(), Ordering::Relaxed);
+    }
+
+    fn try_send_pending(&mut self) {
+        if !self.connect() {
+            return;
+        }
+        let Some(stream) = &mut self.stream else {
+            return;
+        };
+        while let Some(line) = self.pending.front() {
+            if stream.write_all(line).is_err() {
+                // The line is sent again once reconnected
+                self.stream = None;
+                self.schedule_reconnect(Instant::now());
+                return;
+            }
+            self.pending.pop_front();
+        }
+    }
+}
+
+impl Write for TcpWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.pending.len() == MAX_PENDING_LINES {
+            // Never block the non-blocking worker because of an unreachable server
+            self.pending.pop_front();
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        self.pending.push_back(buf.to_vec());
+        self.send_pending();
+        Ok(buf.len())
+    }
+
+    /// Send the pending lines first, if the server is reachable again
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_pending();
+        if let Some(stream) = &mut self.stream {
+            // A lost connection is detected by the next write
+            let _ = stream.flush();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    #[test]
+    fn lines_are_sent_in_order_over_one_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut writer = TcpWriter::new(
+            "127.0.0.1".to_owned(),
+            port,
+            Duration::from_millis(10),
+            Duration::from_millis(100),
+        );
+
+        writer.write_all(b"first line\n").unwrap();
+        writer.write_all(b"second line\n").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.queued_counter().load(Ordering::Relaxed), 0);
+
+        let (stream, _) = listener.accept().unwrap();
+        let lines: Vec<String> = BufReader::new(stream)
+            .lines()
+            .take(2)
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(lines, ["first line", "second line"]);
+    }
+
+    #[test]
+    fn oldest_lines_are_dropped_while_the_server_is_unreachable() {
+        // Bound then closed, so that nothing listens on the port
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut writer = TcpWriter::new(
+            "127.0.0.1".to_owned(),
+            port,
+            Duration::from_secs(60),
+            Duration::from_secs(60),
+        );
+
+        for index in 0..MAX_PENDING_LINES + 3 {
+            writer
+                .write_all(format!("line {index}\n").as_bytes())
+                .unwrap();
+        }
+        assert_eq!(
+            writer.queued_counter().load(Ordering::Relaxed),
+            MAX_PENDING_LINES
+        );
+        assert_eq!(writer.dropped_counter().load(Ordering::Relaxed), 3);
+        assert_eq!(writer.pending.front().unwrap(), b"line 3\n");
+    }
+}
@@ -0,0 +1,228 @@
+use std::{
+    collections::HashMap,
+    fmt, mem,
+    sync::{Arc, Mutex, PoisonError},
+};
+#[cfg(test)]
+use std::{env, fs, path::PathBuf, process};
+
+use tracing::{
+    dispatch::{self, DefaultGuard},
+    field::{Field, Visit},
+    Collect, Event, Level,
+};
+use tracing_subscriber::{
+    registry,
+    subscribe::{CollectExt, Context, Subscribe},
+};
+
+use super::{
+    config::{Log, LogConfig, LogFormat, TimestampConfig},
+    error::LogResult,
+    format::{AppenderFields, EventFormat, Timer},
+    log::appender_filter,
+    memory::MemoryWriter,
+};
+
+/// Handle on the events captured by [`init`], which stops capturing when dropped
+#[must_use]
+pub struct TestLogHandle {
+    writer: MemoryWriter,
+    _guard: DefaultGuard,
+}
+
+impl TestLogHandle {
+    /// Events captured so far, formatted as with the `system` format
+    pub fn lines(&self) -> Vec<String> {
+        self.writer.lines()
+    }
+
+    /// Whether a captured event contains the given text
+    pub fn contains(&self, text: &str) -> bool {
+        self.writer.lines().iter().any(|line| line.contains(text))
+    }
+}
+
+/// Capture every event emitted by the current thread until the handle is dropped
+///
+/// Events are formatted synchronously, and the capture is scoped to the current
+/// thread so that tests running in parallel do not interfere.
+pub fn init() -> TestLogHandle {
+    let writer = MemoryWriter::new();
+
+    let captured = writer.clone();
+    let subscriber = tracing_subscriber::fmt::subscriber()
+        .with_ansi(false)
+        .with_writer(move || captured.clone())
+        .fmt_fields(AppenderFields::default())
+        .event_format(EventFormat::new(
+            LogFormat::System,
+            Timer::new(TimestampConfig::default()),
+        ));
+
+    let guard = dispatch::set_default(&registry().with(subscriber).into());
+    TestLogHandle {
+        writer,
+        _guard: guard,
+    }
+}
+
+/// Event recorded by an appender, as captured by [`with_captured_logs`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedEvent {
+    /// Appender whose filter accepted the event
+    pub appender: String,
+    pub level: Level,
+    pub target: String,
+    /// Message of the event, empty if it has none
+    pub message: String,
+    /// Other fields, formatted as with `Debug` except for strings
+    pub fields: HashMap<String, String>,
+}
+
+/// Record the events accepted by the filter of an appender
+struct EventCapture {
+    appender: String,
+    events: Arc<Mutex<Vec<CapturedEvent>>>,
+}
+
+impl<C: Collect> Subscribe<C> for EventCapture {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, C>) {
+        let metadata = event.metadata();
+        let mut captured = CapturedEvent {
+            appender: self.appender.clone(),
+            level: *metadata.level(),
+            target: metadata.target().to_owned(),
+            message: String::new(),
+            fields: HashMap::new(),
+        };
+        event.record(&mut captured);
+        self.events
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(captured);
+    }
+}
+
+impl Visit for CapturedEvent {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_owned();
+        } else {
+            self.fields
+                .insert(field.name().to_owned(), value.to_owned());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{value:?}");
+        } else {
+            self.fields
+                .insert(field.name().to_owned(), format!("{value:?}"));
+        }
+    }
+}
+
+/// Run a closure, returning the events its thread emitted which the appenders
+/// of a configuration would record, in order
+///
+/// Each appender filters the events as configured, an event being captured
+/// once per appender recording it. Nothing is written to the appenders.
+pub fn with_captured_logs(config: &Log, f: impl FnOnce()) -> LogResult<Vec<CapturedEvent>> {
+    let events = Arc::<Mutex<Vec<CapturedEvent>>>::default();
+
+    let mut captures = Vec::new();
+    for (name, appender) in &config.configs.appenders {
+        let log_config = appender.log_config();
+        if !log_config.is_enabled() {
+            continue;
+        }
+        let capture = EventCapture {
+            appender: name.clone(),
+            events: events.clone(),
+        };
+        captures.push(capture.with_filter(appender_filter(log_config, &config.global)?));
+    }
+
+    let collector = registry()
+        .with(config.global.max_level_filter()?)
+        .with(captures);
+    dispatch::with_default(&collector.into(), f);
+
+    let mut events = events.lock().unwrap_or_else(PoisonError::into_inner);
+    Ok(mem::take(&mut *events))
+}
+
+/// Empty directory of a test, under the temporary directory of the system
+///
+/// The directory is named after the test and the process, so that tests
+/// running in parallel, or again, do not share files.
+#[cfg(test)]
+pub(crate) fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("tracing-reload-example-{}-{name}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("the temporary directory is created");
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, thread};
+
+    use tracing::{info, warn};
+
+    use super::*;
+
+    #[test]
+    fn init_captures_the_events_of_the_current_thread_only() {
+        let handle = init();
+
+        info!(attempt = 3, "logged in");
+        thread::spawn(|| warn!("from another thread"))
+            .join()
+            .unwrap();
+
+        let lines = handle.lines();
+        assert_eq!(lines.len(), 1);
+        assert!(handle.contains("logged in"));
+        assert!(handle.contains("attempt=3"));
+        assert!(!handle.contains("from another thread"));
+    }
+
+    #[test]
+    fn captured_events_follow_the_filters_of_the_appenders() {
+        let file_contents = r#"
+            [log.appenders.everything]
+            kind = "memory"
+            level = "debug"
+
+            [log.appenders.warnings]
+            kind = "memory"
+            level = "warn"
+        "#;
+        let config = Log::parse(file_contents, Path::new(".")).unwrap();
+
+        let events = with_captured_logs(&config, || {
+            tracing::debug!(user = "alice", attempt = 3, "logged in");
+            warn!(user = "bob", "locked out");
+        })
+        .unwrap();
+
+        let summary: Vec<_> = events
+            .iter()
+            .map(|event| (event.appender.as_str(), event.level, event.message.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("everything", Level::DEBUG, "logged in"),
+                ("everything", Level::WARN, "locked out"),
+                ("warnings", Level::WARN, "locked out"),
+            ]
+        );
+        assert_eq!(events[0].fields["user"], "alice");
+        assert_eq!(events[0].fields["attempt"], "3");
+        assert_eq!(events[0].target, module_path!());
+    }
+}
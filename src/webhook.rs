@@ -0,0 +1,269 @@
+use std::{
+    fmt::{self, Write as _},
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+use indexmap::IndexMap;
+use tracing::{
+    field::{Field, Visit},
+    Collect, Event,
+};
+use tracing_subscriber::{
+    fmt::{format::Writer, FmtContext, FormatEvent, FormatFields},
+    registry::LookupSpan,
+};
+
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Format events by rendering a message template
+///
+/// The template supports the `{level}`, `{target}`, `{message}` and `{fields}` placeholders.
+#[derive(Debug)]
+pub struct WebhookFormat {
+    template: String,
+    /// Fields added to every event, rendered before the event fields
+    constant_fields: IndexMap<String, String>,
+}
+
+impl WebhookFormat {
+    pub fn new(template: String) -> Self {
+        Self {
+            template,
+            constant_fields: IndexMap::new(),
+        }
+    }
+
+    pub fn with_constant_fields(self, constant_fields: IndexMap<String, String>) -> Self {
+        Self {
+            constant_fields,
+            ..self
+        }
+    }
+}
+
+impl<C, N> FormatEvent<C, N> for WebhookFormat
+where
+    C: Collect + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        _ctx: &FmtContext<'_, C, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut visitor = TemplateVisitor::default();
+        for (key, value) in &self.constant_fields {
+            visitor.push_field(key, value);
+        }
+        event.record(&mut visitor);
+
+        let metadata = event.metadata();
+        let rendered = self
+            .template
+            .replace("{level}", metadata.level().as_str())
+            .replace("{target}", metadata.target())
+            .replace("{message}", &visitor.message)
+            .replace("{fields}", &visitor.fields);
+
+        write!(writer, "{rendered}")
+    }
+}
+
+#[derive(Default)]
+struct TemplateVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for TemplateVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_owned();
+        } else {
+            self.record_debug(field, &value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" => self.message = format!("{value:?}"),
+            name if name.starts_with("log.") => {}
+            name => self.push_field(name, value),
+        }
+    }
+}
+
+impl TemplateVisitor {
+    fn push_field(&mut self, name: &str, value: &dyn fmt::Debug) {
+        if !self.fields.is_empty() {
+            self.fields.push(' ');
+        }
+        let _ = write!(self.fields, "{name}={value:?}");
+    }
+}
+
+/// Writer posting each formatted event to a webhook, such as a Slack incoming webhook
+///
+/// Events exceeding the rate limit are dropped and reported in the next posted message.
+pub struct WebhookWriter {
+    agent: ureq::Agent,
+    url: String,
+    max_per_minute: u32,
+    window_start: Instant,
+    sent_in_window: u32,
+    suppressed: u64,
+}
+
+impl WebhookWriter {
+    pub fn new(url: String, max_per_minute: u32) -> io::Result<Self> {
+        if url.is_empty() {
+            let msg = "webhook appender requires an URL";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+
+        let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+
+        Ok(Self {
+            agent,
+            url,
+            max_per_minute,
+            window_start: Instant::now(),
+            sent_in_window: 0,
+            suppressed: 0,
+        })
+    }
+
+    fn is_rate_limited(&mut self) -> bool {
+        let now = Instant::now();
+        if now - self.window_start >= RATE_LIMIT_WINDOW {
+            self.window_start = now;
+            self.sent_in_window = 0;
+        }
+
+        if self.sent_in_window >= self.max_per_minute {
+            self.suppressed += 1;
+            return true;
+        }
+
+        self.sent_in_window += 1;
+        false
+    }
+}
+
+impl Write for WebhookWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.is_rate_limited() {
+            return Ok(buf.len());
+        }
+
+        let mut text = String::from_utf8_lossy(buf).trim_end().to_owned();
+        if self.suppressed > 0 {
+            let _ = write!(text, "\n({} similar events suppressed)", self.suppressed);
+            self.suppressed = 0;
+        }
+
+        let body = serde_json::json!({ "text": text }).to_string();
+
+        // Delivery failures are ignored, since the webhook must never disrupt logging
+        let _ = self
+            .agent
+            .post(&self.url)
+            .set("Content-Type", "application/json")
+            .send_string(&body);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read},
+        net::{TcpListener, TcpStream},
+        path::Path,
+        sync::mpsc,
+        thread,
+    };
+
+    use tracing::dispatch;
+
+    use super::*;
+    use crate::{config::Log, log::build_dispatch};
+
+    /// Answer the HTTP requests of a connection, sending their bodies
+    fn handle(stream: TcpStream, bodies: mpsc::Sender<String>) -> io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut stream = stream;
+        loop {
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line)? == 0 {
+                    return Ok(());
+                }
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap_or(0);
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body)?;
+            let _ = bodies.send(String::from_utf8_lossy(&body).into_owned());
+            stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")?;
+        }
+    }
+
+    /// Serve a local webhook, returning its URL and the bodies it receives
+    fn serve() -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, bodies) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let sender = sender.clone();
+                thread::spawn(move || handle(stream, sender));
+            }
+        });
+        (url, bodies)
+    }
+
+    #[test]
+    fn webhook_posts_formatted_events_within_the_rate_limit() {
+        let (url, bodies) = serve();
+        let file_contents = format!(
+            r#"
+            [log.appenders.alerts]
+            kind = "webhook"
+            url = "{url}"
+            rate_limit = 2
+            "#
+        );
+        let log = Log::parse(&file_contents, Path::new(".")).unwrap();
+        let (dispatch, worker_guards) = build_dispatch(log).unwrap();
+
+        dispatch::with_default(&dispatch, || {
+            for _ in 0..5 {
+                tracing::error!(target: "app", code = 7, "disk full");
+            }
+        });
+        // Events are posted synchronously by the worker, which is joined
+        drop(worker_guards);
+
+        let bodies: Vec<_> = bodies.try_iter().collect();
+        assert_eq!(bodies.len(), 2);
+        let expected = serde_json::json!({ "text": "[ERROR] app: disk full code=7" });
+        assert_eq!(bodies[0], expected.to_string());
+    }
+}
@@ -0,0 +1,254 @@
+use std::{
+    cell::Cell,
+    io::{self, Write},
+    mem,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use tracing::error;
+
+use super::config::AppenderErrorPolicy;
+
+/// Callback receiving the name of an appender and the error of one of its writes
+pub type WriteErrorCallback = Arc<dyn Fn(&str, &io::Error) + Send + Sync>;
+
+/// Treatment of the write errors, shared by the writers of every appender so
+/// that reused writers follow the latest configuration
+struct WriteErrorHandling {
+    policy: AppenderErrorPolicy,
+    callback: Option<WriteErrorCallback>,
+}
+
+static WRITE_ERROR_HANDLING: RwLock<WriteErrorHandling> = RwLock::new(WriteErrorHandling {
+    policy: AppenderErrorPolicy::Log,
+    callback: None,
+});
+
+thread_local! {
+    /// Whether the current thread is reporting a write error, so that the
+    /// error event does not report errors in turn
+    static REPORTING: Cell<bool> = const { Cell::new(false) };
+}
+
+pub(crate) fn set_policy(policy: AppenderErrorPolicy) {
+    WRITE_ERROR_HANDLING
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .policy = policy;
+}
+
+fn policy() -> AppenderErrorPolicy {
+    WRITE_ERROR_HANDLING
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .policy
+}
+
+pub(crate) fn set_callback(callback: Option<WriteErrorCallback>) {
+    WRITE_ERROR_HANDLING
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .callback = callback;
+}
+
+/// Writer reporting the errors of the writer of an appender according to the
+/// `on_appender_error` policy, rather than letting the worker drop them silently
+///
+/// With the `log` policy, an error is logged when the writer starts failing
+/// only, so that the error event, which may be written by the failing writer
+/// itself, does not produce an error event in turn.
+pub struct WriteErrorReporter<W> {
+    inner: W,
+    appender: String,
+    failing: bool,
+}
+
+impl<W> WriteErrorReporter<W> {
+    pub fn new(inner: W, appender: &str) -> Self {
+        Self {
+            inner,
+            appender: appender.to_owned(),
+            failing: false,
+        }
+    }
+
+    fn report(&mut self, error: &io::Error) {
+        let (policy, callback) = {
+            let handling = WRITE_ERROR_HANDLING
+                .read()
+                .unwrap_or_else(PoisonError::into_inner);
+            (handling.policy, handling.callback.clone())
+        };
+        self.report_as(error, policy, callback);
+    }
+
+    /// Report an error according to the given policy and callback rather than
+    /// the configured ones
+    fn report_as(
+        &mut self,
+        error: &io::Error,
+        policy: AppenderErrorPolicy,
+        callback: Option<WriteErrorCallback>,
+    ) {
+        if REPORTING.with(|reporting| reporting.replace(true)) {
+            return;
+        }
+
+        if let Some(callback) = callback {
+            callback(&self.appender, error);
+        }
+        let was_failing = mem::replace(&mut self.failing, true);
+        if policy == AppenderErrorPolicy::Log && !was_failing {
+            error!(appender = %self.appender, %error, "Unable to write logs");
+        }
+        REPORTING.with(|reporting| reporting.set(false));
+
+        if policy == AppenderErrorPolicy::Panic {
+            panic!(
+                "unable to write the logs of appender `{}`: {error}",
+                self.appender
+            );
+        }
+    }
+}
+
+impl<W: Write> Write for WriteErrorReporter<W> {
+    /// Drop the line on failure, as the worker would
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.inner.write(buf) {
+            Ok(written) => {
+                self.failing = false;
+                Ok(written)
+            }
+            Err(error) => {
+                self.report(&error);
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Err(error) = self.inner.flush() {
+            self.report(&error);
+        }
+        Ok(())
+    }
+}
+
+/// Writer catching the panics of the writer of an appender, so that the worker
+/// of the appender survives a faulty write and keeps writing the next lines
+///
+/// The line whose write panicked is dropped. Only the first panic is logged,
+/// since the error event may be written by the panicking writer itself. With
+/// the `panic` policy, panics are propagated so that the appender stops.
+pub struct PanicCatcher<W> {
+    inner: W,
+    panics: usize,
+}
+
+impl<W> PanicCatcher<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, panics: 0 }
+    }
+
+    fn catch<T>(&mut self, f: impl FnOnce(&mut W) -> T) -> Option<T> {
+        let payload = match panic::catch_unwind(AssertUnwindSafe(|| f(&mut self.inner))) {
+            Ok(result) => return Some(result),
+            Err(payload) => payload,
+        };
+        if policy() == AppenderErrorPolicy::Panic {
+            panic::resume_unwind(payload);
+        }
+
+        self.panics += 1;
+        if self.panics == 1 && !REPORTING.with(|reporting| reporting.replace(true)) {
+            error!("The writer of an appender panicked, dropping the line being written");
+            REPORTING.with(|reporting| reporting.set(false));
+        }
+        None
+    }
+}
+
+impl<W: Write> Write for PanicCatcher<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.catch(|inner| inner.write(buf))
+            .unwrap_or(Ok(buf.len()))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.catch(W::flush).unwrap_or(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::testing;
+
+    /// Writer whose writes always fail, such as on a full disk
+    struct FailingWriter;
+
+    impl Write for FailingWriter {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Write two lines with a failing writer, reporting its errors as with `policy`
+    fn fail_twice(policy: AppenderErrorPolicy, callback: Option<WriteErrorCallback>) {
+        let mut reporter = WriteErrorReporter::new(FailingWriter, "failing");
+        for _ in 0..2 {
+            let error = reporter.inner.write(b"line\n").unwrap_err();
+            reporter.report_as(&error, policy, callback.clone());
+        }
+    }
+
+    #[test]
+    fn write_errors_follow_the_policy() {
+        let handle = testing::init();
+        fail_twice(AppenderErrorPolicy::Log, None);
+        assert_eq!(handle.lines().len(), 1);
+        assert!(handle.contains("Unable to write logs"));
+        assert!(handle.contains("failing"));
+        drop(handle);
+
+        let handle = testing::init();
+        fail_twice(AppenderErrorPolicy::Ignore, None);
+        assert!(handle.lines().is_empty());
+
+        let panicked = panic::catch_unwind(|| fail_twice(AppenderErrorPolicy::Panic, None));
+        assert!(panicked.is_err());
+        assert!(!REPORTING.with(Cell::get));
+    }
+
+    #[test]
+    fn write_errors_are_passed_to_the_callback() {
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let callback: WriteErrorCallback = Arc::new({
+            let errors = errors.clone();
+            move |appender, error| {
+                errors
+                    .lock()
+                    .unwrap()
+                    .push((appender.to_owned(), error.kind()));
+            }
+        });
+
+        fail_twice(AppenderErrorPolicy::Ignore, Some(callback));
+        let errors = errors.lock().unwrap();
+        assert_eq!(
+            *errors,
+            [
+                ("failing".to_owned(), io::ErrorKind::BrokenPipe),
+                ("failing".to_owned(), io::ErrorKind::BrokenPipe),
+            ]
+        );
+    }
+}